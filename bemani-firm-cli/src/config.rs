@@ -0,0 +1,1007 @@
+//! TOML representation of the settings.
+//!
+//! Everything is optional so a file only needs to mention what it changes.
+//! Per button tables are keyed by button name, with `all` applied first and
+//! then overridden by the individual buttons:
+//!
+//! ```toml
+//! # "joystick", "keyboard" or "switch"
+//! input_mode = "joystick"
+//! # USB serial number from the next boot, "" for the flash chip's unique ID
+//! serial_number = "DP-1P"
+//!
+//! [turntable]
+//! steps = 144
+//! # Rated pulses per rotation of the encoder, and the fastest spin in
+//! # rotations per second it is sampled quickly enough to follow
+//! ppr = 360
+//! max_rps = 50
+//! # "8-bit", "16-bit", "raw" or "digital" (TT+/TT- buttons), the konami
+//! # USB profile always uses 8-bit
+//! axis = "digital"
+//! hold_ms = 100
+//! threshold = 10
+//! # Counts ignored when the platter turns back, against encoder jitter
+//! deadzone = 1
+//! # Low-pass filter time constant, 0 for none
+//! smoothing_ms = 0
+//! # "linear", "accelerated" or "custom" (gains below)
+//! curve = "custom"
+//! # Gain in percent at 0, 50, 100... RPM
+//! curve_gains = [100, 100, 110, 120, 140, 160, 180, 200]
+//!
+//! # Used from the next boot, each encoder gets its own joystick axis. The
+//! # first is the turntable above, the konami USB profile only reports that
+//! # one
+//! [encoders]
+//! count = 2
+//! # Pulses per rotation and report steps of the encoders after the first
+//! extra = [{ ppr = 24, steps = 96 }]
+//!
+//! [switch]
+//! # "stick" or "dpad"
+//! turntable = "stick"
+//!
+//! # Used from the next boot, except in Switch mode
+//! [usb]
+//! # "konami", "open-source" or "custom" (the IDs and strings below)
+//! profile = "custom"
+//! vid = 0x1209
+//! pid = 0x0001
+//! manufacturer = "bemani-firm-rs"
+//! product = "IIDX controller"
+//!
+//! [lighting]
+//! mode = "reactive"
+//! fade_ms = 200
+//! colours = { all = "#000000", key1 = "#ff0000" }
+//! # Button each LED follows, in wiring order
+//! leds = ["key1", "key2", "none"]
+//! ring_effect = "velocity-trail"
+//! ring_colour = "#ff0000"
+//!
+//! [debounce.all]
+//! algorithm = "eager"
+//! press_us = 4000
+//! release_us = 4000
+//!
+//! [debounce.e1]
+//! algorithm = "deferred"
+//!
+//! # Keyboard mode bindings: key names, "none" or a "0x.." usage
+//! [keyboard]
+//! key1 = "z"
+//! tt-up = "left-shift"
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use anyhow::Result;
+use anyhow::bail;
+use bemani_firm_core::button::NUM_BUTTONS;
+use bemani_firm_core::debounce::DebounceAlgorithm;
+use bemani_firm_core::debounce::DebounceConfig;
+use bemani_firm_core::encoder::EncoderScaling;
+use bemani_firm_core::encoder::MAX_ENCODERS;
+use bemani_firm_core::filter::CURVE_POINTS;
+use bemani_firm_core::filter::TurntableCurve;
+use bemani_firm_core::identity::MAX_USB_STRING_LEN;
+use bemani_firm_core::identity::UsbProfile;
+use bemani_firm_core::identity::UsbString;
+use bemani_firm_core::keyboard::NO_KEY;
+use bemani_firm_core::keyboard::NUM_KEYBOARD_INPUTS;
+use bemani_firm_core::keyboard::is_valid_key;
+use bemani_firm_core::settings::InputMode;
+use bemani_firm_core::settings::LedMode;
+use bemani_firm_core::settings::MAX_BUTTON_LEDS;
+use bemani_firm_core::settings::MAX_SERIAL_LEN;
+use bemani_firm_core::settings::NO_BUTTON;
+use bemani_firm_core::settings::RingEffect;
+use bemani_firm_core::settings::SerialNumber;
+use bemani_firm_core::settings::Settings;
+use bemani_firm_core::settings::TurntableAxis;
+use bemani_firm_core::switch::SwitchTurntable;
+use embassy_time::Duration;
+use serde::Deserialize;
+use serde::Serialize;
+use smart_leds::RGB8;
+
+/// Button names, in settings order.
+pub const BUTTON_NAMES: [&str; NUM_BUTTONS] = [
+    "key1", "key2", "key3", "key4", "key5", "key6", "key7", "e1", "e2", "e3", "e4",
+];
+
+/// Keyboard mode input names, in settings order.
+pub const KEYBOARD_INPUT_NAMES: [&str; NUM_KEYBOARD_INPUTS] = [
+    "key1", "key2", "key3", "key4", "key5", "key6", "key7", "e1", "e2", "e3", "e4", "tt-up",
+    "tt-down",
+];
+
+/// Names for keyboard usages other than letters, digits and F keys.
+const KEY_NAMES: &[(&str, u8)] = &[
+    ("enter", 0x28),
+    ("escape", 0x29),
+    ("backspace", 0x2A),
+    ("tab", 0x2B),
+    ("space", 0x2C),
+    ("minus", 0x2D),
+    ("equals", 0x2E),
+    ("left-bracket", 0x2F),
+    ("right-bracket", 0x30),
+    ("backslash", 0x31),
+    ("semicolon", 0x33),
+    ("quote", 0x34),
+    ("grave", 0x35),
+    ("comma", 0x36),
+    ("period", 0x37),
+    ("slash", 0x38),
+    ("caps-lock", 0x39),
+    ("insert", 0x49),
+    ("home", 0x4A),
+    ("page-up", 0x4B),
+    ("delete", 0x4C),
+    ("end", 0x4D),
+    ("page-down", 0x4E),
+    ("right", 0x4F),
+    ("left", 0x50),
+    ("down", 0x51),
+    ("up", 0x52),
+    ("left-ctrl", 0xE0),
+    ("left-shift", 0xE1),
+    ("left-alt", 0xE2),
+    ("left-gui", 0xE3),
+    ("right-ctrl", 0xE4),
+    ("right-shift", 0xE5),
+    ("right-alt", 0xE6),
+    ("right-gui", 0xE7),
+];
+
+/// Table key that applies to every button.
+const ALL: &str = "all";
+
+/// LED mapping entry for an LED that follows no button.
+const NONE: &str = "none";
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub input_mode: Option<InputModeName>,
+    /// USB serial number override, empty for the flash chip's unique ID.
+    pub serial_number: Option<String>,
+    pub turntable: Option<Turntable>,
+    pub encoders: Option<Encoders>,
+    pub switch: Option<Switch>,
+    pub usb: Option<Usb>,
+    pub lighting: Option<Lighting>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub debounce: BTreeMap<String, Debounce>,
+    /// Key each input presses in keyboard mode, by input name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub keyboard: BTreeMap<String, Key>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Turntable {
+    /// Report steps per platter rotation.
+    pub steps: Option<u16>,
+    /// Encoder pulses per rotation.
+    pub ppr: Option<u16>,
+    /// Fastest spin the encoder has to follow, in rotations per second.
+    pub max_rps: Option<u16>,
+    /// Axis format, used from the next boot.
+    pub axis: Option<AxisName>,
+    /// Digital mode button hold time after the platter stops.
+    pub hold_ms: Option<u16>,
+    /// Encoder counts of movement that press a digital mode button.
+    pub threshold: Option<u16>,
+    /// Encoder counts ignored when the platter changes direction.
+    pub deadzone: Option<u16>,
+    /// Low-pass filter time constant, 0 to turn it off.
+    pub smoothing_ms: Option<u16>,
+    pub curve: Option<CurveName>,
+    /// Custom curve gain in percent at each speed step.
+    pub curve_gains: Option<[u8; CURVE_POINTS]>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Encoders {
+    /// Encoders wired up, the turntable included.
+    pub count: Option<u8>,
+    /// Scaling of the encoders after the turntable, in order. Encoders past
+    /// the end are left alone.
+    pub extra: Option<Vec<ExtraEncoder>>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExtraEncoder {
+    /// Encoder pulses per rotation.
+    pub ppr: u16,
+    /// Report steps per rotation.
+    pub steps: u16,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Switch {
+    pub turntable: Option<SwitchTurntableName>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Usb {
+    pub profile: Option<UsbProfileName>,
+    /// Custom profile vendor ID.
+    pub vid: Option<u16>,
+    /// Custom profile product ID.
+    pub pid: Option<u16>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Lighting {
+    pub mode: Option<LedModeName>,
+    /// Reactive mode fade out time.
+    pub fade_ms: Option<u16>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub colours: BTreeMap<String, Colour>,
+    /// Button name each LED follows, LEDs past the end are left alone.
+    pub leds: Option<Vec<String>>,
+    pub ring_effect: Option<RingEffectName>,
+    pub ring_colour: Option<Colour>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Debounce {
+    pub algorithm: Option<AlgorithmName>,
+    pub press_us: Option<u16>,
+    pub release_us: Option<u16>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputModeName {
+    Joystick,
+    Keyboard,
+    Switch,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SwitchTurntableName {
+    Stick,
+    Dpad,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UsbProfileName {
+    Konami,
+    OpenSource,
+    Custom,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AxisName {
+    #[serde(rename = "8-bit")]
+    Wrapped8,
+    #[serde(rename = "16-bit")]
+    Wrapped16,
+    #[serde(rename = "raw")]
+    Raw,
+    #[serde(rename = "digital")]
+    Digital,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CurveName {
+    Linear,
+    Accelerated,
+    Custom,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LedModeName {
+    Static,
+    Reactive,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RingEffectName {
+    SpinningDot,
+    Rainbow,
+    Breathing,
+    VelocityTrail,
+    ScratchFlash,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlgorithmName {
+    Eager,
+    Deferred,
+    Asymmetric,
+}
+
+/// A `#rrggbb` colour.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Colour(pub RGB8);
+
+impl TryFrom<String> for Colour {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let hex = s.strip_prefix('#').unwrap_or(&s);
+        let value = (hex.len() == 6)
+            .then(|| u32::from_str_radix(hex, 16).ok())
+            .flatten()
+            .ok_or_else(|| format!("expected a #rrggbb colour, got {s:?}"))?;
+        let [_, r, g, b] = value.to_be_bytes();
+        Ok(Self(RGB8::new(r, g, b)))
+    }
+}
+
+impl From<Colour> for String {
+    fn from(colour: Colour) -> Self {
+        colour.to_string()
+    }
+}
+
+impl fmt::Display for Colour {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let RGB8 { r, g, b } = self.0;
+        write!(f, "#{r:02x}{g:02x}{b:02x}")
+    }
+}
+
+/// A keyboard usage, by name (`z`, `f5`, `left-shift`), `none` or `0x..`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Key(pub u8);
+
+impl TryFrom<String> for Key {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let usage = parse_key(&s).ok_or_else(|| format!("unknown key {s:?}"))?;
+        if !is_valid_key(usage) {
+            return Err(format!("key {s:?} cannot be sent"));
+        }
+        Ok(Self(usage))
+    }
+}
+
+impl From<Key> for String {
+    fn from(key: Key) -> Self {
+        key.to_string()
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            NO_KEY => write!(f, "{NONE}"),
+            usage @ 0x04..=0x1D => write!(f, "{}", (b'a' + usage - 0x04) as char),
+            usage @ 0x1E..=0x26 => write!(f, "{}", usage - 0x1E + 1),
+            0x27 => write!(f, "0"),
+            usage @ 0x3A..=0x45 => write!(f, "f{}", usage - 0x3A + 1),
+            usage @ 0x68..=0x73 => write!(f, "f{}", usage - 0x68 + 13),
+            usage => match KEY_NAMES.iter().find(|&&(_, u)| u == usage) {
+                Some((name, _)) => write!(f, "{name}"),
+                None => write!(f, "0x{usage:02x}"),
+            },
+        }
+    }
+}
+
+fn parse_key(name: &str) -> Option<u8> {
+    if name == NONE {
+        return Some(NO_KEY);
+    }
+    if let Some(hex) = name.strip_prefix("0x") {
+        return u8::from_str_radix(hex, 16).ok();
+    }
+    if let Some((_, usage)) = KEY_NAMES.iter().find(|&&(n, _)| n == name) {
+        return Some(*usage);
+    }
+    if let Some(f) = name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+        return match f {
+            1..=12 => Some(0x3A + f - 1),
+            13..=24 => Some(0x68 + f - 13),
+            _ => None,
+        };
+    }
+    match *name.as_bytes() {
+        [c @ b'a'..=b'z'] => Some(0x04 + c - b'a'),
+        [b'0'] => Some(0x27),
+        [c @ b'1'..=b'9'] => Some(0x1E + c - b'1'),
+        _ => None,
+    }
+}
+
+impl From<InputMode> for InputModeName {
+    fn from(mode: InputMode) -> Self {
+        match mode {
+            InputMode::Joystick => Self::Joystick,
+            InputMode::Keyboard => Self::Keyboard,
+            InputMode::Switch => Self::Switch,
+        }
+    }
+}
+
+impl From<InputModeName> for InputMode {
+    fn from(mode: InputModeName) -> Self {
+        match mode {
+            InputModeName::Joystick => Self::Joystick,
+            InputModeName::Keyboard => Self::Keyboard,
+            InputModeName::Switch => Self::Switch,
+        }
+    }
+}
+
+impl From<SwitchTurntable> for SwitchTurntableName {
+    fn from(tt: SwitchTurntable) -> Self {
+        match tt {
+            SwitchTurntable::Stick => Self::Stick,
+            SwitchTurntable::Dpad => Self::Dpad,
+        }
+    }
+}
+
+impl From<SwitchTurntableName> for SwitchTurntable {
+    fn from(tt: SwitchTurntableName) -> Self {
+        match tt {
+            SwitchTurntableName::Stick => Self::Stick,
+            SwitchTurntableName::Dpad => Self::Dpad,
+        }
+    }
+}
+
+impl From<UsbProfile> for UsbProfileName {
+    fn from(profile: UsbProfile) -> Self {
+        match profile {
+            UsbProfile::Konami => Self::Konami,
+            UsbProfile::OpenSource => Self::OpenSource,
+            UsbProfile::Custom => Self::Custom,
+        }
+    }
+}
+
+impl From<UsbProfileName> for UsbProfile {
+    fn from(profile: UsbProfileName) -> Self {
+        match profile {
+            UsbProfileName::Konami => Self::Konami,
+            UsbProfileName::OpenSource => Self::OpenSource,
+            UsbProfileName::Custom => Self::Custom,
+        }
+    }
+}
+
+impl From<TurntableAxis> for AxisName {
+    fn from(axis: TurntableAxis) -> Self {
+        match axis {
+            TurntableAxis::Wrapped8 => Self::Wrapped8,
+            TurntableAxis::Wrapped16 => Self::Wrapped16,
+            TurntableAxis::Raw => Self::Raw,
+            TurntableAxis::Digital => Self::Digital,
+        }
+    }
+}
+
+impl From<AxisName> for TurntableAxis {
+    fn from(axis: AxisName) -> Self {
+        match axis {
+            AxisName::Wrapped8 => Self::Wrapped8,
+            AxisName::Wrapped16 => Self::Wrapped16,
+            AxisName::Raw => Self::Raw,
+            AxisName::Digital => Self::Digital,
+        }
+    }
+}
+
+impl From<TurntableCurve> for CurveName {
+    fn from(curve: TurntableCurve) -> Self {
+        match curve {
+            TurntableCurve::Linear => Self::Linear,
+            TurntableCurve::Accelerated => Self::Accelerated,
+            TurntableCurve::Custom => Self::Custom,
+        }
+    }
+}
+
+impl From<CurveName> for TurntableCurve {
+    fn from(curve: CurveName) -> Self {
+        match curve {
+            CurveName::Linear => Self::Linear,
+            CurveName::Accelerated => Self::Accelerated,
+            CurveName::Custom => Self::Custom,
+        }
+    }
+}
+
+impl From<LedMode> for LedModeName {
+    fn from(mode: LedMode) -> Self {
+        match mode {
+            LedMode::Static => Self::Static,
+            LedMode::Reactive => Self::Reactive,
+        }
+    }
+}
+
+impl From<LedModeName> for LedMode {
+    fn from(mode: LedModeName) -> Self {
+        match mode {
+            LedModeName::Static => Self::Static,
+            LedModeName::Reactive => Self::Reactive,
+        }
+    }
+}
+
+impl From<RingEffect> for RingEffectName {
+    fn from(effect: RingEffect) -> Self {
+        match effect {
+            RingEffect::SpinningDot => Self::SpinningDot,
+            RingEffect::Rainbow => Self::Rainbow,
+            RingEffect::Breathing => Self::Breathing,
+            RingEffect::VelocityTrail => Self::VelocityTrail,
+            RingEffect::ScratchFlash => Self::ScratchFlash,
+        }
+    }
+}
+
+impl From<RingEffectName> for RingEffect {
+    fn from(effect: RingEffectName) -> Self {
+        match effect {
+            RingEffectName::SpinningDot => Self::SpinningDot,
+            RingEffectName::Rainbow => Self::Rainbow,
+            RingEffectName::Breathing => Self::Breathing,
+            RingEffectName::VelocityTrail => Self::VelocityTrail,
+            RingEffectName::ScratchFlash => Self::ScratchFlash,
+        }
+    }
+}
+
+impl From<DebounceAlgorithm> for AlgorithmName {
+    fn from(algorithm: DebounceAlgorithm) -> Self {
+        match algorithm {
+            DebounceAlgorithm::Eager => Self::Eager,
+            DebounceAlgorithm::Deferred => Self::Deferred,
+            DebounceAlgorithm::Asymmetric => Self::Asymmetric,
+        }
+    }
+}
+
+impl From<AlgorithmName> for DebounceAlgorithm {
+    fn from(algorithm: AlgorithmName) -> Self {
+        match algorithm {
+            AlgorithmName::Eager => Self::Eager,
+            AlgorithmName::Deferred => Self::Deferred,
+            AlgorithmName::Asymmetric => Self::Asymmetric,
+        }
+    }
+}
+
+impl Config {
+    /// Describes every setting, for dumping a device's configuration.
+    pub fn from_settings(settings: &Settings) -> Self {
+        let named = |i: usize| BUTTON_NAMES[i].to_string();
+
+        Self {
+            input_mode: Some(settings.input_mode.into()),
+            serial_number: Some(settings.serial_number.as_str().into()),
+            turntable: Some(Turntable {
+                steps: Some(settings.tt_steps),
+                ppr: Some(settings.encoder_ppr),
+                max_rps: Some(settings.tt_max_rps),
+                axis: Some(settings.tt_axis.into()),
+                hold_ms: Some(duration_to_ms(settings.tt_hold)),
+                threshold: Some(settings.tt_threshold),
+                deadzone: Some(settings.tt_deadzone),
+                smoothing_ms: Some(duration_to_ms(settings.tt_smoothing)),
+                curve: Some(settings.tt_curve.into()),
+                curve_gains: Some(settings.tt_curve_gains),
+            }),
+            encoders: Some(Encoders {
+                count: Some(settings.encoders),
+                extra: Some(
+                    settings
+                        .extra_encoders
+                        .iter()
+                        .map(|scaling| ExtraEncoder {
+                            ppr: scaling.ppr,
+                            steps: scaling.steps,
+                        })
+                        .collect(),
+                ),
+            }),
+            switch: Some(Switch {
+                turntable: Some(settings.switch_tt.into()),
+            }),
+            usb: Some(Usb {
+                profile: Some(settings.usb_profile.into()),
+                vid: Some(settings.custom_identity.vid),
+                pid: Some(settings.custom_identity.pid),
+                manufacturer: Some(settings.custom_identity.manufacturer.as_str().into()),
+                product: Some(settings.custom_identity.product.as_str().into()),
+            }),
+            lighting: Some(Lighting {
+                mode: Some(settings.led_mode.into()),
+                fade_ms: Some(duration_to_ms(settings.reactive_fade)),
+                colours: (0..NUM_BUTTONS)
+                    .map(|i| (named(i), Colour(settings.key_colours[i])))
+                    .collect(),
+                leds: Some(
+                    settings
+                        .button_leds
+                        .iter()
+                        .map(|&button| match BUTTON_NAMES.get(button as usize) {
+                            Some(name) => name.to_string(),
+                            None => NONE.into(),
+                        })
+                        .collect(),
+                ),
+                ring_effect: Some(settings.ring_effect.into()),
+                ring_colour: Some(Colour(settings.ring_colour)),
+            }),
+            debounce: (0..NUM_BUTTONS)
+                .map(|i| {
+                    let config = &settings.debounce[i];
+                    let debounce = Debounce {
+                        algorithm: Some(config.algorithm.into()),
+                        press_us: Some(duration_to_us(config.press)),
+                        release_us: Some(duration_to_us(config.release)),
+                    };
+                    (named(i), debounce)
+                })
+                .collect(),
+            keyboard: KEYBOARD_INPUT_NAMES
+                .iter()
+                .zip(settings.keyboard_keys)
+                .map(|(name, key)| (name.to_string(), Key(key)))
+                .collect(),
+        }
+    }
+
+    /// Overwrites the settings this config mentions.
+    pub fn apply(&self, settings: &mut Settings) -> Result<()> {
+        if let Some(mode) = self.input_mode {
+            settings.input_mode = mode.into();
+        }
+
+        if let Some(serial) = &self.serial_number {
+            let Some(serial) = SerialNumber::new(serial) else {
+                bail!(
+                    "serial number must be at most {MAX_SERIAL_LEN} printable characters without spaces"
+                );
+            };
+            settings.serial_number = serial;
+        }
+
+        if let Some(turntable) = &self.turntable {
+            if let Some(steps) = turntable.steps {
+                if steps == 0 {
+                    bail!("turntable steps must be at least 1");
+                }
+                settings.tt_steps = steps;
+            }
+            if let Some(ppr) = turntable.ppr {
+                if ppr == 0 {
+                    bail!("encoder ppr must be at least 1");
+                }
+                settings.encoder_ppr = ppr;
+            }
+            if let Some(max_rps) = turntable.max_rps {
+                if max_rps == 0 {
+                    bail!("turntable max_rps must be at least 1");
+                }
+                settings.tt_max_rps = max_rps;
+            }
+            if let Some(axis) = turntable.axis {
+                settings.tt_axis = axis.into();
+            }
+            if let Some(hold) = turntable.hold_ms {
+                settings.tt_hold = Duration::from_millis(hold.into());
+            }
+            if let Some(threshold) = turntable.threshold {
+                if threshold == 0 {
+                    bail!("turntable threshold must be at least 1");
+                }
+                settings.tt_threshold = threshold;
+            }
+            if let Some(deadzone) = turntable.deadzone {
+                settings.tt_deadzone = deadzone;
+            }
+            if let Some(smoothing) = turntable.smoothing_ms {
+                settings.tt_smoothing = Duration::from_millis(smoothing.into());
+            }
+            if let Some(curve) = turntable.curve {
+                settings.tt_curve = curve.into();
+            }
+            if let Some(gains) = turntable.curve_gains {
+                settings.tt_curve_gains = gains;
+            }
+        }
+
+        if let Some(encoders) = &self.encoders {
+            if let Some(count) = encoders.count {
+                if !(1..=MAX_ENCODERS as u8).contains(&count) {
+                    bail!("encoder count must be between 1 and {MAX_ENCODERS}");
+                }
+                settings.encoders = count;
+            }
+            if let Some(extra) = &encoders.extra {
+                if extra.len() > MAX_ENCODERS - 1 {
+                    bail!("at most {} extra encoders can be set up", MAX_ENCODERS - 1);
+                }
+                for (scaling, encoder) in settings.extra_encoders.iter_mut().zip(extra) {
+                    if encoder.ppr == 0 || encoder.steps == 0 {
+                        bail!("encoder ppr and steps must be at least 1");
+                    }
+                    *scaling = EncoderScaling {
+                        ppr: encoder.ppr,
+                        steps: encoder.steps,
+                    };
+                }
+            }
+        }
+
+        if let Some(turntable) = self.switch.as_ref().and_then(|s| s.turntable) {
+            settings.switch_tt = turntable.into();
+        }
+
+        if let Some(usb) = &self.usb {
+            let custom = &mut settings.custom_identity;
+            if let Some(profile) = usb.profile {
+                settings.usb_profile = profile.into();
+            }
+            if let Some(vid) = usb.vid {
+                custom.vid = vid;
+            }
+            if let Some(pid) = usb.pid {
+                custom.pid = pid;
+            }
+            if let Some(manufacturer) = &usb.manufacturer {
+                custom.manufacturer = usb_string(manufacturer)?;
+            }
+            if let Some(product) = &usb.product {
+                custom.product = usb_string(product)?;
+            }
+        }
+
+        if let Some(lighting) = &self.lighting {
+            if let Some(mode) = lighting.mode {
+                settings.led_mode = mode.into();
+            }
+            if let Some(effect) = lighting.ring_effect {
+                settings.ring_effect = effect.into();
+            }
+            if let Some(colour) = lighting.ring_colour {
+                settings.ring_colour = colour.0;
+            }
+            if let Some(fade) = lighting.fade_ms {
+                settings.reactive_fade = Duration::from_millis(fade.into());
+            }
+            if let Some(leds) = &lighting.leds {
+                if leds.len() > MAX_BUTTON_LEDS {
+                    bail!("at most {MAX_BUTTON_LEDS} LEDs can be mapped");
+                }
+                for (led, name) in settings.button_leds.iter_mut().zip(leds) {
+                    *led = led_button(name)?;
+                }
+            }
+            check_names(&lighting.colours)?;
+            for (i, colour) in settings.key_colours.iter_mut().enumerate() {
+                for entry in entries_for(&lighting.colours, i) {
+                    *colour = entry.0;
+                }
+            }
+        }
+
+        check_names(&self.debounce)?;
+        for (i, config) in settings.debounce.iter_mut().enumerate() {
+            for entry in entries_for(&self.debounce, i) {
+                entry.apply(config);
+            }
+        }
+
+        for (name, key) in &self.keyboard {
+            let Some(i) = KEYBOARD_INPUT_NAMES.iter().position(|n| n == name) else {
+                bail!(
+                    "unknown keyboard input {name:?}, expected one of {}",
+                    KEYBOARD_INPUT_NAMES.join(", ")
+                );
+            };
+            settings.keyboard_keys[i] = key.0;
+        }
+
+        Ok(())
+    }
+}
+
+impl Debounce {
+    fn apply(&self, config: &mut DebounceConfig) {
+        if let Some(algorithm) = self.algorithm {
+            config.algorithm = algorithm.into();
+        }
+        if let Some(press) = self.press_us {
+            config.press = Duration::from_micros(press.into());
+        }
+        if let Some(release) = self.release_us {
+            config.release = Duration::from_micros(release.into());
+        }
+    }
+}
+
+fn usb_string(s: &str) -> Result<UsbString> {
+    match UsbString::new(s) {
+        Some(s) => Ok(s),
+        None => bail!("USB strings must be at most {MAX_USB_STRING_LEN} printable characters"),
+    }
+}
+
+fn check_names<T>(table: &BTreeMap<String, T>) -> Result<()> {
+    for name in table.keys() {
+        if name != ALL && !BUTTON_NAMES.contains(&name.as_str()) {
+            bail!(
+                "unknown button {name:?}, expected {ALL} or one of {}",
+                BUTTON_NAMES.join(", ")
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Entries that apply to `button`, `all` first so the button's own entry
+/// overrides it.
+fn entries_for<T>(table: &BTreeMap<String, T>, button: usize) -> impl Iterator<Item = &T> {
+    [ALL, BUTTON_NAMES[button]]
+        .into_iter()
+        .filter_map(|name| table.get(name))
+}
+
+fn led_button(name: &str) -> Result<u8> {
+    if name == NONE {
+        return Ok(NO_BUTTON);
+    }
+    match BUTTON_NAMES.iter().position(|&n| n == name) {
+        Some(i) => Ok(i as u8),
+        None => bail!(
+            "unknown button {name:?} for LED, expected {NONE} or one of {}",
+            BUTTON_NAMES.join(", ")
+        ),
+    }
+}
+
+fn duration_to_ms(d: Duration) -> u16 {
+    d.as_millis().min(u16::MAX.into()) as u16
+}
+
+fn duration_to_us(d: Duration) -> u16 {
+    d.as_micros().min(u16::MAX.into()) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dump_round_trips() {
+        let mut settings = Settings {
+            tt_steps: 256,
+            ..Default::default()
+        };
+        settings.key_colours[4] = RGB8::new(0x12, 0xAB, 0xFF);
+        settings.debounce[9].algorithm = DebounceAlgorithm::Asymmetric;
+        settings.led_mode = LedMode::Reactive;
+        settings.button_leds[1] = NO_BUTTON;
+        settings.button_leds[2] = 8;
+        settings.ring_effect = RingEffect::ScratchFlash;
+        settings.ring_colour = RGB8::new(0, 0, 0x40);
+        settings.tt_axis = TurntableAxis::Digital;
+        settings.tt_hold = Duration::from_millis(60);
+        settings.tt_deadzone = 1;
+        settings.encoder_ppr = 600;
+        settings.tt_max_rps = 40;
+        settings.encoders = 2;
+        settings.extra_encoders[0] = EncoderScaling { ppr: 24, steps: 96 };
+        settings.tt_smoothing = Duration::from_millis(4);
+        settings.tt_curve = TurntableCurve::Custom;
+        settings.tt_curve_gains[3] = 180;
+        settings.input_mode = InputMode::Switch;
+        settings.switch_tt = SwitchTurntable::Dpad;
+        settings.keyboard_keys[0] = 0x27;
+        settings.keyboard_keys[5] = 0x45;
+        settings.keyboard_keys[8] = 0x7F;
+        settings.keyboard_keys[12] = NO_KEY;
+        settings.serial_number = SerialNumber::new("DP-1P").unwrap();
+        settings.usb_profile = UsbProfile::Custom;
+        settings.custom_identity.vid = 0x1234;
+        settings.custom_identity.product = UsbString::new("My IIDX").unwrap();
+
+        let text = toml::to_string(&Config::from_settings(&settings)).unwrap();
+        let config: Config = toml::from_str(&text).unwrap();
+        let mut applied = Settings::default();
+        config.apply(&mut applied).unwrap();
+
+        assert!(text.contains("key5 = \"#12abff\""));
+        assert!(text.contains("axis = \"digital\""));
+        assert!(text.contains("key1 = \"0\""));
+        assert!(text.contains("key6 = \"f12\""));
+        assert!(text.contains("e2 = \"0x7f\""));
+        assert!(text.contains("tt-up = \"left-shift\""));
+        assert!(text.contains("tt-down = \"none\""));
+        assert_eq!(applied, settings);
+    }
+
+    #[test]
+    fn partial_file_only_changes_what_it_mentions() {
+        let config: Config = toml::from_str(
+            r##"
+            [lighting]
+            colours = { all = "#000000", key1 = "#ff0000" }
+            leds = ["e1", "none"]
+
+            [debounce.all]
+            release_us = 8000
+
+            [debounce.e2]
+            algorithm = "deferred"
+            "##,
+        )
+        .unwrap();
+        let mut settings = Settings::default();
+        config.apply(&mut settings).unwrap();
+
+        assert_eq!(settings.key_colours[0], RGB8::new(0xFF, 0, 0));
+        assert_eq!(settings.key_colours[1], RGB8::default());
+        assert_eq!(settings.button_leds[..3], [7, NO_BUTTON, 2]);
+        assert_eq!(settings.debounce[8].algorithm, DebounceAlgorithm::Deferred);
+        assert_eq!(settings.debounce[8].release, Duration::from_millis(8));
+        assert_eq!(settings.debounce[0].algorithm, DebounceAlgorithm::Eager);
+        assert_eq!(settings.tt_steps, Settings::default().tt_steps);
+
+        let config: Config = toml::from_str("[turntable]\naxis = \"raw\"").unwrap();
+        config.apply(&mut settings).unwrap();
+        assert_eq!(settings.tt_axis, TurntableAxis::Raw);
+        assert_eq!(settings.tt_steps, Settings::default().tt_steps);
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        let apply = |text: &str| -> Result<()> {
+            let config: Config = toml::from_str(text)?;
+            config.apply(&mut Settings::default())
+        };
+
+        assert!(apply("[debounce.key8]\npress_us = 1").is_err());
+        assert!(apply("[lighting]\ncolours = { key1 = \"red\" }").is_err());
+        assert!(apply("[turntable]\nsteps = 0").is_err());
+        assert!(apply("[lighting]\nleds = [\"key0\"]").is_err());
+        assert!(apply("tt_steps = 10").is_err());
+        assert!(apply("[turntable]\naxis = \"32-bit\"").is_err());
+        assert!(apply("[turntable]\nthreshold = 0").is_err());
+        assert!(apply("[turntable]\nppr = 0").is_err());
+        assert!(apply("[turntable]\ncurve = \"steep\"").is_err());
+        assert!(apply("[turntable]\ncurve_gains = [100, 200]").is_err());
+        assert!(apply("[turntable]\ncurve_gains = [0, 0, 0, 0, 0, 0, 0, 300]").is_err());
+        assert!(apply("[encoders]\ncount = 5").is_err());
+        assert!(apply("[encoders]\nextra = [{ ppr = 0, steps = 96 }]").is_err());
+        assert!(apply("[keyboard]\nkey1 = \"f25\"").is_err());
+        assert!(apply("[keyboard]\nkey1 = \"0x01\"").is_err());
+        assert!(apply("[keyboard]\ntt = \"a\"").is_err());
+        assert!(apply("serial_number = \"two words\"").is_err());
+        assert!(apply("[usb]\nprofile = \"sony\"").is_err());
+        assert!(apply("[usb]\nvid = 0x10000").is_err());
+        assert!(apply("[usb]\nproduct = \"beatmania IIDX controller premium model\"").is_err());
+    }
+}
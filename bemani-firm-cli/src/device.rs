@@ -0,0 +1,215 @@
+use std::io;
+
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::anyhow;
+use anyhow::bail;
+use bemani_firm_core::latency::InputKind;
+use bemani_firm_core::latency::LatencySummary;
+use bemani_firm_core::protocol;
+use bemani_firm_core::protocol::Command;
+use bemani_firm_core::protocol::FeatureReport;
+use bemani_firm_core::protocol::Info;
+use bemani_firm_core::protocol::PROTOCOL_VERSION;
+use bemani_firm_core::protocol::REPORT_LEN;
+use bemani_firm_core::settings::Settings;
+
+/// Reports that map onto settings, in the order they are read and written.
+const SETTINGS_REPORTS: &[FeatureReport] = &[
+    FeatureReport::Debounce,
+    FeatureReport::Turntable,
+    FeatureReport::Lighting,
+    FeatureReport::InputMode,
+    FeatureReport::ButtonLeds,
+    FeatureReport::RingLighting,
+    FeatureReport::TurntableAxis,
+    FeatureReport::DigitalTurntable,
+    FeatureReport::KeyboardKeys,
+    FeatureReport::SwitchTurntable,
+    FeatureReport::SerialNumber,
+    FeatureReport::UsbIdentity,
+    FeatureReport::TurntableFilter,
+    FeatureReport::Encoder,
+    FeatureReport::Encoders,
+];
+
+/// Moves raw feature reports to and from a controller.
+pub trait Transport {
+    /// Reads the feature report whose ID is in `buf[0]`, returning its
+    /// length including the ID.
+    fn get_feature(&mut self, buf: &mut [u8]) -> io::Result<usize>;
+
+    /// Sends a feature report, starting with its ID.
+    fn set_feature(&mut self, report: &[u8]) -> io::Result<()>;
+}
+
+impl<T: Transport + ?Sized> Transport for &mut T {
+    fn get_feature(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (**self).get_feature(buf)
+    }
+
+    fn set_feature(&mut self, report: &[u8]) -> io::Result<()> {
+        (**self).set_feature(report)
+    }
+}
+
+/// A controller speaking the feature report protocol.
+pub struct Device<T> {
+    transport: T,
+    info: Info,
+}
+
+impl<T: Transport> Device<T> {
+    /// Checks the controller speaks a protocol version this tool understands.
+    pub fn open(mut transport: T) -> Result<Self> {
+        let report = get(&mut transport, FeatureReport::Info)?;
+        let info = Info::decode(&report).map_err(|e| anyhow!("bad info report: {e:?}"))?;
+        if info.protocol_version != PROTOCOL_VERSION {
+            bail!(
+                "firmware speaks protocol version {}, this tool speaks {}",
+                info.protocol_version,
+                PROTOCOL_VERSION
+            );
+        }
+
+        Ok(Self { transport, info })
+    }
+
+    pub fn info(&self) -> &Info {
+        &self.info
+    }
+
+    pub fn read_settings(&mut self) -> Result<Settings> {
+        let mut settings = Settings::default();
+        for id in self.supported(SETTINGS_REPORTS) {
+            let report = get(&mut self.transport, id)?;
+            protocol::set_feature(id as u8, &report, &mut settings)
+                .map_err(|e| anyhow!("bad {id:?} report: {e:?}"))?;
+        }
+        Ok(settings)
+    }
+
+    /// Sends every report that differs between `current` and `updated`.
+    pub fn write_settings(&mut self, current: &Settings, updated: &Settings) -> Result<()> {
+        for id in self.supported(SETTINGS_REPORTS) {
+            let before = report_for(id, current)?;
+            let after = report_for(id, updated)?;
+            if before != after {
+                self.transport
+                    .set_feature(&after)
+                    .with_context(|| format!("controller rejected {id:?} report"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the debounce and queueing latency of every input kind the
+    /// firmware tracks.
+    pub fn read_latency(&mut self) -> Result<Vec<(InputKind, [LatencySummary; 2])>> {
+        let mut latency = Vec::new();
+        for &kind in InputKind::ALL {
+            let id = FeatureReport::latency(kind);
+            if !self.info.supports_report(id) {
+                continue;
+            }
+            let report = get(&mut self.transport, id)?;
+            let summaries = protocol::decode_latency_report(kind, &report)
+                .map_err(|e| anyhow!("bad {id:?} report: {e:?}"))?;
+            latency.push((kind, summaries));
+        }
+        Ok(latency)
+    }
+
+    pub fn command(&mut self, command: Command) -> Result<()> {
+        let mut report = [0; REPORT_LEN];
+        let len = protocol::command_report(command, &mut report).expect("buffer fits a report");
+        self.transport
+            .set_feature(&report[..len])
+            .with_context(|| format!("controller rejected {command:?} command"))
+    }
+
+    /// Filters out reports older firmware does not know about.
+    fn supported(&self, reports: &[FeatureReport]) -> Vec<FeatureReport> {
+        reports
+            .iter()
+            .copied()
+            .filter(|&report| self.info.supports_report(report))
+            .collect()
+    }
+}
+
+fn get(transport: &mut impl Transport, id: FeatureReport) -> Result<Vec<u8>> {
+    let mut buf = vec![0; REPORT_LEN];
+    buf[0] = id as u8;
+    let len = transport
+        .get_feature(&mut buf)
+        .with_context(|| format!("failed to read {id:?} report"))?;
+    buf.truncate(len);
+    Ok(buf)
+}
+
+fn report_for(id: FeatureReport, settings: &Settings) -> Result<[u8; REPORT_LEN]> {
+    let mut report = [0; REPORT_LEN];
+    protocol::get_feature(id as u8, settings, &mut report)
+        .ok_or_else(|| anyhow!("{id:?} is not a readable report"))?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::SimulatedDevice;
+
+    #[test]
+    fn reads_device_settings() {
+        let mut sim = SimulatedDevice::default();
+        sim.settings.tt_steps = 256;
+        sim.settings.key_colours[2] = smart_leds::RGB8::new(1, 2, 3);
+        let expected = sim.settings.clone();
+
+        let mut device = Device::open(&mut sim).unwrap();
+
+        assert_eq!(device.read_settings().unwrap(), expected);
+    }
+
+    #[test]
+    fn writes_only_changed_reports() {
+        let mut sim = SimulatedDevice::default();
+        let mut device = Device::open(&mut sim).unwrap();
+
+        let current = device.read_settings().unwrap();
+        let mut updated = current.clone();
+        updated.tt_steps = 72;
+        device.write_settings(&current, &updated).unwrap();
+        device.command(Command::Save).unwrap();
+
+        assert_eq!(sim.settings, updated);
+        assert_eq!(sim.saved, Some(updated));
+        assert_eq!(
+            sim.writes,
+            [FeatureReport::Turntable as u8, FeatureReport::Command as u8]
+        );
+    }
+
+    #[test]
+    fn rejected_report_is_an_error() {
+        let mut sim = SimulatedDevice::default();
+        let mut device = Device::open(&mut sim).unwrap();
+
+        let current = device.read_settings().unwrap();
+        let mut updated = current.clone();
+        updated.tt_steps = 0;
+
+        assert!(device.write_settings(&current, &updated).is_err());
+    }
+
+    #[test]
+    fn refuses_unknown_protocol_version() {
+        let mut sim = SimulatedDevice {
+            protocol_version: PROTOCOL_VERSION + 1,
+            ..Default::default()
+        };
+
+        assert!(Device::open(&mut sim).is_err());
+    }
+}
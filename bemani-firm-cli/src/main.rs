@@ -0,0 +1,326 @@
+//! Configures bemani-firm-rs controllers over their HID feature reports.
+
+mod config;
+mod device;
+mod hidraw;
+#[cfg(test)]
+mod sim;
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::bail;
+use bemani_firm_core::identity::KONAMI_OVERRIDE_NOTE;
+use bemani_firm_core::identity::konami_overrides;
+use bemani_firm_core::latency::InputKind;
+use bemani_firm_core::latency::LatencySummary;
+use bemani_firm_core::protocol::Command;
+
+use crate::config::Config;
+use crate::device::Device;
+use crate::device::Transport;
+use crate::hidraw::Hidraw;
+
+const USAGE: &str = "\
+usage: bemani-firm-cli <command> [options]
+
+commands:
+    list                    list connected controllers
+    dump                    print the controller's settings as TOML
+    apply <file> [--save]   apply settings from a TOML file, optionally
+                            writing them to flash
+    save                    write the current settings to flash
+    defaults                reset the settings to their defaults (not saved)
+    latency                 print input-to-USB latency statistics
+    reset-latency           clear the latency statistics
+
+options:
+    --device <path>         hidraw node to use, e.g. /dev/hidraw3, when
+                            more than one controller is connected";
+
+enum Action {
+    List,
+    Dump,
+    Apply { file: PathBuf, save: bool },
+    Latency,
+    Command(Command),
+}
+
+struct Args {
+    action: Action,
+    device: Option<PathBuf>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let mut positional = Vec::new();
+    let mut device = None;
+    let mut save = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--device" => device = Some(args.next().context("--device needs a path")?.into()),
+            "--save" => save = true,
+            "-h" | "--help" => bail!("{USAGE}"),
+            flag if flag.starts_with('-') => bail!("unknown option {flag}\n\n{USAGE}"),
+            _ => positional.push(arg),
+        }
+    }
+
+    let action = match positional.as_slice() {
+        [cmd] if cmd == "list" => Action::List,
+        [cmd] if cmd == "dump" => Action::Dump,
+        [cmd, file] if cmd == "apply" => Action::Apply {
+            file: file.into(),
+            save,
+        },
+        [cmd] if cmd == "save" => Action::Command(Command::Save),
+        [cmd] if cmd == "defaults" => Action::Command(Command::LoadDefaults),
+        [cmd] if cmd == "latency" => Action::Latency,
+        [cmd] if cmd == "reset-latency" => Action::Command(Command::ResetLatency),
+        _ => bail!("{USAGE}"),
+    };
+
+    Ok(Args { action, device })
+}
+
+fn main() -> ExitCode {
+    match parse_args(std::env::args().skip(1)).and_then(run) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Args) -> Result<()> {
+    if let Action::List = args.action {
+        return list();
+    }
+
+    let path = match args.device {
+        Some(path) => path,
+        None => only_device()?,
+    };
+    let hidraw =
+        Hidraw::open(&path).with_context(|| format!("failed to open {}", path.display()))?;
+
+    run_action(args.action, Device::open(hidraw)?, &mut std::io::stdout())
+}
+
+fn run_action<T: Transport>(
+    action: Action,
+    mut device: Device<T>,
+    out: &mut impl std::io::Write,
+) -> Result<()> {
+    match action {
+        Action::List => unreachable!("handled without opening a device"),
+        Action::Dump => {
+            let settings = device.read_settings()?;
+            write!(
+                out,
+                "{}",
+                toml::to_string(&Config::from_settings(&settings))?
+            )?;
+        }
+        Action::Apply { file, save } => {
+            let text = fs::read_to_string(&file)
+                .with_context(|| format!("failed to read {}", file.display()))?;
+            let config: Config =
+                toml::from_str(&text).with_context(|| format!("invalid {}", file.display()))?;
+
+            let current = device.read_settings()?;
+            let mut updated = current.clone();
+            config.apply(&mut updated)?;
+            device.write_settings(&current, &updated)?;
+            if save {
+                device.command(Command::Save)?;
+            }
+            if konami_overrides(&updated) {
+                writeln!(out, "note: {KONAMI_OVERRIDE_NOTE}")?;
+            }
+        }
+        Action::Latency => {
+            let latency = device.read_latency()?;
+            if latency.is_empty() {
+                bail!("this firmware does not record latency");
+            }
+            print_latency(&latency, out)?;
+        }
+        Action::Command(command) => device.command(command)?,
+    }
+    Ok(())
+}
+
+fn print_latency(
+    latency: &[(InputKind, [LatencySummary; 2])],
+    out: &mut impl std::io::Write,
+) -> Result<()> {
+    writeln!(
+        out,
+        "{:<10} {:<9} {:>7} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}",
+        "input", "stage", "count", "min us", "avg us", "p50 us", "p90 us", "p99 us", "max us"
+    )?;
+    for (kind, summaries) in latency {
+        let input = match kind {
+            InputKind::Keys => "keys",
+            InputKind::EButtons => "e-buttons",
+            InputKind::Turntable => "turntable",
+        };
+        for (stage, s) in ["debounce", "queue"].into_iter().zip(summaries) {
+            writeln!(
+                out,
+                "{input:<10} {stage:<9} {:>7} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}",
+                s.count, s.min_us, s.mean_us, s.p50_us, s.p90_us, s.p99_us, s.max_us
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn list() -> Result<()> {
+    let devices = hidraw::enumerate().context("failed to list hidraw devices")?;
+    if devices.is_empty() {
+        println!("no controllers found");
+    }
+
+    for info in devices {
+        let protocol = Hidraw::open(&info.path)
+            .map_err(anyhow::Error::from)
+            .and_then(Device::open)
+            .map(|device| format!("protocol v{}", device.info().protocol_version))
+            .unwrap_or_else(|e| format!("not accessible: {e:#}"));
+
+        println!(
+            "{}  {:04x}:{:04x}  {}  serial {}  ({protocol})",
+            info.path.display(),
+            info.vendor_id,
+            info.product_id,
+            info.name,
+            info.serial_number,
+        );
+    }
+    Ok(())
+}
+
+fn only_device() -> Result<PathBuf> {
+    let mut devices = hidraw::enumerate().context("failed to list hidraw devices")?;
+    match devices.len() {
+        0 => bail!("no controllers found"),
+        1 => Ok(devices.remove(0).path),
+        _ => bail!("more than one controller found, pick one with --device"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::SimulatedDevice;
+    use bemani_firm_core::latency::LatencyStats;
+    use bemani_firm_core::latency::Stage;
+    use bemani_firm_core::settings::Settings;
+    use bemani_firm_core::settings::TurntableAxis;
+    use embassy_time::Duration;
+
+    fn args(s: &str) -> Result<Args> {
+        parse_args(s.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn parses_commands() {
+        let parsed = args("apply dp.toml --save --device /dev/hidraw3").unwrap();
+
+        assert!(matches!(parsed.action, Action::Apply { save: true, .. }));
+        assert_eq!(parsed.device, Some("/dev/hidraw3".into()));
+        assert!(args("apply").is_err());
+        assert!(args("dump --bogus").is_err());
+    }
+
+    #[test]
+    fn dump_then_apply_restores_settings() {
+        let mut source = SimulatedDevice::default();
+        source.settings.tt_steps = 500;
+        source.settings.key_colours[6].g = 0x80;
+
+        let mut dump = Vec::new();
+        run_action(Action::Dump, Device::open(&mut source).unwrap(), &mut dump).unwrap();
+
+        let file =
+            std::env::temp_dir().join(format!("bemani-firm-cli-{}.toml", std::process::id()));
+        fs::write(&file, &dump).unwrap();
+
+        let mut dest = SimulatedDevice::default();
+        let apply = Action::Apply {
+            file: file.clone(),
+            save: true,
+        };
+        let result = run_action(apply, Device::open(&mut dest).unwrap(), &mut Vec::new());
+        fs::remove_file(&file).unwrap();
+
+        result.unwrap();
+        assert_eq!(dest.settings, source.settings);
+        assert_eq!(dest.saved.as_ref(), Some(&source.settings));
+        assert_ne!(dest.settings, Settings::default());
+    }
+
+    #[test]
+    fn apply_notes_konami_overrides() {
+        let file = std::env::temp_dir().join(format!(
+            "bemani-firm-cli-konami-{}.toml",
+            std::process::id()
+        ));
+        fs::write(&file, "[turntable]\naxis = \"16-bit\"\n").unwrap();
+
+        let mut sim = SimulatedDevice::default();
+        let apply = Action::Apply {
+            file: file.clone(),
+            save: false,
+        };
+        let mut out = Vec::new();
+        let result = run_action(apply, Device::open(&mut sim).unwrap(), &mut out);
+        fs::remove_file(&file).unwrap();
+
+        result.unwrap();
+        assert_eq!(sim.settings.tt_axis, TurntableAxis::Wrapped16);
+        assert!(
+            String::from_utf8(out)
+                .unwrap()
+                .contains(KONAMI_OVERRIDE_NOTE)
+        );
+    }
+
+    #[test]
+    fn latency_is_printed_and_reset() {
+        let mut sim = SimulatedDevice::default();
+        sim.latency.record(
+            InputKind::Turntable,
+            Stage::Queue,
+            Duration::from_micros(750),
+        );
+
+        let mut out = Vec::new();
+        run_action(Action::Latency, Device::open(&mut sim).unwrap(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.lines().any(|line| {
+            line.split_whitespace().collect::<Vec<_>>()
+                == [
+                    "turntable",
+                    "queue",
+                    "1",
+                    "750",
+                    "750",
+                    "750",
+                    "750",
+                    "750",
+                    "750",
+                ]
+        }));
+
+        let reset = Action::Command(Command::ResetLatency);
+        run_action(reset, Device::open(&mut sim).unwrap(), &mut Vec::new()).unwrap();
+        assert_eq!(sim.latency, LatencyStats::new());
+    }
+}
@@ -0,0 +1,73 @@
+//! An in-process controller for testing against.
+
+use std::io;
+
+use bemani_firm_core::latency::InputKind;
+use bemani_firm_core::latency::LatencyStats;
+use bemani_firm_core::protocol;
+use bemani_firm_core::protocol::Command;
+use bemani_firm_core::protocol::FeatureReport;
+use bemani_firm_core::protocol::PROTOCOL_VERSION;
+use bemani_firm_core::settings::Settings;
+
+use crate::device::Transport;
+
+/// Answers feature reports the same way the firmware's configuration
+/// interface does, from the core protocol handlers.
+pub struct SimulatedDevice {
+    pub settings: Settings,
+    /// Settings as of the last save command.
+    pub saved: Option<Settings>,
+    /// IDs of the reports that were accepted, in order.
+    pub writes: Vec<u8>,
+    /// Version reported in the info report.
+    pub protocol_version: u8,
+    pub latency: LatencyStats,
+}
+
+impl Default for SimulatedDevice {
+    fn default() -> Self {
+        Self {
+            settings: Settings::default(),
+            saved: None,
+            writes: Vec::new(),
+            protocol_version: PROTOCOL_VERSION,
+            latency: LatencyStats::new(),
+        }
+    }
+}
+
+impl Transport for SimulatedDevice {
+    fn get_feature(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let id = buf[0];
+        let latency = InputKind::ALL
+            .iter()
+            .find(|&&kind| FeatureReport::latency(kind) as u8 == id);
+        let len = match latency {
+            Some(&kind) => protocol::latency_report(kind, &self.latency, buf),
+            None => protocol::get_feature(id, &self.settings, buf),
+        }
+        .ok_or(stall())?;
+        if id == FeatureReport::Info as u8 {
+            buf[1] = self.protocol_version;
+        }
+        Ok(len)
+    }
+
+    fn set_feature(&mut self, report: &[u8]) -> io::Result<()> {
+        let id = *report.first().ok_or(stall())?;
+        let command = protocol::set_feature(id, report, &mut self.settings).map_err(|_| stall())?;
+        match command {
+            Some(Command::Save) => self.saved = Some(self.settings.clone()),
+            Some(Command::ResetLatency) => self.latency.reset(),
+            Some(Command::LoadDefaults) | None => {}
+        }
+        self.writes.push(id);
+        Ok(())
+    }
+}
+
+/// What hidraw returns when the device rejects a control request.
+fn stall() -> io::Error {
+    io::Error::from_raw_os_error(libc::EPIPE)
+}
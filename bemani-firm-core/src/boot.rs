@@ -0,0 +1,150 @@
+//! Picking the USB personality by holding a key while plugging in.
+//!
+//! The buttons are sampled once before USB starts. Holding exactly one of
+//! the [`BOOT_MODE_KEYS`] uses its mode for this boot; holding
+//! [`BOOT_SAVE_BUTTON`] as well also makes it the saved default.
+
+use embassy_time::Duration;
+use smart_leds::RGB8;
+
+use crate::button::NUM_BUTTONS;
+use crate::settings::{InputMode, Settings};
+
+/// Key held at power-on for each mode, as settings order button indices.
+pub const BOOT_MODE_KEYS: [(usize, InputMode); 3] = [
+    (0, InputMode::Joystick),
+    (1, InputMode::Keyboard),
+    (2, InputMode::Switch),
+];
+
+/// E1, held along with a mode key to save the mode.
+pub const BOOT_SAVE_BUTTON: usize = 7;
+
+/// How long the button LEDs show the chosen mode after boot.
+pub const BOOT_INDICATOR_TIME: Duration = Duration::from_secs(2);
+
+/// Blink period of the indicator when the mode was saved.
+const SAVED_BLINK_PERIOD: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BootSelection {
+    pub mode: InputMode,
+    /// Whether the mode should also be saved as the default.
+    pub save: bool,
+}
+
+impl BootSelection {
+    /// Reads the selection from the buttons held at power-on, in settings
+    /// order. Holding more than one mode key picks nothing.
+    pub fn from_held(held: &[bool; NUM_BUTTONS]) -> Option<Self> {
+        let mut modes = BOOT_MODE_KEYS
+            .iter()
+            .filter(|&&(button, _)| held[button])
+            .map(|&(_, mode)| mode);
+
+        let mode = modes.next()?;
+        if modes.next().is_some() {
+            return None;
+        }
+
+        Some(Self {
+            mode,
+            save: held[BOOT_SAVE_BUTTON],
+        })
+    }
+
+    fn key(&self) -> usize {
+        BOOT_MODE_KEYS
+            .iter()
+            .find(|&&(_, mode)| mode == self.mode)
+            .map_or(NUM_BUTTONS, |&(button, _)| button)
+    }
+
+    /// Lights the LED of the chosen mode's key white and the rest off,
+    /// blinking if the mode was saved. Returns `false` once the indicator
+    /// has run its time, leaving `leds` alone.
+    pub fn render(&self, settings: &Settings, leds: &mut [RGB8], since_boot: Duration) -> bool {
+        if since_boot >= BOOT_INDICATOR_TIME {
+            return false;
+        }
+
+        let blink_off =
+            self.save && (since_boot.as_millis() / SAVED_BLINK_PERIOD.as_millis()) % 2 == 1;
+        let key = self.key();
+
+        for (led, &button) in leds.iter_mut().zip(&settings.button_leds) {
+            *led = if button as usize == key && !blink_off {
+                RGB8::new(0xFF, 0xFF, 0xFF)
+            } else {
+                RGB8::default()
+            };
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn held(buttons: &[usize]) -> [bool; NUM_BUTTONS] {
+        core::array::from_fn(|i| buttons.contains(&i))
+    }
+
+    #[test]
+    fn mode_keys_pick_modes() {
+        assert_eq!(BootSelection::from_held(&held(&[])), None);
+        assert_eq!(
+            BootSelection::from_held(&held(&[1])),
+            Some(BootSelection {
+                mode: InputMode::Keyboard,
+                save: false
+            })
+        );
+        assert_eq!(
+            BootSelection::from_held(&held(&[2, BOOT_SAVE_BUTTON])),
+            Some(BootSelection {
+                mode: InputMode::Switch,
+                save: true
+            })
+        );
+    }
+
+    #[test]
+    fn ambiguous_or_unrelated_buttons_pick_nothing() {
+        assert_eq!(BootSelection::from_held(&held(&[0, 2])), None);
+        assert_eq!(
+            BootSelection::from_held(&held(&[5, BOOT_SAVE_BUTTON])),
+            None
+        );
+    }
+
+    #[test]
+    fn indicator_lights_the_mode_key() {
+        let settings = Settings::default();
+        let selection = BootSelection {
+            mode: InputMode::Keyboard,
+            save: true,
+        };
+        let mut leds = [RGB8::new(1, 1, 1); 3];
+
+        assert!(selection.render(&settings, &mut leds, Duration::from_millis(100)));
+        assert_eq!(
+            leds,
+            [
+                RGB8::default(),
+                RGB8::new(0xFF, 0xFF, 0xFF),
+                RGB8::default()
+            ]
+        );
+
+        // Saved selections blink
+        assert!(selection.render(&settings, &mut leds, Duration::from_millis(300)));
+        assert_eq!(leds, [RGB8::default(); 3]);
+
+        leds[0] = RGB8::new(1, 1, 1);
+        assert!(!selection.render(&settings, &mut leds, BOOT_INDICATOR_TIME));
+        assert_eq!(leds[0], RGB8::new(1, 1, 1));
+    }
+}
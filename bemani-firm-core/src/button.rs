@@ -0,0 +1,152 @@
+use embassy_time::{Duration, Instant};
+
+use crate::debounce::{AnyDebouncer, DebounceConfig, Debouncer};
+
+pub const POLL_PERIOD: Duration = Duration::from_micros(250);
+
+pub const NUM_BUTTONS: usize = 11;
+
+/// Report bit for each button: the seven keys, then E1-E4 in the high byte.
+pub const OUTPUT_INDICES: [u8; NUM_BUTTONS] = [0, 1, 2, 3, 4, 5, 6, 8, 9, 10, 11];
+
+/// Debounce state for a single button.
+pub struct Button {
+    output_index: u8,
+    debouncer: AnyDebouncer,
+}
+
+impl Button {
+    pub fn new(output_index: u8, config: &DebounceConfig) -> Self {
+        Self {
+            output_index,
+            debouncer: AnyDebouncer::new(config, POLL_PERIOD),
+        }
+    }
+
+    pub fn output_index(&self) -> u8 {
+        self.output_index
+    }
+
+    pub fn pressed(&self) -> bool {
+        self.debouncer.is_pressed()
+    }
+
+    /// Swaps the debounce algorithm. The new debouncer starts released and
+    /// picks a held button back up on its next samples.
+    pub fn set_config(&mut self, config: &DebounceConfig) {
+        self.debouncer = AnyDebouncer::new(config, POLL_PERIOD);
+    }
+
+    /// Feeds a new pin sample taken at `time`, returning the debounced state.
+    pub fn update(&mut self, new_pin_state: bool, time: Instant) -> bool {
+        self.debouncer.update(new_pin_state, time)
+    }
+}
+
+/// Creates the full set of buttons, each with its own debounce config.
+pub fn new_buttons(configs: &[DebounceConfig; NUM_BUTTONS]) -> [Button; NUM_BUTTONS] {
+    core::array::from_fn(|i| Button::new(OUTPUT_INDICES[i], &configs[i]))
+}
+
+/// Applies per-button debounce configs, only resetting buttons whose
+/// config actually changed.
+pub fn apply_debounce_configs(
+    b: &mut [Button; NUM_BUTTONS],
+    old: &[DebounceConfig; NUM_BUTTONS],
+    new: &[DebounceConfig; NUM_BUTTONS],
+) {
+    for ((button, old), new) in b.iter_mut().zip(old).zip(new) {
+        if old != new {
+            button.set_config(new);
+        }
+    }
+}
+
+/// Updates every button from the matching entry in `pin_states`.
+pub fn poll_buttons(b: &mut [Button], pin_states: &[bool], time: Instant) {
+    for (button, new_pin_state) in b.iter_mut().zip(pin_states) {
+        button.update(*new_pin_state, time);
+    }
+}
+
+pub fn buttons_to_bitstring(b: &[Button]) -> u16 {
+    let mut output: u16 = 0;
+
+    for button in b {
+        if button.pressed() {
+            output |= 1 << button.output_index;
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debounce::DebounceAlgorithm;
+
+    #[test]
+    fn bitstring_uses_output_indices() {
+        let config = DebounceConfig::default();
+        let mut buttons = [
+            Button::new(0, &config),
+            Button::new(6, &config),
+            Button::new(8, &config),
+            Button::new(11, &config),
+        ];
+        poll_buttons(
+            &mut buttons,
+            &[true, false, true, true],
+            Instant::from_millis(10),
+        );
+
+        assert_eq!(buttons_to_bitstring(&buttons), 0b1001_0000_0001);
+    }
+
+    #[test]
+    fn buttons_have_independent_timing() {
+        let mut configs = [DebounceConfig::default(); NUM_BUTTONS];
+        // E1 holds presses for longer, key 1 keeps the default
+        configs[7].press = Duration::from_millis(20);
+        let mut buttons = new_buttons(&configs);
+
+        let mut pins = [false; NUM_BUTTONS];
+        pins[0] = true;
+        pins[7] = true;
+        poll_buttons(&mut buttons, &pins, Instant::from_millis(10));
+
+        pins = [false; NUM_BUTTONS];
+        poll_buttons(&mut buttons, &pins, Instant::from_millis(15));
+
+        assert_eq!(buttons_to_bitstring(&buttons), 1 << 8);
+
+        poll_buttons(&mut buttons, &pins, Instant::from_millis(31));
+        assert_eq!(buttons_to_bitstring(&buttons), 0);
+    }
+
+    #[test]
+    fn apply_only_resets_changed_buttons() {
+        let old = [DebounceConfig::default(); NUM_BUTTONS];
+        let mut buttons = new_buttons(&old);
+        poll_buttons(&mut buttons, &[true; NUM_BUTTONS], Instant::from_millis(10));
+
+        let mut new = old;
+        new[3].algorithm = DebounceAlgorithm::Deferred;
+        apply_debounce_configs(&mut buttons, &old, &new);
+
+        assert_eq!(buttons_to_bitstring(&buttons), 0b1111_0111_0111);
+    }
+
+    #[test]
+    fn set_config_switches_algorithm() {
+        let mut button = Button::new(0, &DebounceConfig::default());
+        button.set_config(&DebounceConfig {
+            algorithm: DebounceAlgorithm::Deferred,
+            ..Default::default()
+        });
+
+        // Deferred needs 4ms of samples before a press registers
+        assert!(!button.update(true, Instant::from_millis(10)));
+    }
+}
@@ -0,0 +1,123 @@
+//! Little-endian cursor helpers shared by the settings record and the
+//! configuration reports.
+
+pub struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+    overflowed: bool,
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self {
+            buf,
+            pos: 0,
+            overflowed: false,
+        }
+    }
+
+    /// Number of bytes written, or `None` if `buf` was too small.
+    pub fn finish(self) -> Option<usize> {
+        (!self.overflowed).then_some(self.pos)
+    }
+
+    pub fn bytes(&mut self, data: &[u8]) {
+        match self.buf.get_mut(self.pos..self.pos + data.len()) {
+            Some(dst) => {
+                dst.copy_from_slice(data);
+                self.pos += data.len();
+            }
+            None => self.overflowed = true,
+        }
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes(&[value]);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+}
+
+/// Reads fields in order, returning `None` once the data runs out.
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub fn bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (head, tail) = self.data.split_first_chunk::<N>()?;
+        self.data = tail;
+        Some(*head)
+    }
+
+    pub fn u8(&mut self) -> Option<u8> {
+        self.bytes::<1>().map(|b| b[0])
+    }
+
+    pub fn u16(&mut self) -> Option<u16> {
+        self.bytes().map(u16::from_le_bytes)
+    }
+
+    pub fn u32(&mut self) -> Option<u32> {
+        self.bytes().map(u32::from_le_bytes)
+    }
+}
+
+/// CRC-32 (IEEE 802.3, as used by zlib).
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut buf = [0u8; 7];
+        let mut w = Writer::new(&mut buf);
+        w.u8(1);
+        w.u16(0x0302);
+        w.u32(0x0706_0504);
+        assert_eq!(w.finish(), Some(7));
+        assert_eq!(buf, [1, 2, 3, 4, 5, 6, 7]);
+
+        let mut r = Reader::new(&buf);
+        assert_eq!(r.u8(), Some(1));
+        assert_eq!(r.u16(), Some(0x0302));
+        assert_eq!(r.u32(), Some(0x0706_0504));
+        assert_eq!(r.u8(), None);
+    }
+
+    #[test]
+    fn writer_reports_overflow() {
+        let mut buf = [0u8; 3];
+        let mut w = Writer::new(&mut buf);
+        w.u16(1);
+        w.u16(2);
+        assert_eq!(w.finish(), None);
+    }
+}
@@ -0,0 +1,413 @@
+use embassy_time::{Duration, Instant};
+
+pub const DEBOUNCE_TIME: Duration = Duration::from_millis(4);
+
+/// Turns raw, possibly bouncing, switch samples into a stable pressed state.
+pub trait Debouncer {
+    /// Feeds a raw sample taken at `now`, returning the debounced state.
+    fn update(&mut self, raw: bool, now: Instant) -> bool;
+
+    fn is_pressed(&self) -> bool;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum DebounceAlgorithm {
+    /// Report a transition immediately, then lock out further changes.
+    #[default]
+    Eager = 0,
+    /// Only report a transition once the input has settled on it.
+    Deferred = 1,
+    /// Report presses immediately but defer releases until they settle.
+    Asymmetric = 2,
+}
+
+impl DebounceAlgorithm {
+    /// Every algorithm this firmware supports.
+    pub const ALL: &[DebounceAlgorithm] = &[
+        DebounceAlgorithm::Eager,
+        DebounceAlgorithm::Deferred,
+        DebounceAlgorithm::Asymmetric,
+    ];
+}
+
+impl TryFrom<u8> for DebounceAlgorithm {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Eager),
+            1 => Ok(Self::Deferred),
+            2 => Ok(Self::Asymmetric),
+            x => Err(x),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DebounceConfig {
+    pub algorithm: DebounceAlgorithm,
+    /// Time a press is locked in for, or has to be stable for.
+    pub press: Duration,
+    /// Time a release is locked in for, or has to be stable for.
+    pub release: Duration,
+}
+
+impl Default for DebounceConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: DebounceAlgorithm::default(),
+            press: DEBOUNCE_TIME,
+            release: DEBOUNCE_TIME,
+        }
+    }
+}
+
+/// Eager "lock-out after transition" debouncing.
+///
+/// A change is reported on the first sample that shows it, after which the
+/// input is ignored until the lockout for that transition has passed. This
+/// gives the lowest latency but lets single-sample noise through.
+pub struct EagerDebouncer {
+    press_lockout: Duration,
+    release_lockout: Duration,
+    pressed: bool,
+    transition_time: Instant,
+}
+
+impl EagerDebouncer {
+    pub const fn new(press_lockout: Duration, release_lockout: Duration) -> Self {
+        Self {
+            press_lockout,
+            release_lockout,
+            pressed: false,
+            transition_time: Instant::from_ticks(0),
+        }
+    }
+}
+
+impl Debouncer for EagerDebouncer {
+    fn update(&mut self, raw: bool, now: Instant) -> bool {
+        let lockout = if self.pressed {
+            self.press_lockout
+        } else {
+            self.release_lockout
+        };
+        let debounce_time_elapsed = now - self.transition_time > lockout;
+
+        if raw != self.pressed && debounce_time_elapsed {
+            self.pressed = raw;
+            self.transition_time = now;
+        }
+
+        self.pressed
+    }
+
+    fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+}
+
+/// Deferred, integrating debouncing.
+///
+/// Every sample that disagrees with the current state counts towards a
+/// change and every sample that agrees counts back down, so a change is only
+/// reported after it has been seen on N more samples than it was not.
+pub struct DeferredDebouncer {
+    press_samples: u16,
+    release_samples: u16,
+    count: u16,
+    pressed: bool,
+}
+
+impl DeferredDebouncer {
+    pub const fn new(press_samples: u16, release_samples: u16) -> Self {
+        Self {
+            press_samples,
+            release_samples,
+            count: 0,
+            pressed: false,
+        }
+    }
+
+    /// Builds a debouncer that needs the input to settle for `press` or
+    /// `release` when sampled every `poll_period`.
+    pub fn from_durations(press: Duration, release: Duration, poll_period: Duration) -> Self {
+        Self::new(
+            samples_for(press, poll_period),
+            samples_for(release, poll_period),
+        )
+    }
+}
+
+fn samples_for(time: Duration, poll_period: Duration) -> u16 {
+    let poll_ticks = poll_period.as_ticks().max(1);
+    let samples = time.as_ticks().div_ceil(poll_ticks).max(1);
+    samples.min(u16::MAX as u64) as u16
+}
+
+impl Debouncer for DeferredDebouncer {
+    fn update(&mut self, raw: bool, _now: Instant) -> bool {
+        if raw == self.pressed {
+            self.count = self.count.saturating_sub(1);
+            return self.pressed;
+        }
+
+        self.count += 1;
+
+        let required = if raw {
+            self.press_samples
+        } else {
+            self.release_samples
+        };
+
+        if self.count >= required {
+            self.pressed = raw;
+            self.count = 0;
+        }
+
+        self.pressed
+    }
+
+    fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+}
+
+/// Eager presses with deferred releases.
+///
+/// A press is reported immediately and held for the press time. After that
+/// the input has to read released continuously for the release time before
+/// the release is reported, which hides switches that chatter on the way up.
+pub struct AsymmetricDebouncer {
+    press_hold: Duration,
+    release_settle: Duration,
+    pressed: bool,
+    transition_time: Instant,
+    release_since: Option<Instant>,
+}
+
+impl AsymmetricDebouncer {
+    pub const fn new(press_hold: Duration, release_settle: Duration) -> Self {
+        Self {
+            press_hold,
+            release_settle,
+            pressed: false,
+            transition_time: Instant::from_ticks(0),
+            release_since: None,
+        }
+    }
+}
+
+impl Debouncer for AsymmetricDebouncer {
+    fn update(&mut self, raw: bool, now: Instant) -> bool {
+        if !self.pressed {
+            if raw {
+                self.pressed = true;
+                self.transition_time = now;
+            }
+            return self.pressed;
+        }
+
+        if now - self.transition_time <= self.press_hold || raw {
+            self.release_since = None;
+            return self.pressed;
+        }
+
+        let since = *self.release_since.get_or_insert(now);
+        if now - since >= self.release_settle {
+            self.pressed = false;
+            self.transition_time = now;
+            self.release_since = None;
+        }
+
+        self.pressed
+    }
+
+    fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+}
+
+/// A debouncer whose algorithm is picked at runtime from a [`DebounceConfig`].
+pub enum AnyDebouncer {
+    Eager(EagerDebouncer),
+    Deferred(DeferredDebouncer),
+    Asymmetric(AsymmetricDebouncer),
+}
+
+impl AnyDebouncer {
+    pub fn new(config: &DebounceConfig, poll_period: Duration) -> Self {
+        match config.algorithm {
+            DebounceAlgorithm::Eager => {
+                Self::Eager(EagerDebouncer::new(config.press, config.release))
+            }
+            DebounceAlgorithm::Deferred => Self::Deferred(DeferredDebouncer::from_durations(
+                config.press,
+                config.release,
+                poll_period,
+            )),
+            DebounceAlgorithm::Asymmetric => {
+                Self::Asymmetric(AsymmetricDebouncer::new(config.press, config.release))
+            }
+        }
+    }
+}
+
+impl Debouncer for AnyDebouncer {
+    fn update(&mut self, raw: bool, now: Instant) -> bool {
+        match self {
+            Self::Eager(d) => d.update(raw, now),
+            Self::Deferred(d) => d.update(raw, now),
+            Self::Asymmetric(d) => d.update(raw, now),
+        }
+    }
+
+    fn is_pressed(&self) -> bool {
+        match self {
+            Self::Eager(d) => d.is_pressed(),
+            Self::Deferred(d) => d.is_pressed(),
+            Self::Asymmetric(d) => d.is_pressed(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    const POLL_US: u64 = 250;
+    // Start well after boot so the initial eager lockout has expired
+    const START_US: u64 = 10_000;
+
+    /// Expands `(samples, level)` segments into one raw level per poll.
+    fn trace(segments: &[(usize, bool)]) -> Vec<bool> {
+        segments
+            .iter()
+            .flat_map(|&(n, level)| core::iter::repeat_n(level, n))
+            .collect()
+    }
+
+    fn run(d: &mut impl Debouncer, raw: &[bool]) -> Vec<bool> {
+        raw.iter()
+            .enumerate()
+            .map(|(i, &level)| d.update(level, Instant::from_micros(START_US + i as u64 * POLL_US)))
+            .collect()
+    }
+
+    /// Sample indices at which the debounced output changed.
+    fn edges(out: &[bool]) -> Vec<usize> {
+        let mut prev = false;
+        let mut edges = Vec::new();
+        for (i, &level) in out.iter().enumerate() {
+            if level != prev {
+                edges.push(i);
+                prev = level;
+            }
+        }
+        edges
+    }
+
+    // A press that bounces for 1ms, holds, then bounces again on release
+    fn bouncy_press() -> Vec<bool> {
+        trace(&[
+            (4, false),
+            (1, true),
+            (1, false),
+            (2, true),
+            (1, false),
+            (40, true),
+            (1, false),
+            (1, true),
+            (2, false),
+            (1, true),
+            (40, false),
+        ])
+    }
+
+    #[test]
+    fn eager_reports_first_edge_and_ignores_bounce() {
+        let mut d = EagerDebouncer::new(DEBOUNCE_TIME, DEBOUNCE_TIME);
+        let out = run(&mut d, &bouncy_press());
+
+        assert_eq!(edges(&out), [4, 49]);
+    }
+
+    #[test]
+    fn eager_lets_single_sample_noise_through() {
+        let mut d = EagerDebouncer::new(DEBOUNCE_TIME, DEBOUNCE_TIME);
+        let out = run(&mut d, &trace(&[(4, false), (1, true), (40, false)]));
+
+        // The spike is held for the whole lockout
+        assert_eq!(edges(&out), [4, 21]);
+    }
+
+    #[test]
+    fn deferred_waits_for_consistent_samples() {
+        let mut d = DeferredDebouncer::new(4, 4);
+        let out = run(&mut d, &bouncy_press());
+
+        // Press: +1 -1 +2 -1 then three more samples to reach four
+        assert_eq!(edges(&out), [11, 56]);
+    }
+
+    #[test]
+    fn deferred_ignores_single_sample_noise() {
+        let mut d = DeferredDebouncer::new(4, 4);
+        let out = run(&mut d, &trace(&[(4, false), (1, true), (40, false)]));
+
+        assert!(edges(&out).is_empty());
+    }
+
+    #[test]
+    fn deferred_samples_follow_poll_period() {
+        let d = DeferredDebouncer::from_durations(
+            Duration::from_millis(1),
+            Duration::from_micros(1100),
+            Duration::from_micros(POLL_US),
+        );
+
+        assert_eq!((d.press_samples, d.release_samples), (4, 5));
+    }
+
+    #[test]
+    fn asymmetric_presses_eagerly_and_releases_late() {
+        let mut d = AsymmetricDebouncer::new(Duration::from_millis(1), Duration::from_millis(2));
+        let out = run(&mut d, &bouncy_press());
+
+        // Release bounces end at sample 53, then 2ms (8 samples) must pass
+        assert_eq!(edges(&out), [4, 62]);
+    }
+
+    #[test]
+    fn asymmetric_ignores_short_release_glitch() {
+        let mut d = AsymmetricDebouncer::new(Duration::from_millis(1), Duration::from_millis(2));
+        let out = run(&mut d, &trace(&[(20, true), (6, false), (20, true)]));
+
+        assert_eq!(edges(&out), [0]);
+    }
+
+    #[test]
+    fn any_debouncer_follows_config() {
+        let config = DebounceConfig {
+            algorithm: DebounceAlgorithm::Deferred,
+            ..Default::default()
+        };
+        let mut d = AnyDebouncer::new(&config, Duration::from_micros(POLL_US));
+        let out = run(&mut d, &trace(&[(1, true), (40, false)]));
+
+        assert!(matches!(d, AnyDebouncer::Deferred(_)));
+        assert!(edges(&out).is_empty());
+    }
+
+    #[test]
+    fn algorithm_round_trips_through_u8() {
+        for &algorithm in DebounceAlgorithm::ALL {
+            assert_eq!(DebounceAlgorithm::try_from(algorithm as u8), Ok(algorithm));
+        }
+        assert_eq!(DebounceAlgorithm::try_from(3), Err(3));
+    }
+}
@@ -0,0 +1,447 @@
+//! Effects for the turntable LED ring.
+//!
+//! Each effect draws a whole frame from the current inputs and the time, so
+//! frames can be rendered into plain buffers and checked on the host.
+
+use embassy_time::{Duration, Instant};
+use smart_leds::RGB8;
+use smart_leds::hsv::{Hsv, hsv2rgb};
+
+use crate::lights::dim;
+use crate::settings::RingEffect;
+
+/// Time for the rainbow to go once around the hue circle.
+pub const RAINBOW_CYCLE_TIME: Duration = Duration::from_millis(3500);
+
+/// Time for one breath in and out.
+pub const BREATHING_PERIOD: Duration = Duration::from_millis(4000);
+
+/// Time a trail LED takes to fade out once the dot has passed.
+pub const TRAIL_FADE_TIME: Duration = Duration::from_millis(300);
+
+/// Time a scratch flash takes to fade out.
+pub const FLASH_TIME: Duration = Duration::from_millis(150);
+
+/// What the effects can react to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InputState {
+    /// Raw encoder count.
+    pub tt_position: i32,
+    /// Encoder counts in one platter rotation.
+    pub counts_per_rotation: i32,
+    /// Signed platter speed in thousandths of an RPM.
+    pub tt_milli_rpm: i32,
+    /// Platter direction changes so far, wrapping, see
+    /// [`TurntableMotion::reversals`](crate::velocity::TurntableMotion::reversals).
+    /// A change takes one report step back at the configured `tt_steps`.
+    pub tt_reversals: u32,
+    /// Debounced buttons, as produced by the button task.
+    pub buttons: u16,
+}
+
+impl InputState {
+    /// Index of the LED under the platter's reference point.
+    pub fn ring_index(&self, num_leds: usize) -> usize {
+        let counts = self.counts_per_rotation.max(1) as i64;
+        let position = (self.tt_position as i64).rem_euclid(counts);
+        (position * num_leds as i64 / counts) as usize
+    }
+}
+
+pub trait Effect {
+    /// Draws the frame for `now` into `leds`.
+    fn render(&mut self, leds: &mut [RGB8], input: &InputState, now: Instant);
+}
+
+/// Fraction of the way through `period` at `now`, out of `scale`.
+fn phase(now: Instant, period: Duration, scale: u64) -> u64 {
+    let period = period.as_ticks().max(1);
+    now.as_ticks() % period * scale / period
+}
+
+/// `level` out of 255 after `elapsed` of a linear fade lasting `time`.
+fn fade(elapsed: Duration, time: Duration) -> u8 {
+    let (elapsed, time) = (elapsed.as_ticks(), time.as_ticks());
+    if elapsed >= time {
+        0
+    } else {
+        (u8::MAX as u64 * (time - elapsed) / time) as u8
+    }
+}
+
+/// A single LED following the platter.
+pub struct SpinningDot {
+    pub colour: RGB8,
+}
+
+impl Effect for SpinningDot {
+    fn render(&mut self, leds: &mut [RGB8], input: &InputState, _now: Instant) {
+        leds.fill(RGB8::default());
+        if !leds.is_empty() {
+            leds[input.ring_index(leds.len())] = self.colour;
+        }
+    }
+}
+
+/// Hues spread around the ring, cycling over time.
+pub struct Rainbow {
+    pub cycle_time: Duration,
+}
+
+impl Effect for Rainbow {
+    fn render(&mut self, leds: &mut [RGB8], _input: &InputState, now: Instant) {
+        let start = phase(now, self.cycle_time, 256);
+        let num_leds = leds.len() as u64;
+
+        for (i, led) in leds.iter_mut().enumerate() {
+            let hue = (start + i as u64 * 256 / num_leds) as u8;
+            *led = hsv2rgb(Hsv {
+                hue,
+                sat: 255,
+                val: 255,
+            });
+        }
+    }
+}
+
+/// The whole ring pulsing in and out.
+pub struct Breathing {
+    pub colour: RGB8,
+    pub period: Duration,
+}
+
+impl Effect for Breathing {
+    fn render(&mut self, leds: &mut [RGB8], _input: &InputState, now: Instant) {
+        let t = phase(now, self.period, 510);
+        let triangle = if t < 256 { t } else { 510 - t };
+        // Squared so the dim end lingers like a real breath
+        let level = (triangle * triangle / 255) as u8;
+        leds.fill(dim(self.colour, level));
+    }
+}
+
+/// A dot with a tail behind it as long as the ground the platter covers in
+/// `fade_time` at its current speed, dimming towards the end, so the trail
+/// grows with spin speed.
+pub struct VelocityTrail {
+    pub colour: RGB8,
+    pub fade_time: Duration,
+}
+
+impl VelocityTrail {
+    /// LEDs the dot passes in `fade_time`, at most all but the head.
+    fn length(&self, input: &InputState, num_leds: usize) -> usize {
+        // 1000 milli-RPM for 60 000 ms is one rotation
+        let leds =
+            input.tt_milli_rpm.unsigned_abs() as u64 * num_leds as u64 * self.fade_time.as_millis()
+                / 60_000_000;
+        leds.min(num_leds as u64 - 1) as usize
+    }
+}
+
+impl Effect for VelocityTrail {
+    fn render(&mut self, leds: &mut [RGB8], input: &InputState, _now: Instant) {
+        leds.fill(RGB8::default());
+        let num_leds = leds.len();
+        if num_leds == 0 {
+            return;
+        }
+        let head = input.ring_index(num_leds);
+        let len = self.length(input, num_leds);
+
+        for step in 0..=len {
+            // The tail lies where the platter came from
+            let index = if input.tt_milli_rpm >= 0 {
+                (head + num_leds - step) % num_leds
+            } else {
+                (head + step) % num_leds
+            };
+            let level = u8::MAX as usize * (len + 1 - step) / (len + 1);
+            leds[index] = dim(self.colour, level as u8);
+        }
+    }
+}
+
+/// The spinning dot, with the whole ring flashing whenever the platter
+/// changes direction.
+pub struct ScratchFlash {
+    pub colour: RGB8,
+    pub flash_time: Duration,
+    /// Last [`InputState::tt_reversals`] seen.
+    reversals: Option<u32>,
+    flashed_at: Option<Instant>,
+}
+
+impl ScratchFlash {
+    pub fn new(colour: RGB8, flash_time: Duration) -> Self {
+        Self {
+            colour,
+            flash_time,
+            reversals: None,
+            flashed_at: None,
+        }
+    }
+
+    fn track(&mut self, input: &InputState, now: Instant) {
+        let last = self.reversals.replace(input.tt_reversals);
+        if last.is_some_and(|last| last != input.tt_reversals) {
+            self.flashed_at = Some(now);
+        }
+    }
+}
+
+impl Effect for ScratchFlash {
+    fn render(&mut self, leds: &mut [RGB8], input: &InputState, now: Instant) {
+        self.track(input, now);
+
+        SpinningDot {
+            colour: self.colour,
+        }
+        .render(leds, input, now);
+
+        if let Some(flashed_at) = self.flashed_at {
+            let flash = dim(self.colour, fade(now - flashed_at, self.flash_time));
+            for led in leds.iter_mut() {
+                *led = RGB8::new(led.r.max(flash.r), led.g.max(flash.g), led.b.max(flash.b));
+            }
+        }
+    }
+}
+
+/// An effect picked at runtime from the settings.
+pub enum AnyEffect {
+    SpinningDot(SpinningDot),
+    Rainbow(Rainbow),
+    Breathing(Breathing),
+    VelocityTrail(VelocityTrail),
+    ScratchFlash(ScratchFlash),
+}
+
+impl AnyEffect {
+    pub fn new(effect: RingEffect, colour: RGB8) -> Self {
+        match effect {
+            RingEffect::SpinningDot => Self::SpinningDot(SpinningDot { colour }),
+            RingEffect::Rainbow => Self::Rainbow(Rainbow {
+                cycle_time: RAINBOW_CYCLE_TIME,
+            }),
+            RingEffect::Breathing => Self::Breathing(Breathing {
+                colour,
+                period: BREATHING_PERIOD,
+            }),
+            RingEffect::VelocityTrail => Self::VelocityTrail(VelocityTrail {
+                colour,
+                fade_time: TRAIL_FADE_TIME,
+            }),
+            RingEffect::ScratchFlash => Self::ScratchFlash(ScratchFlash::new(colour, FLASH_TIME)),
+        }
+    }
+}
+
+impl Effect for AnyEffect {
+    fn render(&mut self, leds: &mut [RGB8], input: &InputState, now: Instant) {
+        match self {
+            Self::SpinningDot(e) => e.render(leds, input, now),
+            Self::Rainbow(e) => e.render(leds, input, now),
+            Self::Breathing(e) => e.render(leds, input, now),
+            Self::VelocityTrail(e) => e.render(leds, input, now),
+            Self::ScratchFlash(e) => e.render(leds, input, now),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: RGB8 = RGB8::new(255, 0, 0);
+    const OFF: RGB8 = RGB8::new(0, 0, 0);
+
+    fn input(tt_position: i32) -> InputState {
+        InputState {
+            tt_position,
+            counts_per_rotation: 1440,
+            tt_milli_rpm: 0,
+            tt_reversals: 0,
+            buttons: 0,
+        }
+    }
+
+    fn spinning(tt_position: i32, tt_milli_rpm: i32) -> InputState {
+        InputState {
+            tt_milli_rpm,
+            ..input(tt_position)
+        }
+    }
+
+    fn reversed(tt_position: i32, tt_reversals: u32) -> InputState {
+        InputState {
+            tt_reversals,
+            ..input(tt_position)
+        }
+    }
+
+    fn lit(leds: &[RGB8]) -> std::vec::Vec<usize> {
+        (0..leds.len()).filter(|&i| leds[i] != OFF).collect()
+    }
+
+    #[test]
+    fn spinning_dot_follows_platter_both_ways() {
+        let mut dot = SpinningDot { colour: RED };
+        let mut leds = [OFF; 48];
+        let now = Instant::from_secs(1);
+
+        dot.render(&mut leds, &input(0), now);
+        assert_eq!(lit(&leds), [0]);
+
+        dot.render(&mut leds, &input(360), now);
+        assert_eq!(lit(&leds), [12]);
+
+        dot.render(&mut leds, &input(-360), now);
+        assert_eq!(lit(&leds), [36]);
+
+        dot.render(&mut leds, &input(1440 * 3 + 30), now);
+        assert_eq!(lit(&leds), [1]);
+    }
+
+    #[test]
+    fn rainbow_cycles_over_time() {
+        let mut rainbow = Rainbow {
+            cycle_time: Duration::from_millis(1000),
+        };
+        let mut start = [OFF; 4];
+        let mut half = [OFF; 4];
+
+        rainbow.render(&mut start, &input(0), Instant::from_secs(5));
+        rainbow.render(&mut half, &input(0), Instant::from_millis(5500));
+
+        assert_eq!(start[0], RED);
+        assert_ne!(start[0], start[2]);
+        // Half a cycle later the first LED has the colour the third had
+        assert_eq!(half[0], start[2]);
+    }
+
+    #[test]
+    fn breathing_pulses() {
+        let mut breathing = Breathing {
+            colour: RED,
+            period: Duration::from_millis(1000),
+        };
+        let mut leds = [OFF; 3];
+
+        breathing.render(&mut leds, &input(0), Instant::from_secs(1));
+        assert_eq!(leds, [OFF; 3]);
+
+        breathing.render(&mut leds, &input(0), Instant::from_millis(1500));
+        assert!(leds.iter().all(|&led| led.r > 250));
+
+        breathing.render(&mut leds, &input(0), Instant::from_millis(1250));
+        assert!(leds.iter().all(|&led| led.r > 0 && led.r < 128));
+    }
+
+    #[test]
+    fn trail_grows_with_speed() {
+        let mut trail = VelocityTrail {
+            colour: RED,
+            fade_time: Duration::from_millis(300),
+        };
+        let mut leds = [OFF; 48];
+        let now = Instant::from_secs(1);
+
+        // 10 RPM covers 2.4 LEDs in the fade time, 100 RPM 24
+        trail.render(&mut leds, &spinning(360, 10_000), now);
+        assert_eq!(lit(&leds), [10, 11, 12]);
+        trail.render(&mut leds, &spinning(360, 100_000), now);
+        assert_eq!(lit(&leds).len(), 25);
+        // The head is always at full brightness, dimming down the tail
+        assert_eq!(leds[12], RED);
+        assert!(leds[11].r > leds[0].r);
+
+        // However fast it spins, the tail never laps the head
+        trail.render(&mut leds, &spinning(360, 1_000_000), now);
+        assert_eq!(lit(&leds).len(), 48);
+    }
+
+    #[test]
+    fn trail_follows_the_spin_direction() {
+        let mut trail = VelocityTrail {
+            colour: RED,
+            fade_time: Duration::from_millis(300),
+        };
+        let mut leds = [OFF; 48];
+        let now = Instant::from_secs(1);
+
+        trail.render(&mut leds, &spinning(0, -10_000), now);
+        assert_eq!(lit(&leds), [0, 1, 2]);
+
+        trail.render(&mut leds, &spinning(0, 10_000), now);
+        assert_eq!(lit(&leds), [0, 46, 47]);
+    }
+
+    #[test]
+    fn trail_shrinks_to_the_dot_when_stopped() {
+        let mut trail = VelocityTrail {
+            colour: RED,
+            fade_time: Duration::from_millis(300),
+        };
+        let mut leds = [OFF; 48];
+        let now = Instant::from_secs(1);
+
+        trail.render(&mut leds, &spinning(300, 50_000), now);
+        assert!(lit(&leds).len() > 5);
+
+        trail.render(&mut leds, &spinning(300, 0), now);
+        assert_eq!(lit(&leds), [10]);
+    }
+
+    #[test]
+    fn scratch_flash_on_direction_change() {
+        let mut flash = ScratchFlash::new(RED, Duration::from_millis(100));
+        let mut leds = [OFF; 48];
+        let start = Instant::from_secs(1);
+        let at = |ms| start + Duration::from_millis(ms);
+
+        // The count the flash starts from is not a change
+        flash.render(&mut leds, &reversed(0, 7), at(0));
+        flash.render(&mut leds, &reversed(100, 7), at(10));
+        assert_eq!(lit(&leds).len(), 1);
+
+        flash.render(&mut leds, &reversed(50, 8), at(30));
+        assert_eq!(lit(&leds).len(), 48);
+
+        flash.render(&mut leds, &reversed(0, 8), at(200));
+        assert_eq!(lit(&leds).len(), 1);
+
+        // Any change to the count flashes, even several between two frames
+        flash.render(&mut leds, &reversed(0, 1), at(210));
+        assert_eq!(lit(&leds).len(), 48);
+    }
+
+    #[test]
+    fn scratch_flash_survives_the_counter_wrap() {
+        let mut flash = ScratchFlash::new(RED, Duration::from_millis(100));
+        let mut leds = [OFF; 48];
+        let start = Instant::from_secs(1);
+
+        flash.render(&mut leds, &input(0), start);
+        flash.render(
+            &mut leds,
+            &input(i32::MIN),
+            start + Duration::from_millis(10),
+        );
+        assert_eq!(lit(&leds).len(), 1);
+    }
+
+    #[test]
+    fn any_effect_follows_settings() {
+        for &kind in RingEffect::ALL {
+            let mut effect = AnyEffect::new(kind, RED);
+            let mut leds = [OFF; 16];
+            effect.render(&mut leds, &input(0), Instant::from_millis(1500));
+        }
+
+        assert!(matches!(
+            AnyEffect::new(RingEffect::Rainbow, RED),
+            AnyEffect::Rainbow(_)
+        ));
+    }
+}
@@ -0,0 +1,527 @@
+use embassy_time::{Duration, Instant};
+
+use crate::velocity::{Direction, TurntableMotion};
+
+/// Rated pulses per rotation of the stock encoder.
+pub const DEFAULT_ENCODER_PPR: u16 = 360;
+
+/// Quadrature counts per encoder pulse, one for each edge on either channel.
+pub const COUNTS_PER_PULSE: i32 = 4;
+
+/// Quadrature counts per rotation of the stock encoder. Boards with other
+/// encoders set [`Settings::encoder_ppr`](crate::settings::Settings::encoder_ppr).
+pub const PPR: i32 = DEFAULT_ENCODER_PPR as i32 * COUNTS_PER_PULSE;
+pub const TARGET_STEPS: i32 = 144;
+
+/// Fastest spin the encoder is sampled quickly enough for by default.
+pub const DEFAULT_MAX_ROTATIONS_PER_SECOND: u16 = 50;
+
+/// Times the PIO samples the encoder for each count at the fastest spin.
+pub const SAMPLES_PER_COUNT: u32 = 10;
+
+/// Encoders one PIO block can read, one per state machine. The first is
+/// always the turntable.
+pub const MAX_ENCODERS: usize = 4;
+
+/// Report bits of the TT+ and TT- buttons in the digital turntable mode,
+/// buttons 13 and 14 after E1-E4.
+pub const TT_UP_BIT: u16 = 1 << 12;
+pub const TT_DOWN_BIT: u16 = 1 << 13;
+
+pub const DEFAULT_TT_HOLD: Duration = Duration::from_millis(100);
+/// One 8-bit axis step at the default resolution.
+pub const DEFAULT_TT_THRESHOLD: u16 = (PPR / TARGET_STEPS) as u16;
+
+pub const THRESHOLD: i32 = (PPR) / gcd(PPR, TARGET_STEPS);
+pub const ENCODER_STEP: i32 = TARGET_STEPS / gcd(PPR, TARGET_STEPS);
+
+/// Reduces `counts_per_rotation / target_steps` to the
+/// `(threshold, encoder_step)` pair used by [`TurntableScaler`].
+pub const fn ratio(counts_per_rotation: i32, target_steps: i32) -> (i32, i32) {
+    let counts_per_rotation = if counts_per_rotation > 0 {
+        counts_per_rotation
+    } else {
+        1
+    };
+    let divisor = gcd(counts_per_rotation, target_steps);
+    (counts_per_rotation / divisor, target_steps / divisor)
+}
+
+/// PIO sample rate in Hz needed to catch every count at `max_rps` rotations
+/// per second.
+pub fn sample_rate(counts_per_rotation: i32, max_rps: u16) -> u32 {
+    let rate = counts_per_rotation.max(1) as u64 * SAMPLES_PER_COUNT as u64 * max_rps.max(1) as u64;
+    rate.min(u32::MAX as u64) as u32
+}
+
+const fn gcd(n: i32, m: i32) -> i32 {
+    if m == 0 { n } else { gcd(m, n % m) }
+}
+
+/// Resolution of one encoder and the axis steps it reports per rotation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EncoderScaling {
+    /// Rated pulses per rotation.
+    pub ppr: u16,
+    /// Report steps per rotation.
+    pub steps: u16,
+}
+
+impl EncoderScaling {
+    /// Quadrature counts per rotation.
+    pub fn counts_per_rotation(&self) -> i32 {
+        self.ppr as i32 * COUNTS_PER_PULSE
+    }
+
+    /// Quadrature counts per report step, at least one.
+    pub fn counts_per_step(&self) -> i32 {
+        (self.counts_per_rotation() / (self.steps as i32).max(1)).max(1)
+    }
+}
+
+impl Default for EncoderScaling {
+    fn default() -> Self {
+        Self {
+            ppr: DEFAULT_ENCODER_PPR,
+            steps: TARGET_STEPS as u16,
+        }
+    }
+}
+
+/// Where the platter is, in every form the joystick report can use.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TurntablePosition {
+    /// Scaled report steps, wrapping at 16 bits. The 8-bit axis uses the
+    /// low byte.
+    pub steps: u16,
+    /// Quadrature counts, after [`TurntableFilter`](crate::filter::TurntableFilter).
+    pub counts: i32,
+    /// Quadrature counts as read from the encoder, before the filter.
+    pub raw_counts: i32,
+    /// [`TT_UP_BIT`] or [`TT_DOWN_BIT`] while the digital turntable is held.
+    pub digital: u16,
+}
+
+/// Converts raw quadrature counts into the turntable position reported to
+/// the game, scaled to a configurable number of steps per rotation.
+///
+/// All the arithmetic wraps: the PIO's 32-bit counter, the difference
+/// between two readings and the 16-bit position. The position is always the
+/// movement since the last [`Self::set_ratio`] times
+/// `encoder_step / threshold`, rounded down, so however long the platter
+/// spins, turning back to the same count lands on the same step.
+pub struct TurntableScaler {
+    threshold: i32,
+    encoder_step: i32,
+    last_value: i32,
+    /// Progress towards the next step, always in `0..threshold`.
+    rolling_delta: i32,
+    game_reported_value: u16,
+}
+
+impl TurntableScaler {
+    pub const fn new(counts_per_rotation: i32, target_steps: i32) -> Self {
+        let (threshold, encoder_step) = ratio(counts_per_rotation, target_steps);
+        Self {
+            threshold,
+            encoder_step,
+            last_value: 0,
+            rolling_delta: 0,
+            game_reported_value: 0,
+        }
+    }
+
+    /// Changes the encoder resolution or the number of report steps per
+    /// rotation, keeping the current position.
+    pub fn set_ratio(&mut self, counts_per_rotation: i32, target_steps: i32) {
+        (self.threshold, self.encoder_step) = ratio(counts_per_rotation, target_steps);
+        self.rolling_delta = 0;
+    }
+
+    pub fn value(&self) -> u8 {
+        self.game_reported_value as u8
+    }
+
+    /// The last reading along with its scaled position. The scaler only
+    /// sees the counts it is fed, so the caller fills in
+    /// [`TurntablePosition::raw_counts`] if they were filtered.
+    pub fn position(&self) -> TurntablePosition {
+        TurntablePosition {
+            steps: self.game_reported_value,
+            counts: self.last_value,
+            raw_counts: self.last_value,
+            digital: 0,
+        }
+    }
+
+    /// Feeds a new raw encoder reading, returning the position to report.
+    pub fn update(&mut self, new_reading: i32) -> u8 {
+        // Readings a whole 32-bit wrap apart would alias, which at the PIO's
+        // sample rate can't happen between two reads
+        let moved = new_reading.wrapping_sub(self.last_value) as i64;
+        let progress = self.rolling_delta as i64 + moved * self.encoder_step as i64;
+        let threshold = self.threshold as i64;
+
+        let steps = progress.div_euclid(threshold);
+        self.rolling_delta = progress.rem_euclid(threshold) as i32;
+        // Truncating keeps the step count modulo the 16-bit position
+        self.game_reported_value = self.game_reported_value.wrapping_add(steps as u16);
+        self.last_value = new_reading;
+
+        self.value()
+    }
+}
+
+impl Default for TurntableScaler {
+    fn default() -> Self {
+        Self::new(PPR, TARGET_STEPS)
+    }
+}
+
+/// Turns encoder movement into TT+/TT- button presses.
+///
+/// The direction comes from the [`VelocityEstimator`]. It is pressed once the
+/// platter moves `threshold` counts that way from where it rested, and stays
+/// pressed until it has gone `hold` without moving any further that way. A
+/// reversal the estimator reports switches straight to the other direction
+/// instead of waiting out the hold.
+///
+/// [`VelocityEstimator`]: crate::velocity::VelocityEstimator
+pub struct DigitalTurntable {
+    hold: Duration,
+    threshold: i32,
+    /// Rest position, or the furthest reading in the held direction.
+    anchor: Option<i32>,
+    /// Held direction and when it last moved further.
+    spin: Option<(Direction, Instant)>,
+    /// Last [`TurntableMotion::reversals`] seen.
+    reversals: Option<u32>,
+}
+
+impl DigitalTurntable {
+    pub fn new(hold: Duration, threshold: u16) -> Self {
+        Self {
+            hold,
+            threshold: threshold.max(1) as i32,
+            anchor: None,
+            spin: None,
+            reversals: None,
+        }
+    }
+
+    /// Changes the timing, keeping the current state.
+    pub fn set_config(&mut self, hold: Duration, threshold: u16) {
+        self.hold = hold;
+        self.threshold = threshold.max(1) as i32;
+    }
+
+    /// Feeds the estimator's latest motion, returning the button bits to
+    /// report.
+    pub fn update(&mut self, motion: &TurntableMotion, now: Instant) -> u16 {
+        let reading = motion.counts;
+        let anchor = *self.anchor.get_or_insert(reading);
+        let moved = reading.wrapping_sub(anchor);
+        let last_reversals = self.reversals.replace(motion.reversals);
+        let reversed = last_reversals.is_some_and(|last| last != motion.reversals);
+
+        let spin = match (motion.direction, self.spin) {
+            (Some(direction), Some(_)) if reversed => Some(direction),
+            (Some(Direction::Forward), Some((Direction::Forward, _))) if moved > 0 => {
+                Some(Direction::Forward)
+            }
+            (Some(Direction::Backward), Some((Direction::Backward, _))) if moved < 0 => {
+                Some(Direction::Backward)
+            }
+            (Some(Direction::Forward), None) if moved >= self.threshold => Some(Direction::Forward),
+            (Some(Direction::Backward), None) if moved <= -self.threshold => {
+                Some(Direction::Backward)
+            }
+            _ => None,
+        };
+
+        if let Some(spin) = spin {
+            self.anchor = Some(reading);
+            self.spin = Some((spin, now));
+        } else if let Some((_, moved_at)) = self.spin
+            && now >= moved_at + self.hold
+        {
+            self.anchor = Some(reading);
+            self.spin = None;
+        }
+
+        self.bits()
+    }
+
+    pub fn bits(&self) -> u16 {
+        match self.spin {
+            Some((Direction::Forward, _)) => TT_UP_BIT,
+            Some((Direction::Backward, _)) => TT_DOWN_BIT,
+            None => 0,
+        }
+    }
+}
+
+impl Default for DigitalTurntable {
+    fn default() -> Self {
+        Self::new(DEFAULT_TT_HOLD, DEFAULT_TT_THRESHOLD)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::velocity::{VELOCITY_WINDOW, VelocityEstimator};
+    use proptest::prelude::*;
+
+    #[test]
+    fn ratio_is_reduced() {
+        assert_eq!(THRESHOLD, 10);
+        assert_eq!(ENCODER_STEP, 1);
+    }
+
+    #[test]
+    fn runtime_ratio_matches_constants() {
+        assert_eq!(ratio(PPR, TARGET_STEPS), (THRESHOLD, ENCODER_STEP));
+        assert_eq!(ratio(PPR, 256), (45, 8));
+        // 600 and 1000 pulse encoders
+        assert_eq!(ratio(2400, TARGET_STEPS), (50, 3));
+        assert_eq!(ratio(4000, TARGET_STEPS), (250, 9));
+    }
+
+    #[test]
+    fn sample_rate_follows_resolution_and_speed() {
+        assert_eq!(
+            sample_rate(PPR, DEFAULT_MAX_ROTATIONS_PER_SECOND),
+            PPR as u32 * 10 * 50
+        );
+        assert_eq!(sample_rate(4000, 20), 800_000);
+        assert_eq!(sample_rate(u16::MAX as i32 * 4, u16::MAX), u32::MAX);
+    }
+
+    #[test]
+    fn resolution_can_change_at_runtime() {
+        let mut scaler = TurntableScaler::default();
+        scaler.update(PPR / 2);
+        assert_eq!(scaler.position().steps, TARGET_STEPS as u16 / 2);
+
+        // Half a rotation of a 1000 pulse encoder is another half rotation
+        scaler.set_ratio(4000, TARGET_STEPS);
+        scaler.update(PPR / 2 + 2000);
+        assert_eq!(scaler.position().steps, TARGET_STEPS as u16);
+    }
+
+    #[test]
+    fn target_steps_can_change_at_runtime() {
+        let mut scaler = TurntableScaler::default();
+        scaler.set_ratio(PPR, PPR);
+        assert_eq!(scaler.update(2), 2);
+    }
+
+    #[test]
+    fn small_movements_do_not_step() {
+        let mut scaler = TurntableScaler::default();
+        for reading in 0..THRESHOLD {
+            assert_eq!(scaler.update(reading), 0);
+        }
+        assert_eq!(scaler.update(THRESHOLD), 1);
+    }
+
+    #[test]
+    fn backwards_movement_steps_down() {
+        let mut scaler = TurntableScaler::default();
+        assert_eq!(scaler.update(-1), 255);
+    }
+
+    #[test]
+    fn position_wraps_without_panicking() {
+        let mut scaler = TurntableScaler::default();
+        let mut reading = 0;
+        for _ in 0..(PPR * 4) {
+            reading += 1;
+            scaler.update(reading);
+        }
+        // Four full rotations forward
+        assert_eq!(scaler.value(), (4 * TARGET_STEPS) as u8);
+    }
+
+    #[test]
+    fn position_keeps_sixteen_bits_and_raw_counts() {
+        let mut scaler = TurntableScaler::new(PPR, PPR);
+        for reading in 1..=300 {
+            scaler.update(reading);
+        }
+        assert_eq!(scaler.value(), (300 - 256) as u8);
+        assert_eq!(
+            scaler.position(),
+            TurntablePosition {
+                steps: 300,
+                counts: 300,
+                raw_counts: 300,
+                digital: 0,
+            }
+        );
+
+        scaler.update(-1000);
+        assert_eq!(scaler.position().counts, -1000);
+    }
+
+    fn ms(ms: u64) -> Instant {
+        Instant::from_millis(ms)
+    }
+
+    /// A digital turntable fed through a velocity estimator, as the encoder
+    /// task does, both with a threshold of 10 counts.
+    struct Digital {
+        tt: DigitalTurntable,
+        estimator: VelocityEstimator,
+    }
+
+    impl Digital {
+        fn new() -> Self {
+            Self {
+                tt: DigitalTurntable::new(Duration::from_millis(100), 10),
+                estimator: VelocityEstimator::new(PPR, VELOCITY_WINDOW, 10),
+            }
+        }
+
+        fn update(&mut self, reading: i32, now: Instant) -> u16 {
+            let motion = self.estimator.update(reading, now);
+            self.tt.update(&motion, now)
+        }
+    }
+
+    #[test]
+    fn digital_presses_after_threshold_and_holds() {
+        let mut tt = Digital::new();
+        assert_eq!(tt.update(0, ms(0)), 0);
+        assert_eq!(tt.update(9, ms(1)), 0);
+        assert_eq!(tt.update(10, ms(2)), TT_UP_BIT);
+
+        // Every further count in the same direction restarts the hold
+        assert_eq!(tt.update(11, ms(50)), TT_UP_BIT);
+        assert_eq!(tt.update(11, ms(149)), TT_UP_BIT);
+        assert_eq!(tt.update(11, ms(150)), 0);
+    }
+
+    #[test]
+    fn digital_jitter_does_not_extend_hold() {
+        let mut tt = Digital::new();
+        tt.update(0, ms(0));
+        tt.update(-10, ms(1));
+
+        for t in 2..100 {
+            let reading = if t % 2 == 0 { -9 } else { -10 };
+            assert_eq!(tt.update(reading, ms(t)), TT_DOWN_BIT);
+        }
+        assert_eq!(tt.update(-10, ms(101)), 0);
+    }
+
+    #[test]
+    fn digital_reversal_switches_immediately() {
+        let mut tt = Digital::new();
+        tt.update(0, ms(0));
+        assert_eq!(tt.update(30, ms(1)), TT_UP_BIT);
+        assert_eq!(tt.update(21, ms(2)), TT_UP_BIT);
+        assert_eq!(tt.update(20, ms(3)), TT_DOWN_BIT);
+        assert_eq!(tt.update(25, ms(4)), TT_DOWN_BIT);
+    }
+
+    #[test]
+    fn digital_presses_the_estimators_direction() {
+        let mut tt = Digital::new();
+        tt.update(0, ms(0));
+        tt.update(10, ms(1));
+        assert_eq!(tt.update(10, ms(200)), 0);
+
+        // Back past the estimator's threshold from rest presses the other way
+        assert_eq!(tt.update(1, ms(201)), 0);
+        assert_eq!(tt.update(0, ms(202)), TT_DOWN_BIT);
+    }
+
+    /// Position `moved` counts from zero should give, modulo 16 bits.
+    fn expected_steps(moved: i64, target_steps: i32) -> u16 {
+        let (threshold, encoder_step) = ratio(PPR, target_steps);
+        (moved * encoder_step as i64).div_euclid(threshold as i64) as u16
+    }
+
+    proptest! {
+        #[test]
+        fn random_spins_do_not_drift(
+            target_steps in 1..=u16::MAX as i32,
+            start in any::<i32>(),
+            moves in prop::collection::vec(-2000..=2000, 1..2000),
+        ) {
+            let mut scaler = TurntableScaler::new(PPR, target_steps);
+            let mut reading = start;
+            let mut moved = start as i64;
+            scaler.update(reading);
+
+            for step in moves {
+                reading = reading.wrapping_add(step);
+                moved += step as i64;
+                scaler.update(reading);
+                prop_assert_eq!(scaler.position().steps, expected_steps(moved, target_steps));
+            }
+        }
+
+        #[test]
+        fn crossing_the_32_bit_wrap_loses_nothing(
+            start in i32::MAX - 10_000..=i32::MAX,
+            moves in prop::collection::vec(50..=500, 500..1000),
+        ) {
+            let mut scaler = TurntableScaler::default();
+            scaler.update(start);
+            let before = scaler.position().steps;
+
+            let mut reading = start;
+            for &step in &moves {
+                reading = reading.wrapping_add(step);
+                scaler.update(reading);
+            }
+            let forward: i64 = moves.iter().map(|&step| step as i64).sum();
+            prop_assert!(reading < start, "spin did not wrap");
+            prop_assert_eq!(
+                scaler.position().steps,
+                expected_steps(start as i64 + forward, TARGET_STEPS)
+            );
+
+            // And back again the other way lands on the same step
+            for &step in moves.iter().rev() {
+                reading = reading.wrapping_sub(step);
+                scaler.update(reading);
+            }
+            prop_assert_eq!(reading, start);
+            prop_assert_eq!(scaler.position().steps, before);
+        }
+
+        #[test]
+        fn whole_rotations_give_target_steps(
+            target_steps in 1..=u16::MAX as i32,
+            rotations in -20..=20i64,
+            chunks in prop::collection::vec(1..=PPR as i64, 1..50),
+        ) {
+            let mut scaler = TurntableScaler::new(PPR, target_steps);
+            let goal = rotations * PPR as i64;
+            let mut moved = 0;
+            let mut chunks = chunks.iter().cycle();
+
+            while moved != goal {
+                let left = goal - moved;
+                moved += left.signum() * chunks.next().unwrap().min(&left.abs());
+                scaler.update(moved as i32);
+            }
+            prop_assert_eq!(scaler.position().steps, (rotations * target_steps as i64) as u16);
+        }
+    }
+
+    #[test]
+    fn each_threshold_of_counts_is_encoder_step_steps() {
+        let mut scaler = TurntableScaler::default();
+        for n in 1..=2 * TARGET_STEPS {
+            scaler.update(n * THRESHOLD);
+            assert_eq!(scaler.position().steps, (n * ENCODER_STEP) as u16);
+        }
+    }
+}
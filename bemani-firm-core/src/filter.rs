@@ -0,0 +1,332 @@
+//! Turntable input conditioning between the encoder and the reported value.
+//!
+//! Readings go through three stages, each of which can be turned off:
+//!
+//! 1. A deadzone on direction changes, so a worn encoder bouncing a count
+//!    back and forth on a resting platter does not move the axis.
+//! 2. A sensitivity curve scaling movement by a gain that depends on the
+//!    platter's speed, see [`TurntableCurve`].
+//! 3. A low-pass filter smoothing what is left, with a configurable time
+//!    constant.
+
+use embassy_time::{Duration, Instant};
+
+use crate::settings::Settings;
+use crate::velocity::Direction;
+
+/// Points in a sensitivity curve table.
+pub const CURVE_POINTS: usize = 8;
+
+/// Platter speed between two curve points.
+pub const CURVE_STEP_RPM: u32 = 50;
+
+/// Gain leaving movement unchanged, curve gains are in percent.
+pub const UNITY_GAIN: u8 = 100;
+
+const LINEAR_GAINS: [u8; CURVE_POINTS] = [UNITY_GAIN; CURVE_POINTS];
+
+const ACCELERATED_GAINS: [u8; CURVE_POINTS] = [100, 100, 115, 130, 150, 175, 200, 225];
+
+/// Fractional bits of the smoothing filter's state.
+const SMOOTHING_FRAC_BITS: u32 = 16;
+
+/// How platter speed changes the distance the axis moves.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum TurntableCurve {
+    /// Every count moves the axis by one count.
+    #[default]
+    Linear = 0,
+    /// Fast spins move the axis further than slow ones, for big scratches
+    /// without giving up precision in song select.
+    Accelerated = 1,
+    /// Gains from [`Settings::tt_curve_gains`].
+    Custom = 2,
+}
+
+impl TurntableCurve {
+    /// Every curve this firmware supports.
+    pub const ALL: &[TurntableCurve] = &[
+        TurntableCurve::Linear,
+        TurntableCurve::Accelerated,
+        TurntableCurve::Custom,
+    ];
+
+    /// Gain in percent at each curve point, `custom` being the table used
+    /// by [`Self::Custom`].
+    pub fn gains(self, custom: &[u8; CURVE_POINTS]) -> [u8; CURVE_POINTS] {
+        match self {
+            Self::Linear => LINEAR_GAINS,
+            Self::Accelerated => ACCELERATED_GAINS,
+            Self::Custom => *custom,
+        }
+    }
+}
+
+impl TryFrom<u8> for TurntableCurve {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|&curve| curve as u8 == value)
+            .ok_or(value)
+    }
+}
+
+/// Gain in percent at `milli_rpm`, interpolated between the curve points
+/// and held past the last one.
+pub fn curve_gain(gains: &[u8; CURVE_POINTS], milli_rpm: i32) -> u32 {
+    let step = CURVE_STEP_RPM * 1000;
+    let speed = milli_rpm.unsigned_abs();
+    let i = (speed / step) as usize;
+    if i + 1 >= CURVE_POINTS {
+        return gains[CURVE_POINTS - 1] as u32;
+    }
+
+    let (from, to) = (gains[i] as u32, gains[i + 1] as u32);
+    let frac = speed % step;
+    (from * (step - frac) + to * frac) / step
+}
+
+/// Turns raw encoder readings into the counts the turntable reports.
+pub struct TurntableFilter {
+    deadzone: i32,
+    gains: Option<[u8; CURVE_POINTS]>,
+    smoothing: Duration,
+    /// Direction movement is passed through in, once past the deadzone.
+    direction: Option<Direction>,
+    /// Furthest reading in `direction`.
+    turning_point: Option<i32>,
+    /// Hundredths of a count the curve has not output yet.
+    remainder: i64,
+    /// Position after the deadzone and curve.
+    curved: i32,
+    /// How far the smoothed output trails `curved`, in fixed point.
+    lag: i64,
+    last_update: Option<Instant>,
+}
+
+impl TurntableFilter {
+    pub fn new(settings: &Settings) -> Self {
+        let mut filter = Self {
+            deadzone: 0,
+            gains: None,
+            smoothing: Duration::from_ticks(0),
+            direction: None,
+            turning_point: None,
+            remainder: 0,
+            curved: 0,
+            lag: 0,
+            last_update: None,
+        };
+        filter.set_config(settings);
+        filter
+    }
+
+    /// Picks up changed filter settings, keeping the current position.
+    pub fn set_config(&mut self, settings: &Settings) {
+        self.deadzone = settings.tt_deadzone as i32;
+        // The linear curve leaves counts alone, skip the arithmetic
+        self.gains = (settings.tt_curve != TurntableCurve::Linear)
+            .then(|| settings.tt_curve.gains(&settings.tt_curve_gains));
+        self.smoothing = settings.tt_smoothing;
+        if self.smoothing.as_ticks() == 0 {
+            self.lag = 0;
+        }
+    }
+
+    /// Feeds a new raw encoder reading taken at `now` while the platter
+    /// turns at `milli_rpm`, returning the filtered count.
+    pub fn update(&mut self, reading: i32, milli_rpm: i32, now: Instant) -> i32 {
+        let moved = self.pass_deadzone(reading);
+        let moved = self.apply_curve(moved, milli_rpm);
+        self.curved = self.curved.wrapping_add(moved);
+        self.smooth(moved, now)
+    }
+
+    /// Movement since the last reading that makes it through the deadzone.
+    fn pass_deadzone(&mut self, reading: i32) -> i32 {
+        let turning_point = *self.turning_point.get_or_insert(reading);
+        let moved = reading.wrapping_sub(turning_point);
+
+        let passed = match self.direction {
+            Some(Direction::Forward) if moved > 0 => moved,
+            Some(Direction::Backward) if moved < 0 => moved,
+            // Turning back swallows the deadzone before anything moves
+            _ if moved > self.deadzone => {
+                self.direction = Some(Direction::Forward);
+                moved - self.deadzone
+            }
+            _ if moved < -self.deadzone => {
+                self.direction = Some(Direction::Backward);
+                moved + self.deadzone
+            }
+            _ => return 0,
+        };
+        self.turning_point = Some(reading);
+        passed
+    }
+
+    fn apply_curve(&mut self, moved: i32, milli_rpm: i32) -> i32 {
+        let Some(gains) = &self.gains else {
+            return moved;
+        };
+        if moved == 0 {
+            return 0;
+        }
+
+        // Leftovers from the other direction would delay the reversal
+        if (moved > 0) != (self.remainder > 0) {
+            self.remainder = 0;
+        }
+        self.remainder += moved as i64 * curve_gain(gains, milli_rpm) as i64;
+        let out = self.remainder / UNITY_GAIN as i64;
+        self.remainder -= out * UNITY_GAIN as i64;
+        out as i32
+    }
+
+    fn smooth(&mut self, moved: i32, now: Instant) -> i32 {
+        let last_update = self.last_update.replace(now).unwrap_or(now);
+        let tau = self.smoothing.as_micros() as i64;
+        if tau == 0 {
+            return self.curved;
+        }
+
+        self.lag += (moved as i64) << SMOOTHING_FRAC_BITS;
+        let dt = now.saturating_duration_since(last_update).as_micros() as i64;
+        self.lag -= self.lag * dt / (tau + dt);
+
+        let half = 1 << (SMOOTHING_FRAC_BITS - 1);
+        let lag = (self.lag + half) >> SMOOTHING_FRAC_BITS;
+        self.curved.wrapping_sub(lag as i32)
+    }
+}
+
+impl Default for TurntableFilter {
+    fn default() -> Self {
+        Self::new(&Settings::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Instant {
+        Instant::from_millis(ms)
+    }
+
+    fn filter(configure: impl FnOnce(&mut Settings)) -> TurntableFilter {
+        let mut settings = Settings::default();
+        configure(&mut settings);
+        TurntableFilter::new(&settings)
+    }
+
+    #[test]
+    fn defaults_pass_counts_through() {
+        let mut filter = TurntableFilter::default();
+        for (t, reading) in [0, 1, 5, 4, -20, -19].into_iter().enumerate() {
+            assert_eq!(filter.update(reading, 0, ms(t as u64)), reading);
+        }
+    }
+
+    #[test]
+    fn deadzone_hides_jitter_at_rest() {
+        let mut filter = filter(|s| s.tt_deadzone = 1);
+
+        for t in 0..100 {
+            assert_eq!(filter.update(t as i32 % 2, 0, ms(t)), 0);
+        }
+
+        // After moving, bouncing back a count does not move it either
+        assert_eq!(filter.update(10, 0, ms(100)), 9);
+        for t in 101..200 {
+            let reading = if t % 2 == 0 { 10 } else { 9 };
+            assert_eq!(filter.update(reading, 0, ms(t)), 9);
+        }
+    }
+
+    #[test]
+    fn deadzone_is_swallowed_on_reversal() {
+        let mut filter = filter(|s| s.tt_deadzone = 3);
+
+        assert_eq!(filter.update(0, 0, ms(0)), 0);
+        assert_eq!(filter.update(10, 0, ms(1)), 7);
+        assert_eq!(filter.update(7, 0, ms(2)), 7);
+        assert_eq!(filter.update(6, 0, ms(3)), 6);
+        assert_eq!(filter.update(0, 0, ms(4)), 0);
+        // Carrying on the same way follows every count
+        assert_eq!(filter.update(-1, 0, ms(5)), -1);
+    }
+
+    #[test]
+    fn accelerated_curve_only_boosts_fast_spins() {
+        let mut slow = filter(|s| s.tt_curve = TurntableCurve::Accelerated);
+        let mut fast = filter(|s| s.tt_curve = TurntableCurve::Accelerated);
+
+        for t in 0..=100 {
+            slow.update(t, 20_000, ms(t as u64));
+            fast.update(t, 400_000, ms(t as u64));
+        }
+        assert_eq!(slow.update(100, 20_000, ms(100)), 100);
+        assert_eq!(fast.update(100, 400_000, ms(100)), 225);
+    }
+
+    #[test]
+    fn custom_curve_interpolates_and_carries_fractions() {
+        let mut gains = [UNITY_GAIN; CURVE_POINTS];
+        gains[1] = 200;
+        assert_eq!(curve_gain(&gains, 25_000), 150);
+        assert_eq!(curve_gain(&gains, -50_000), 200);
+        assert_eq!(curve_gain(&gains, 1_000_000), 100);
+
+        let mut filter = filter(|s| {
+            s.tt_curve = TurntableCurve::Custom;
+            s.tt_curve_gains = gains;
+        });
+        // One and a half counts per count, so every second count carries
+        assert_eq!(filter.update(0, 25_000, ms(0)), 0);
+        assert_eq!(filter.update(1, 25_000, ms(1)), 1);
+        assert_eq!(filter.update(2, 25_000, ms(2)), 3);
+        assert_eq!(filter.update(3, 25_000, ms(3)), 4);
+        // Turning back drops the half count left over, so going back as far
+        // lands where it started
+        assert_eq!(filter.update(2, -25_000, ms(4)), 3);
+        assert_eq!(filter.update(1, -25_000, ms(5)), 1);
+    }
+
+    #[test]
+    fn smoothing_lags_then_settles() {
+        let mut filter = filter(|s| s.tt_smoothing = Duration::from_millis(10));
+
+        filter.update(0, 0, ms(0));
+        let first = filter.update(100, 0, ms(1));
+        assert!(first > 0 && first < 100, "{first}");
+
+        let mut last = first;
+        for t in 2..100 {
+            let position = filter.update(100, 0, ms(t));
+            assert!(position >= last);
+            last = position;
+        }
+        assert_eq!(last, 100);
+    }
+
+    #[test]
+    fn turning_smoothing_off_catches_up() {
+        let mut settings = Settings {
+            tt_smoothing: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let mut filter = TurntableFilter::new(&settings);
+        filter.update(0, 0, ms(0));
+        assert!(filter.update(100, 0, ms(1)) < 100);
+
+        settings.tt_smoothing = Duration::from_ticks(0);
+        filter.set_config(&settings);
+        assert_eq!(filter.update(100, 0, ms(2)), 100);
+    }
+}
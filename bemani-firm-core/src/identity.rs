@@ -0,0 +1,271 @@
+//! Vendor/product IDs and strings the controller enumerates with.
+//!
+//! Joystick and keyboard mode present as one of the [`UsbProfile`]s. Switch
+//! mode always uses the HORI pad identity, as the console only accepts pads
+//! it recognises.
+
+use crate::codec::{Reader, Writer};
+use crate::settings::{InputMode, Settings, TurntableAxis};
+use crate::switch::{SWITCH_MANUFACTURER, SWITCH_PID, SWITCH_PRODUCT, SWITCH_VID};
+
+/// Longest custom manufacturer or product string.
+pub const MAX_USB_STRING_LEN: usize = 28;
+
+const KONAMI_VID: u16 = 0x1CCF;
+const KONAMI_PID: u16 = 0x8048;
+const KONAMI_MANUFACTURER: &str = "Konami Amusement";
+const KONAMI_PRODUCT: &str = "beatmania IIDX controller premium model";
+const KONAMI_TT_AXIS: TurntableAxis = TurntableAxis::Wrapped8;
+const KONAMI_ENCODERS: u8 = 1;
+
+/// Shown when [`konami_overrides`] holds, so the settings don't look ignored.
+pub const KONAMI_OVERRIDE_NOTE: &str =
+    "the konami USB profile always uses the 8-bit turntable axis and one encoder";
+
+// pid.codes test IDs, free for anyone to use
+const OPEN_VID: u16 = 0x1209;
+const OPEN_PID: u16 = 0x0001;
+const OPEN_MANUFACTURER: &str = "bemani-firm-rs";
+const OPEN_PRODUCT: &str = "IIDX controller";
+
+/// Named USB identity, applied on the next boot.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum UsbProfile {
+    /// Konami premium model, with its single 8-bit turntable axis so games
+    /// that recognise the IDs get the report they expect.
+    #[default]
+    Konami = 0,
+    /// pid.codes test IDs under this firmware's name.
+    OpenSource = 1,
+    /// IDs and strings from [`Settings::custom_identity`].
+    Custom = 2,
+}
+
+impl UsbProfile {
+    /// Every profile this firmware supports.
+    pub const ALL: &[UsbProfile] = &[
+        UsbProfile::Konami,
+        UsbProfile::OpenSource,
+        UsbProfile::Custom,
+    ];
+}
+
+impl TryFrom<u8> for UsbProfile {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|&profile| profile as u8 == value)
+            .ok_or(value)
+    }
+}
+
+/// User-defined identity for [`UsbProfile::Custom`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CustomIdentity {
+    pub vid: u16,
+    pub pid: u16,
+    pub manufacturer: UsbString,
+    pub product: UsbString,
+}
+
+impl Default for CustomIdentity {
+    fn default() -> Self {
+        Self {
+            vid: OPEN_VID,
+            pid: OPEN_PID,
+            manufacturer: UsbString::new(OPEN_MANUFACTURER).unwrap_or_default(),
+            product: UsbString::new(OPEN_PRODUCT).unwrap_or_default(),
+        }
+    }
+}
+
+impl CustomIdentity {
+    pub(crate) fn encode(&self, w: &mut Writer) {
+        w.u16(self.vid);
+        w.u16(self.pid);
+        self.manufacturer.encode(w);
+        self.product.encode(w);
+    }
+
+    /// Reads an identity, giving `Some(None)` if it was present but held a
+    /// string that is not printable ASCII.
+    pub(crate) fn decode(r: &mut Reader) -> Option<Option<Self>> {
+        let vid = r.u16()?;
+        let pid = r.u16()?;
+        let manufacturer = UsbString::decode(r)?;
+        let product = UsbString::decode(r)?;
+
+        Some(Some(Self {
+            vid,
+            pid,
+            manufacturer: manufacturer?,
+            product: product?,
+        }))
+    }
+}
+
+/// What the USB device enumerates as, and the report format that goes with it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UsbIdentity<'a> {
+    pub vid: u16,
+    pub pid: u16,
+    /// `None` leaves the string out.
+    pub manufacturer: Option<&'a str>,
+    pub product: Option<&'a str>,
+    /// Turntable axis of the joystick report.
+    pub tt_axis: TurntableAxis,
+    /// Encoders with an axis in the joystick report.
+    pub encoders: usize,
+}
+
+impl<'a> UsbIdentity<'a> {
+    /// Identity for running as `input_mode`, which can differ from the
+    /// settings when picked at boot.
+    pub fn new(input_mode: InputMode, settings: &'a Settings) -> Self {
+        let (vid, pid, manufacturer, product) = match (input_mode, settings.usb_profile) {
+            (InputMode::Switch, _) => (SWITCH_VID, SWITCH_PID, SWITCH_MANUFACTURER, SWITCH_PRODUCT),
+            (_, UsbProfile::Konami) => {
+                (KONAMI_VID, KONAMI_PID, KONAMI_MANUFACTURER, KONAMI_PRODUCT)
+            }
+            (_, UsbProfile::OpenSource) => (OPEN_VID, OPEN_PID, OPEN_MANUFACTURER, OPEN_PRODUCT),
+            (_, UsbProfile::Custom) => {
+                let custom = &settings.custom_identity;
+                (
+                    custom.vid,
+                    custom.pid,
+                    custom.manufacturer.as_str(),
+                    custom.product.as_str(),
+                )
+            }
+        };
+
+        let (tt_axis, encoders) = match settings.usb_profile {
+            UsbProfile::Konami => (KONAMI_TT_AXIS, KONAMI_ENCODERS as usize),
+            UsbProfile::OpenSource | UsbProfile::Custom => {
+                (settings.tt_axis, settings.encoders as usize)
+            }
+        };
+
+        Self {
+            vid,
+            pid,
+            manufacturer: (!manufacturer.is_empty()).then_some(manufacturer),
+            product: (!product.is_empty()).then_some(product),
+            tt_axis,
+            encoders,
+        }
+    }
+}
+
+/// Whether `settings` ask for a turntable axis or encoder count that the
+/// Konami profile replaces. They are kept, and apply once the profile
+/// changes.
+pub fn konami_overrides(settings: &Settings) -> bool {
+    settings.usb_profile == UsbProfile::Konami
+        && (settings.tt_axis != KONAMI_TT_AXIS || settings.encoders != KONAMI_ENCODERS)
+}
+
+/// A short printable ASCII string, spaces included, stored inline in the
+/// settings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UsbString {
+    len: u8,
+    bytes: [u8; MAX_USB_STRING_LEN],
+}
+
+impl UsbString {
+    pub fn new(s: &str) -> Option<Self> {
+        let mut bytes = [0; MAX_USB_STRING_LEN];
+        bytes.get_mut(..s.len())?.copy_from_slice(s.as_bytes());
+        Self::from_raw(s.len() as u8, bytes)
+    }
+
+    fn from_raw(len: u8, bytes: [u8; MAX_USB_STRING_LEN]) -> Option<Self> {
+        let valid = bytes
+            .get(..len as usize)?
+            .iter()
+            .all(|&b| b == b' ' || b.is_ascii_graphic());
+
+        valid.then_some(Self { len, bytes })
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only ever built from printable ASCII
+        core::str::from_utf8(&self.bytes[..self.len as usize]).unwrap_or_default()
+    }
+
+    fn encode(&self, w: &mut Writer) {
+        w.u8(self.len);
+        w.bytes(&self.bytes);
+    }
+
+    fn decode(r: &mut Reader) -> Option<Option<Self>> {
+        let len = r.u8()?;
+        let bytes = r.bytes()?;
+        Some(Self::from_raw(len, bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_pick_ids_and_axis() {
+        let mut settings = Settings {
+            tt_axis: TurntableAxis::Raw,
+            encoders: 2,
+            ..Default::default()
+        };
+
+        let konami = UsbIdentity::new(InputMode::Joystick, &settings);
+        assert_eq!((konami.vid, konami.pid), (0x1CCF, 0x8048));
+        assert_eq!(konami.tt_axis, TurntableAxis::Wrapped8);
+        assert_eq!(konami.encoders, 1);
+        assert!(konami_overrides(&settings));
+        assert!(!konami_overrides(&Settings::default()));
+
+        settings.usb_profile = UsbProfile::OpenSource;
+        let open = UsbIdentity::new(InputMode::Keyboard, &settings);
+        assert_eq!((open.vid, open.pid), (0x1209, 0x0001));
+        assert_eq!(open.tt_axis, TurntableAxis::Raw);
+        assert_eq!(open.encoders, 2);
+        assert!(!konami_overrides(&settings));
+
+        settings.usb_profile = UsbProfile::Custom;
+        settings.custom_identity = CustomIdentity {
+            vid: 0x1234,
+            pid: 0x5678,
+            manufacturer: UsbString::new("Me").unwrap(),
+            product: UsbString::default(),
+        };
+        let custom = UsbIdentity::new(InputMode::Joystick, &settings);
+        assert_eq!((custom.vid, custom.pid), (0x1234, 0x5678));
+        assert_eq!(custom.manufacturer, Some("Me"));
+        assert_eq!(custom.product, None);
+    }
+
+    #[test]
+    fn switch_mode_ignores_the_profile() {
+        let settings = Settings {
+            usb_profile: UsbProfile::Custom,
+            ..Default::default()
+        };
+        let identity = UsbIdentity::new(InputMode::Switch, &settings);
+
+        assert_eq!((identity.vid, identity.pid), (SWITCH_VID, SWITCH_PID));
+        assert_eq!(identity.product, Some(SWITCH_PRODUCT));
+    }
+
+    #[test]
+    fn usb_string_rejects_bad_strings() {
+        assert!(UsbString::new("beatmania IIDX controller premium model").is_none());
+        assert!(UsbString::new("tab\there").is_none());
+        assert_eq!(UsbString::new("My Pad").unwrap().as_str(), "My Pad");
+    }
+}
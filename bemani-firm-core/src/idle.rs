@@ -0,0 +1,93 @@
+//! When input reports are sent, following the HID idle rate.
+//!
+//! A report goes out as soon as it differs from the last one sent. While it
+//! stays the same it is repeated at the idle rate the host picks with
+//! SET_IDLE, or never if that rate is 0.
+
+use embassy_time::{Duration, Instant};
+
+/// Idle rate a boot keyboard starts with, per the HID specification.
+pub const KEYBOARD_IDLE_MS: u32 = 500;
+
+/// Remembers the last report sent and decides whether the next one goes out.
+pub struct ReportScheduler<const N: usize> {
+    last: [u8; N],
+    len: usize,
+    sent_at: Option<Instant>,
+    idle: Option<Duration>,
+}
+
+impl<const N: usize> ReportScheduler<N> {
+    pub fn new(idle_ms: u32) -> Self {
+        let mut scheduler = Self {
+            last: [0; N],
+            len: 0,
+            sent_at: None,
+            idle: None,
+        };
+        scheduler.set_idle_ms(idle_ms);
+        scheduler
+    }
+
+    /// Takes a SET_IDLE rate, 0 meaning only send on change.
+    pub fn set_idle_ms(&mut self, idle_ms: u32) {
+        self.idle = (idle_ms != 0).then(|| Duration::from_millis(idle_ms as u64));
+    }
+
+    /// Whether `report` should be sent now, recording it as sent if so.
+    pub fn should_send(&mut self, report: &[u8], now: Instant) -> bool {
+        let changed = self.sent_at.is_none() || report != &self.last[..self.len];
+        let idle_expired = self.deadline().is_some_and(|deadline| now >= deadline);
+        if !changed && !idle_expired {
+            return false;
+        }
+
+        let len = report.len().min(N);
+        self.last[..len].copy_from_slice(&report[..len]);
+        self.len = len;
+        self.sent_at = Some(now);
+        true
+    }
+
+    /// When the unchanged report is next due, if ever.
+    pub fn deadline(&self) -> Option<Instant> {
+        Some(self.sent_at? + self.idle?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(ms: u64) -> Instant {
+        Instant::from_millis(ms)
+    }
+
+    #[test]
+    fn sends_only_changes_without_idle_rate() {
+        let mut scheduler = ReportScheduler::<4>::new(0);
+
+        assert!(scheduler.should_send(&[1, 2], at(0)));
+        assert!(!scheduler.should_send(&[1, 2], at(1)));
+        assert!(!scheduler.should_send(&[1, 2], at(10_000)));
+        assert_eq!(scheduler.deadline(), None);
+        assert!(scheduler.should_send(&[1, 3], at(10_001)));
+    }
+
+    #[test]
+    fn repeats_unchanged_reports_at_idle_rate() {
+        let mut scheduler = ReportScheduler::<4>::new(KEYBOARD_IDLE_MS);
+
+        assert!(scheduler.should_send(&[0; 4], at(0)));
+        assert_eq!(scheduler.deadline(), Some(at(500)));
+        assert!(!scheduler.should_send(&[0; 4], at(499)));
+        assert!(scheduler.should_send(&[0; 4], at(500)));
+
+        // A change restarts the idle period
+        assert!(scheduler.should_send(&[1, 0, 0, 0], at(700)));
+        assert_eq!(scheduler.deadline(), Some(at(1200)));
+
+        scheduler.set_idle_ms(8);
+        assert_eq!(scheduler.deadline(), Some(at(708)));
+    }
+}
@@ -0,0 +1,146 @@
+//! NKRO keyboard personality, see [`InputMode::Keyboard`].
+//!
+//! Every key has its own bit in the report, so any number of them can be
+//! held at once. The turntable presses keys through the digital turntable
+//! bits, see [`DigitalTurntable`](crate::encoder::DigitalTurntable).
+//!
+//! [`InputMode::Keyboard`]: crate::settings::InputMode::Keyboard
+
+use crate::button::{NUM_BUTTONS, OUTPUT_INDICES};
+use crate::encoder::{TT_DOWN_BIT, TT_UP_BIT};
+
+/// Inputs that can be bound to keys: the buttons in settings order, then
+/// TT+ and TT-.
+pub const NUM_KEYBOARD_INPUTS: usize = NUM_BUTTONS + 2;
+
+/// Binding for an input that presses nothing.
+pub const NO_KEY: u8 = 0;
+
+const FIRST_MODIFIER: u8 = 0xE0;
+const LAST_MODIFIER: u8 = 0xE7;
+
+/// Keyboard usages covered by the key bitmap, from 0.
+const NUM_KEY_BITS: usize = 128;
+
+/// Modifier byte followed by the key bitmap.
+pub const KEYBOARD_REPORT_LEN: usize = 1 + NUM_KEY_BITS / 8;
+
+/// The usual LR2 and beatoraja layout: Z S X D C F V for the keys, Q W E R
+/// for E1-E4 and left Shift / left Ctrl for the turntable.
+pub const DEFAULT_KEYBOARD_KEYS: [u8; NUM_KEYBOARD_INPUTS] = [
+    0x1D, 0x16, 0x1B, 0x07, 0x06, 0x09, 0x19, // Z S X D C F V
+    0x14, 0x1A, 0x08, 0x15, // Q W E R
+    0xE1, 0xE0, // Left Shift, Left Ctrl
+];
+
+/// Whether `key` is a keyboard usage this report can carry, or [`NO_KEY`].
+pub fn is_valid_key(key: u8) -> bool {
+    // Usages 1-3 are error codes rather than keys
+    key == NO_KEY
+        || (0x04..NUM_KEY_BITS as u8).contains(&key)
+        || (FIRST_MODIFIER..=LAST_MODIFIER).contains(&key)
+}
+
+/// Button bitmask bit for each keyboard input.
+fn input_bit(input: usize) -> u16 {
+    match OUTPUT_INDICES.get(input) {
+        Some(&index) => 1 << index,
+        None if input == NUM_BUTTONS => TT_UP_BIT,
+        None => TT_DOWN_BIT,
+    }
+}
+
+/// Serializes the keyboard report for the button bitmask (including the
+/// digital turntable bits) into `buf`, returning the number of bytes used.
+pub fn serialize_keyboard_report(
+    keys: &[u8; NUM_KEYBOARD_INPUTS],
+    buttons: u16,
+    buf: &mut [u8],
+) -> Option<usize> {
+    let report = buf.get_mut(..KEYBOARD_REPORT_LEN)?;
+    report.fill(0);
+
+    for (input, &key) in keys.iter().enumerate() {
+        if buttons & input_bit(input) == 0 || key == NO_KEY {
+            continue;
+        }
+        if (FIRST_MODIFIER..=LAST_MODIFIER).contains(&key) {
+            report[0] |= 1 << (key - FIRST_MODIFIER);
+        } else if let Some(byte) = report.get_mut(1 + key as usize / 8) {
+            *byte |= 1 << (key % 8);
+        }
+    }
+
+    Some(KEYBOARD_REPORT_LEN)
+}
+
+/// Report descriptor for [`serialize_keyboard_report`]: a bit per modifier,
+/// then a bit per key usage.
+#[rustfmt::skip]
+pub const KEYBOARD_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01,       // Usage Page (Generic Desktop)
+    0x09, 0x06,       // Usage (Keyboard)
+    0xA1, 0x01,       // Collection (Application)
+    0x05, 0x07,       //   Usage Page (Keyboard/Keypad)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x25, 0x01,       //   Logical Maximum (1)
+    0x75, 0x01,       //   Report Size (1)
+    0x19, FIRST_MODIFIER, // Usage Minimum (Left Control)
+    0x29, LAST_MODIFIER,  // Usage Maximum (Right GUI)
+    0x95, 0x08,       //   Report Count (8)
+    0x81, 0x02,       //   Input (Data,Var,Abs)
+    0x19, 0x00,       //   Usage Minimum (0)
+    0x29, NUM_KEY_BITS as u8 - 1, // Usage Maximum
+    0x95, NUM_KEY_BITS as u8,     // Report Count
+    0x81, 0x02,       //   Input (Data,Var,Abs)
+    0xC0,             // End Collection
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(buttons: u16) -> [u8; KEYBOARD_REPORT_LEN] {
+        let mut buf = [0xAA; KEYBOARD_REPORT_LEN];
+        assert_eq!(
+            serialize_keyboard_report(&DEFAULT_KEYBOARD_KEYS, buttons, &mut buf),
+            Some(KEYBOARD_REPORT_LEN)
+        );
+        buf
+    }
+
+    #[test]
+    fn every_held_input_sets_its_key() {
+        // Key 1, key 7, E1 and TT+ all at once
+        let buf = report(1 | 1 << 6 | 1 << 8 | TT_UP_BIT);
+
+        assert_eq!(buf[0], 0b10); // Left Shift
+        assert_eq!(buf[1 + 0x1D / 8], 1 << (0x1D % 8) | 1 << (0x19 % 8)); // Z, V
+        assert_eq!(buf[1 + 0x14 / 8], 1 << (0x14 % 8)); // Q
+        assert_eq!(buf.iter().map(|b| b.count_ones()).sum::<u32>(), 4);
+    }
+
+    #[test]
+    fn nothing_held_is_an_empty_report() {
+        assert_eq!(report(0), [0; KEYBOARD_REPORT_LEN]);
+        assert_eq!(report(TT_DOWN_BIT)[0], 0b1); // Left Ctrl
+    }
+
+    #[test]
+    fn unbound_inputs_press_nothing() {
+        let mut keys = DEFAULT_KEYBOARD_KEYS;
+        keys[0] = NO_KEY;
+        let mut buf = [0; KEYBOARD_REPORT_LEN];
+        serialize_keyboard_report(&keys, 1, &mut buf).unwrap();
+
+        assert_eq!(buf, [0; KEYBOARD_REPORT_LEN]);
+    }
+
+    #[test]
+    fn default_keys_are_valid() {
+        assert!(DEFAULT_KEYBOARD_KEYS.iter().all(|&k| is_valid_key(k)));
+        assert!(!is_valid_key(0x01));
+        assert!(!is_valid_key(0x80));
+        assert!(!is_valid_key(0xE8));
+    }
+}
@@ -0,0 +1,423 @@
+//! Input-to-USB latency statistics.
+//!
+//! Every input change is timed in two stages: debounce, from the first raw
+//! sample disagreeing with a button's state to the debounced change, and
+//! queueing, from the debounced change (or encoder movement) to the host
+//! accepting a report carrying it. Each stage keeps a histogram per
+//! [`InputKind`].
+
+use embassy_time::{Duration, Instant};
+
+use crate::button::{NUM_BUTTONS, OUTPUT_INDICES};
+use crate::codec::{Reader, Writer};
+
+/// Histogram buckets. Bucket 0 holds anything under [`FIRST_BUCKET_US`],
+/// each later one twice the range of the one before, and the last one
+/// everything beyond.
+pub const NUM_BUCKETS: usize = 16;
+
+/// Upper bound of the first bucket, in microseconds.
+pub const FIRST_BUCKET_US: u32 = 16;
+
+pub const NUM_INPUT_KINDS: usize = 3;
+
+pub const NUM_STAGES: usize = 2;
+
+/// Inputs whose latency is tracked separately.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum InputKind {
+    /// Keys 1-7.
+    Keys = 0,
+    /// E1-E4.
+    EButtons = 1,
+    /// Turntable movement, which has no debounce stage.
+    Turntable = 2,
+}
+
+impl InputKind {
+    pub const ALL: &[InputKind] = &[InputKind::Keys, InputKind::EButtons, InputKind::Turntable];
+
+    /// Kind of the button on report bit `output_index`.
+    pub fn of_button(output_index: u8) -> Self {
+        if output_index < 8 {
+            Self::Keys
+        } else {
+            Self::EButtons
+        }
+    }
+}
+
+impl TryFrom<u8> for InputKind {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|&kind| kind as u8 == value)
+            .ok_or(value)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Stage {
+    /// Raw pin change to debounced change.
+    Debounce = 0,
+    /// Debounced change to report accepted by the host.
+    Queue = 1,
+}
+
+/// A value and when it changed, so its latency can be measured downstream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stamped<T> {
+    pub value: T,
+    pub at: Instant,
+}
+
+/// Distribution of one stage's latency, in microseconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    count: u32,
+    sum_us: u64,
+    min_us: u32,
+    max_us: u32,
+    buckets: [u32; NUM_BUCKETS],
+}
+
+impl LatencyHistogram {
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            sum_us: 0,
+            min_us: u32::MAX,
+            max_us: 0,
+            buckets: [0; NUM_BUCKETS],
+        }
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        let us = latency.as_micros().min(u32::MAX as u64) as u32;
+        self.count = self.count.saturating_add(1);
+        self.sum_us = self.sum_us.saturating_add(us as u64);
+        self.min_us = self.min_us.min(us);
+        self.max_us = self.max_us.max(us);
+        self.buckets[bucket(us)] = self.buckets[bucket(us)].saturating_add(1);
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Latency `percent`% of the samples are at or under, rounded up to the
+    /// end of its bucket but never past the largest sample.
+    pub fn percentile_us(&self, percent: u8) -> u32 {
+        if self.count == 0 {
+            return 0;
+        }
+
+        let rank = (self.count as u64 * percent.min(100) as u64)
+            .div_ceil(100)
+            .max(1);
+        let mut seen = 0;
+        for (i, &n) in self.buckets.iter().enumerate() {
+            seen += n as u64;
+            if seen >= rank {
+                return bucket_end_us(i).min(self.max_us);
+            }
+        }
+        self.max_us
+    }
+
+    pub fn summary(&self) -> LatencySummary {
+        if self.count == 0 {
+            return LatencySummary::default();
+        }
+
+        LatencySummary {
+            count: self.count,
+            min_us: self.min_us,
+            mean_us: (self.sum_us / self.count as u64) as u32,
+            max_us: self.max_us,
+            p50_us: self.percentile_us(50),
+            p90_us: self.percentile_us(90),
+            p99_us: self.percentile_us(99),
+        }
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn bucket(us: u32) -> usize {
+    let mut i = 0;
+    while i < NUM_BUCKETS - 1 && us >= bucket_end_us(i) {
+        i += 1;
+    }
+    i
+}
+
+/// Exclusive upper bound of bucket `i`.
+fn bucket_end_us(i: usize) -> u32 {
+    if i == NUM_BUCKETS - 1 {
+        u32::MAX
+    } else {
+        FIRST_BUCKET_US << i
+    }
+}
+
+/// What the host gets for one histogram, all in microseconds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LatencySummary {
+    pub count: u32,
+    pub min_us: u32,
+    pub mean_us: u32,
+    pub max_us: u32,
+    pub p50_us: u32,
+    pub p90_us: u32,
+    pub p99_us: u32,
+}
+
+impl LatencySummary {
+    pub(crate) fn encode(&self, w: &mut Writer) {
+        for value in [
+            self.count,
+            self.min_us,
+            self.mean_us,
+            self.max_us,
+            self.p50_us,
+            self.p90_us,
+            self.p99_us,
+        ] {
+            w.u32(value);
+        }
+    }
+
+    pub(crate) fn decode(r: &mut Reader) -> Option<Self> {
+        Some(Self {
+            count: r.u32()?,
+            min_us: r.u32()?,
+            mean_us: r.u32()?,
+            max_us: r.u32()?,
+            p50_us: r.u32()?,
+            p90_us: r.u32()?,
+            p99_us: r.u32()?,
+        })
+    }
+}
+
+/// Histograms for every input kind and stage.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    histograms: [[LatencyHistogram; NUM_STAGES]; NUM_INPUT_KINDS],
+}
+
+impl LatencyStats {
+    pub const fn new() -> Self {
+        Self {
+            histograms: [[LatencyHistogram::new(); NUM_STAGES]; NUM_INPUT_KINDS],
+        }
+    }
+
+    pub fn record(&mut self, kind: InputKind, stage: Stage, latency: Duration) {
+        self.histograms[kind as usize][stage as usize].record(latency);
+    }
+
+    pub fn histogram(&self, kind: InputKind, stage: Stage) -> &LatencyHistogram {
+        &self.histograms[kind as usize][stage as usize]
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+/// Times the debounce stage of each button.
+#[derive(Default)]
+pub struct DebounceTimer {
+    /// When each button's pin first disagreed with its debounced state.
+    raw_since: [Option<Instant>; NUM_BUTTONS],
+}
+
+impl DebounceTimer {
+    /// Takes the pin samples of one poll, in settings order, and the
+    /// debounced bitmask before and after it.
+    pub fn update(
+        &mut self,
+        pins: &[bool; NUM_BUTTONS],
+        before: u16,
+        after: u16,
+        now: Instant,
+        stats: &mut LatencyStats,
+    ) {
+        for ((since, &pressed), &bit) in self.raw_since.iter_mut().zip(pins).zip(&OUTPUT_INDICES) {
+            let mask = 1 << bit;
+            if (before ^ after) & mask != 0 {
+                let kind = InputKind::of_button(bit);
+                stats.record(kind, Stage::Debounce, now - since.unwrap_or(now));
+                *since = None;
+            } else if pressed != (after & mask != 0) {
+                since.get_or_insert(now);
+            } else {
+                // Bounced back before the debouncer accepted it
+                *since = None;
+            }
+        }
+    }
+}
+
+/// Times the queueing stage: changes waiting for a report to carry them.
+#[derive(Default)]
+pub struct PendingChanges {
+    since: [Option<Instant>; NUM_INPUT_KINDS],
+}
+
+impl PendingChanges {
+    /// Notes a change of `kind`, keeping the earliest one still waiting.
+    pub fn mark(&mut self, kind: InputKind, at: Instant) {
+        self.since[kind as usize].get_or_insert(at);
+    }
+
+    /// Notes the buttons in `changed`, a bitmask of report bits.
+    pub fn mark_buttons(&mut self, changed: u16, at: Instant) {
+        for &bit in &OUTPUT_INDICES {
+            if changed & (1 << bit) != 0 {
+                self.mark(InputKind::of_button(bit), at);
+            }
+        }
+    }
+
+    /// Forgets changes that cancelled out before a report went out.
+    pub fn clear(&mut self) {
+        self.since = Default::default();
+    }
+
+    /// Records the waiting changes as delivered by a report accepted at `now`.
+    pub fn sent(&mut self, now: Instant, stats: &mut LatencyStats) {
+        for (&kind, since) in InputKind::ALL.iter().zip(&mut self.since) {
+            if let Some(at) = since.take() {
+                stats.record(kind, Stage::Queue, now - at);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn us(n: u64) -> Duration {
+        Duration::from_micros(n)
+    }
+
+    #[test]
+    fn histogram_summary() {
+        let mut histogram = LatencyHistogram::new();
+        for n in 1..=100 {
+            histogram.record(us(n * 10));
+        }
+
+        let summary = histogram.summary();
+        assert_eq!(summary.count, 100);
+        assert_eq!((summary.min_us, summary.max_us), (10, 1000));
+        assert_eq!(summary.mean_us, 505);
+        // Rounded up to the bucket ends: 512 and 1024, capped at the max
+        assert_eq!(summary.p50_us, 512);
+        assert_eq!(summary.p90_us, 1000);
+        assert_eq!(LatencyHistogram::new().summary(), LatencySummary::default());
+    }
+
+    #[test]
+    fn huge_latencies_land_in_the_last_bucket() {
+        let mut histogram = LatencyHistogram::new();
+        histogram.record(Duration::from_secs(5000));
+
+        assert_eq!(histogram.buckets[NUM_BUCKETS - 1], 1);
+        assert_eq!(histogram.percentile_us(99), u32::MAX);
+    }
+
+    #[test]
+    fn debounce_timer_measures_raw_to_debounced() {
+        let mut timer = DebounceTimer::default();
+        let mut stats = LatencyStats::new();
+        let mut pins = [false; NUM_BUTTONS];
+        pins[0] = true;
+        pins[8] = true;
+
+        // Key 1 and E2 go down, E2 is accepted straight away
+        timer.update(&pins, 0, 1 << 9, Instant::from_micros(1000), &mut stats);
+        timer.update(
+            &pins,
+            1 << 9,
+            1 << 9,
+            Instant::from_micros(1250),
+            &mut stats,
+        );
+        timer.update(
+            &pins,
+            1 << 9,
+            1 << 9 | 1,
+            Instant::from_micros(5000),
+            &mut stats,
+        );
+
+        let keys = stats.histogram(InputKind::Keys, Stage::Debounce).summary();
+        assert_eq!((keys.count, keys.max_us), (1, 4000));
+        let e = stats
+            .histogram(InputKind::EButtons, Stage::Debounce)
+            .summary();
+        assert_eq!((e.count, e.max_us), (1, 0));
+    }
+
+    #[test]
+    fn bounces_restart_the_debounce_timer() {
+        let mut timer = DebounceTimer::default();
+        let mut stats = LatencyStats::new();
+        let mut pins = [false; NUM_BUTTONS];
+
+        pins[1] = true;
+        timer.update(&pins, 0, 0, Instant::from_micros(0), &mut stats);
+        pins[1] = false;
+        timer.update(&pins, 0, 0, Instant::from_micros(250), &mut stats);
+        pins[1] = true;
+        timer.update(&pins, 0, 0, Instant::from_micros(500), &mut stats);
+        timer.update(&pins, 0, 1 << 1, Instant::from_micros(1500), &mut stats);
+
+        let keys = stats.histogram(InputKind::Keys, Stage::Debounce).summary();
+        assert_eq!(keys.max_us, 1000);
+    }
+
+    #[test]
+    fn pending_changes_measure_queueing() {
+        let mut pending = PendingChanges::default();
+        let mut stats = LatencyStats::new();
+
+        pending.mark_buttons(1 << 2, Instant::from_micros(100));
+        pending.mark_buttons(1 << 2 | 1 << 10, Instant::from_micros(300));
+        pending.mark(InputKind::Turntable, Instant::from_micros(400));
+        pending.sent(Instant::from_micros(1100), &mut stats);
+        // Nothing left waiting
+        pending.sent(Instant::from_micros(9000), &mut stats);
+
+        let queue = |kind| stats.histogram(kind, Stage::Queue).summary();
+        assert_eq!(
+            (queue(InputKind::Keys).count, queue(InputKind::Keys).max_us),
+            (1, 1000)
+        );
+        assert_eq!(queue(InputKind::EButtons).max_us, 800);
+        assert_eq!(queue(InputKind::Turntable).max_us, 700);
+
+        pending.mark(InputKind::Keys, Instant::from_micros(9000));
+        pending.clear();
+        pending.sent(Instant::from_micros(9500), &mut stats);
+        let keys = stats.histogram(InputKind::Keys, Stage::Queue);
+        assert_eq!(keys.count(), 1);
+    }
+}
@@ -0,0 +1,31 @@
+//! Hardware-independent logic for the bemani-firm-rs controller firmware.
+//!
+//! Everything in here is plain `no_std` code with no knowledge of the RP2040
+//! peripherals, so it can be unit tested on the host with `cargo test`. The
+//! firmware crate feeds it pin levels, encoder counts and colours and takes
+//! back debounced buttons, report values and PIO words.
+#![no_std]
+
+#[cfg(test)]
+extern crate std;
+
+pub mod boot;
+pub mod button;
+mod codec;
+pub mod debounce;
+pub mod effect;
+pub mod encoder;
+pub mod filter;
+pub mod identity;
+pub mod idle;
+pub mod keyboard;
+pub mod latency;
+pub mod lights;
+pub mod protocol;
+pub mod report;
+pub mod rgb;
+pub mod settings;
+pub mod shell;
+pub mod storage;
+pub mod switch;
+pub mod velocity;
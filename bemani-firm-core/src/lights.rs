@@ -0,0 +1,217 @@
+//! Button and turntable light state.
+//!
+//! Games and IO hooks send one level per light through the joystick's output
+//! report. While they keep doing so the host's levels replace the local
+//! lighting; once they stop for [`HOST_LIGHTS_TIMEOUT`] the controller goes
+//! back to its own [`LedMode`](crate::settings::LedMode).
+//!
+//! Whatever decides the levels, each physical LED shows the colour of the
+//! button it is mapped to in [`Settings::button_leds`].
+
+use embassy_time::{Duration, Instant};
+use smart_leds::RGB8;
+
+use crate::button::{NUM_BUTTONS, OUTPUT_INDICES};
+use crate::settings::Settings;
+
+/// Lights in the output report: one per button in settings order, then the
+/// turntable ring's red, green and blue.
+pub const NUM_LIGHTS: usize = NUM_BUTTONS + 3;
+
+/// How long the host's lights are kept after its last report.
+pub const HOST_LIGHTS_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HostLights {
+    /// Level of each button's light, 0 is off.
+    pub buttons: [u8; NUM_BUTTONS],
+    pub turntable: RGB8,
+}
+
+impl HostLights {
+    /// Parses the output report, which has no report ID.
+    pub fn from_report(report: &[u8]) -> Option<Self> {
+        let report: &[u8; NUM_LIGHTS] = report.first_chunk()?;
+        let (buttons, turntable) = report.split_first_chunk::<NUM_BUTTONS>()?;
+
+        Some(Self {
+            buttons: *buttons,
+            turntable: RGB8::new(turntable[0], turntable[1], turntable[2]),
+        })
+    }
+}
+
+/// Tracks whether the host is currently driving the lights.
+#[derive(Default)]
+pub struct HostLightsState {
+    last: Option<(HostLights, Instant)>,
+}
+
+impl HostLightsState {
+    pub fn receive(&mut self, lights: HostLights, now: Instant) {
+        self.last = Some((lights, now));
+    }
+
+    /// The host's lights, or `None` if it has not sent any recently.
+    pub fn active(&self, now: Instant) -> Option<&HostLights> {
+        self.last
+            .as_ref()
+            .filter(|(_, received)| now - *received < HOST_LIGHTS_TIMEOUT)
+            .map(|(lights, _)| lights)
+    }
+}
+
+/// Fade state for the reactive LED mode, fed with the debounced buttons.
+#[derive(Default)]
+pub struct ReactiveLights {
+    pressed: [bool; NUM_BUTTONS],
+    released_at: [Option<Instant>; NUM_BUTTONS],
+}
+
+impl ReactiveLights {
+    /// Takes the button bitmask produced by the button task.
+    pub fn update(&mut self, buttons: u16, now: Instant) {
+        for (i, &bit) in OUTPUT_INDICES.iter().enumerate() {
+            let pressed = buttons & (1 << bit) != 0;
+            if self.pressed[i] && !pressed {
+                self.released_at[i] = Some(now);
+            }
+            self.pressed[i] = pressed;
+        }
+    }
+
+    /// Light level of `button`, fading linearly to 0 over `fade` after release.
+    pub fn level(&self, button: usize, fade: Duration, now: Instant) -> u8 {
+        if self.pressed[button] {
+            return u8::MAX;
+        }
+
+        let Some(released_at) = self.released_at[button] else {
+            return 0;
+        };
+        let elapsed = (now - released_at).as_ticks();
+        let fade = fade.as_ticks();
+        if elapsed >= fade {
+            return 0;
+        }
+
+        (u8::MAX as u64 * (fade - elapsed) / fade) as u8
+    }
+}
+
+/// Fills `leds` with the colour of the button each one is mapped to, dimmed
+/// to `level(button)`.
+pub fn render_button_leds(
+    settings: &Settings,
+    leds: &mut [RGB8],
+    mut level: impl FnMut(usize) -> u8,
+) {
+    for (led, &button) in leds.iter_mut().zip(&settings.button_leds) {
+        let button = button as usize;
+        *led = match settings.key_colours.get(button) {
+            Some(&colour) => dim(colour, level(button)),
+            None => RGB8::default(),
+        };
+    }
+}
+
+/// Scales `colour` by `level` out of 255.
+pub fn dim(colour: RGB8, level: u8) -> RGB8 {
+    let scale = |c: u8| (c as u16 * level as u16 / 255) as u8;
+    RGB8::new(scale(colour.r), scale(colour.g), scale(colour.b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_report() {
+        let mut report = [0u8; NUM_LIGHTS];
+        report[0] = 255;
+        report[10] = 7;
+        report[NUM_BUTTONS..].copy_from_slice(&[1, 2, 3]);
+        let lights = HostLights::from_report(&report).unwrap();
+
+        assert_eq!(lights.buttons[0], 255);
+        assert_eq!(lights.buttons[10], 7);
+        assert_eq!(lights.turntable, RGB8::new(1, 2, 3));
+        assert_eq!(HostLights::from_report(&report[..NUM_LIGHTS - 1]), None);
+    }
+
+    #[test]
+    fn host_lights_time_out() {
+        let mut state = HostLightsState::default();
+        let start = Instant::from_secs(10);
+        assert!(state.active(start).is_none());
+
+        state.receive(HostLights::default(), start);
+        assert!(state.active(start + Duration::from_millis(1999)).is_some());
+        assert!(state.active(start + HOST_LIGHTS_TIMEOUT).is_none());
+    }
+
+    #[test]
+    fn reactive_lights_fade_after_release() {
+        let fade = Duration::from_millis(100);
+        let mut lights = ReactiveLights::default();
+        let start = Instant::from_secs(1);
+        assert_eq!(lights.level(0, fade, start), 0);
+
+        // Key 1 is bit 0, E1 is bit 8
+        lights.update(0b1_0000_0001, start);
+        assert_eq!(lights.level(0, fade, start), 255);
+        assert_eq!(lights.level(7, fade, start), 255);
+        assert_eq!(lights.level(1, fade, start), 0);
+
+        lights.update(0b1_0000_0000, start + Duration::from_millis(10));
+        assert_eq!(
+            lights.level(0, fade, start + Duration::from_millis(60)),
+            127
+        );
+        assert_eq!(lights.level(0, fade, start + Duration::from_millis(110)), 0);
+        assert_eq!(
+            lights.level(7, fade, start + Duration::from_millis(110)),
+            255
+        );
+    }
+
+    #[test]
+    fn zero_fade_turns_off_immediately() {
+        let mut lights = ReactiveLights::default();
+        lights.update(1, Instant::from_secs(1));
+        lights.update(0, Instant::from_secs(1));
+
+        assert_eq!(
+            lights.level(0, Duration::from_ticks(0), Instant::from_secs(1)),
+            0
+        );
+    }
+
+    #[test]
+    fn leds_follow_their_mapped_button() {
+        let mut settings = Settings::default();
+        settings.key_colours[4] = RGB8::new(200, 100, 0);
+        settings.button_leds[..3].copy_from_slice(&[4, crate::settings::NO_BUTTON, 0]);
+        let mut leds = [RGB8::new(1, 1, 1); 3];
+
+        render_button_leds(
+            &settings,
+            &mut leds,
+            |button| if button == 4 { 255 } else { 0 },
+        );
+
+        assert_eq!(
+            leds,
+            [RGB8::new(200, 100, 0), RGB8::default(), RGB8::default()]
+        );
+    }
+
+    #[test]
+    fn dim_scales_each_channel() {
+        let colour = RGB8::new(255, 128, 0);
+
+        assert_eq!(dim(colour, 255), colour);
+        assert_eq!(dim(colour, 0), RGB8::default());
+        assert_eq!(dim(colour, 128), RGB8::new(128, 64, 0));
+    }
+}
@@ -0,0 +1,849 @@
+//! Feature reports used by host tools to read and change the settings.
+//!
+//! The reports live on their own vendor-defined HID interface so the
+//! joystick's input report stays exactly what the games expect. Every report
+//! is [`REPORT_LEN`] bytes: the report ID followed by a zero padded payload
+//! laid out like the settings record (little-endian, fixed order).
+//!
+//! The same functions serve both ends. The firmware answers GET_REPORT with
+//! [`get_feature`] and SET_REPORT with [`set_feature`]; a host tool builds the
+//! reports it sends with [`get_feature`] on its own copy of the settings and
+//! reads the ones it receives back with [`set_feature`].
+
+use embassy_time::Duration;
+
+use crate::button::NUM_BUTTONS;
+use crate::codec::{Reader, Writer};
+use crate::debounce::DebounceAlgorithm;
+use crate::encoder::{EncoderScaling, MAX_ENCODERS};
+use crate::filter::TurntableCurve;
+use crate::identity::{CustomIdentity, UsbProfile};
+use crate::keyboard::{NUM_KEYBOARD_INPUTS, is_valid_key};
+use crate::latency::{InputKind, LatencyStats, LatencySummary, Stage};
+use crate::settings::{
+    InputMode, LedMode, MAX_BUTTON_LEDS, RingEffect, SETTINGS_VERSION, SerialNumber, Settings,
+    TurntableAxis, duration_to_ms, is_valid_led_button, read_colour, read_debounce, write_colour,
+    write_debounce,
+};
+use crate::switch::SwitchTurntable;
+
+/// Version of the report layouts below, bumped on any incompatible change.
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// Bytes following the report ID in every feature report.
+pub const PAYLOAD_LEN: usize = 63;
+
+/// Size of a feature report including its ID.
+pub const REPORT_LEN: usize = PAYLOAD_LEN + 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum FeatureReport {
+    /// Read-only protocol version and capabilities, see [`Info`].
+    Info = 1,
+    /// Per button algorithm, press and release time in microseconds.
+    Debounce = 2,
+    /// Turntable report steps per platter rotation.
+    Turntable = 3,
+    /// LED mode followed by each key's colour.
+    Lighting = 4,
+    /// USB personality used from the next boot.
+    InputMode = 5,
+    /// Write-only, runs a [`Command`].
+    Command = 6,
+    /// Reactive fade time in milliseconds, then the button each LED follows.
+    ButtonLeds = 7,
+    /// Turntable ring effect followed by its colour.
+    RingLighting = 8,
+    /// Turntable axis format used from the next boot. Stored as is under
+    /// the Konami profile, which replaces it, see [`konami_overrides`].
+    ///
+    /// [`konami_overrides`]: crate::identity::konami_overrides
+    TurntableAxis = 9,
+    /// Digital turntable hold time in milliseconds, then the movement
+    /// threshold in encoder counts.
+    DigitalTurntable = 10,
+    /// Keyboard usage bound to each input in keyboard mode.
+    KeyboardKeys = 11,
+    /// What the turntable moves in Switch mode.
+    SwitchTurntable = 12,
+    /// USB serial number override used from the next boot: its length, then
+    /// the padded string. Empty uses the flash chip's unique ID.
+    SerialNumber = 13,
+    /// USB profile used from the next boot, then the custom identity: vendor
+    /// and product ID, manufacturer and product strings as length and padded
+    /// string.
+    UsbIdentity = 14,
+    /// Read-only [`LatencySummary`] of the keys' debounce stage, then of
+    /// their queueing stage, see [`latency_report`].
+    KeyLatency = 15,
+    /// Read-only latency of the E buttons, laid out like [`Self::KeyLatency`].
+    EButtonLatency = 16,
+    /// Read-only latency of the turntable, laid out like
+    /// [`Self::KeyLatency`]. The debounce stage is always empty.
+    TurntableLatency = 17,
+    /// Turntable deadzone in encoder counts, smoothing time constant in
+    /// milliseconds, [`TurntableCurve`] and the custom curve's gains.
+    TurntableFilter = 18,
+    /// Encoder pulses per rotation, then the fastest spin it has to follow in
+    /// rotations per second.
+    Encoder = 19,
+    /// Number of encoders used from the next boot, then the pulses per
+    /// rotation and report steps of each encoder after the turntable. The
+    /// count is stored like [`Self::TurntableAxis`] under the Konami profile.
+    Encoders = 20,
+}
+
+impl FeatureReport {
+    pub const ALL: &[FeatureReport] = &[
+        FeatureReport::Info,
+        FeatureReport::Debounce,
+        FeatureReport::Turntable,
+        FeatureReport::Lighting,
+        FeatureReport::InputMode,
+        FeatureReport::Command,
+        FeatureReport::ButtonLeds,
+        FeatureReport::RingLighting,
+        FeatureReport::TurntableAxis,
+        FeatureReport::DigitalTurntable,
+        FeatureReport::KeyboardKeys,
+        FeatureReport::SwitchTurntable,
+        FeatureReport::SerialNumber,
+        FeatureReport::UsbIdentity,
+        FeatureReport::KeyLatency,
+        FeatureReport::EButtonLatency,
+        FeatureReport::TurntableLatency,
+        FeatureReport::TurntableFilter,
+        FeatureReport::Encoder,
+        FeatureReport::Encoders,
+    ];
+}
+
+impl FeatureReport {
+    /// Report carrying the latency of `kind`.
+    pub fn latency(kind: InputKind) -> Self {
+        match kind {
+            InputKind::Keys => Self::KeyLatency,
+            InputKind::EButtons => Self::EButtonLatency,
+            InputKind::Turntable => Self::TurntableLatency,
+        }
+    }
+}
+
+impl TryFrom<u8> for FeatureReport {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|&report| report as u8 == value)
+            .ok_or(value)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Command {
+    /// Write the current settings to flash.
+    Save = 1,
+    /// Replace the current settings with the defaults, without saving.
+    LoadDefaults = 2,
+    /// Clear the latency histograms.
+    ResetLatency = 3,
+}
+
+impl Command {
+    pub const ALL: &[Command] = &[Command::Save, Command::LoadDefaults, Command::ResetLatency];
+}
+
+impl TryFrom<u8> for Command {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|&command| command as u8 == value)
+            .ok_or(value)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ProtocolError {
+    UnknownReport(u8),
+    /// The report can only be read.
+    ReadOnly,
+    /// The report ID byte did not match the report being handled.
+    IdMismatch,
+    Truncated,
+    /// A field held a value this firmware does not accept.
+    InvalidValue,
+}
+
+/// Contents of the [`FeatureReport::Info`] report.
+///
+/// Each mask has bit `n` set if the value `n` is supported, so a tool can
+/// grey out options the connected firmware does not know about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Info {
+    pub protocol_version: u8,
+    pub settings_version: u16,
+    pub num_buttons: u8,
+    /// Supported [`FeatureReport`] IDs.
+    pub reports: u32,
+    /// Supported [`Command`]s.
+    pub commands: u32,
+    /// Supported [`DebounceAlgorithm`]s.
+    pub debounce_algorithms: u32,
+    /// Supported [`LedMode`]s.
+    pub led_modes: u32,
+    /// Supported [`InputMode`]s.
+    pub input_modes: u32,
+    /// Supported [`RingEffect`]s.
+    pub ring_effects: u32,
+    /// Supported [`TurntableAxis`] formats.
+    pub turntable_axes: u32,
+    /// Supported [`SwitchTurntable`] options.
+    pub switch_turntables: u32,
+    /// Supported [`UsbProfile`]s.
+    pub usb_profiles: u32,
+    /// Supported [`TurntableCurve`]s.
+    pub turntable_curves: u32,
+}
+
+impl Info {
+    /// Describes this firmware.
+    pub fn current() -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            settings_version: SETTINGS_VERSION,
+            num_buttons: NUM_BUTTONS as u8,
+            reports: mask(FeatureReport::ALL.iter().map(|&r| r as u8)),
+            commands: mask(Command::ALL.iter().map(|&c| c as u8)),
+            debounce_algorithms: mask(DebounceAlgorithm::ALL.iter().map(|&a| a as u8)),
+            led_modes: mask(LedMode::ALL.iter().map(|&m| m as u8)),
+            input_modes: mask(InputMode::ALL.iter().map(|&m| m as u8)),
+            ring_effects: mask(RingEffect::ALL.iter().map(|&e| e as u8)),
+            turntable_axes: mask(TurntableAxis::ALL.iter().map(|&a| a as u8)),
+            switch_turntables: mask(SwitchTurntable::ALL.iter().map(|&t| t as u8)),
+            usb_profiles: mask(UsbProfile::ALL.iter().map(|&p| p as u8)),
+            turntable_curves: mask(TurntableCurve::ALL.iter().map(|&c| c as u8)),
+        }
+    }
+
+    /// Parses an info report, including its ID byte.
+    pub fn decode(report: &[u8]) -> Result<Self, ProtocolError> {
+        let mut r = payload(FeatureReport::Info as u8, report)?;
+        let mut read = || -> Option<Self> {
+            Some(Self {
+                protocol_version: r.u8()?,
+                settings_version: r.u16()?,
+                num_buttons: r.u8()?,
+                reports: r.u32()?,
+                commands: r.u32()?,
+                debounce_algorithms: r.u32()?,
+                led_modes: r.u32()?,
+                input_modes: r.u32()?,
+                // Zero padding from firmware that predates these
+                ring_effects: r.u32()?,
+                turntable_axes: r.u32()?,
+                switch_turntables: r.u32()?,
+                usb_profiles: r.u32()?,
+                turntable_curves: r.u32()?,
+            })
+        };
+        read().ok_or(ProtocolError::Truncated)
+    }
+
+    fn encode(&self, w: &mut Writer) {
+        w.u8(self.protocol_version);
+        w.u16(self.settings_version);
+        w.u8(self.num_buttons);
+        w.u32(self.reports);
+        w.u32(self.commands);
+        w.u32(self.debounce_algorithms);
+        w.u32(self.led_modes);
+        w.u32(self.input_modes);
+        w.u32(self.ring_effects);
+        w.u32(self.turntable_axes);
+        w.u32(self.switch_turntables);
+        w.u32(self.usb_profiles);
+        w.u32(self.turntable_curves);
+    }
+
+    pub fn supports_report(&self, report: FeatureReport) -> bool {
+        self.reports & (1 << report as u8) != 0
+    }
+}
+
+fn mask(values: impl Iterator<Item = u8>) -> u32 {
+    values.fold(0, |mask, value| mask | (1 << value))
+}
+
+/// Writes the feature report `id` for `settings` into `buf`, returning its
+/// length, or `None` if there is no such readable report.
+pub fn get_feature(id: u8, settings: &Settings, buf: &mut [u8]) -> Option<usize> {
+    let report = FeatureReport::try_from(id).ok()?;
+    let buf = buf.get_mut(..REPORT_LEN)?;
+    buf.fill(0);
+
+    let mut w = Writer::new(buf);
+    w.u8(id);
+    match report {
+        FeatureReport::Info => Info::current().encode(&mut w),
+        FeatureReport::Debounce => {
+            for config in &settings.debounce {
+                write_debounce(&mut w, config);
+            }
+        }
+        FeatureReport::Turntable => w.u16(settings.tt_steps),
+        FeatureReport::Lighting => {
+            w.u8(settings.led_mode as u8);
+            for colour in &settings.key_colours {
+                write_colour(&mut w, colour);
+            }
+        }
+        FeatureReport::InputMode => w.u8(settings.input_mode as u8),
+        FeatureReport::Command
+        | FeatureReport::KeyLatency
+        | FeatureReport::EButtonLatency
+        | FeatureReport::TurntableLatency => return None,
+        FeatureReport::ButtonLeds => {
+            w.u16(duration_to_ms(settings.reactive_fade));
+            w.bytes(&settings.button_leds);
+        }
+        FeatureReport::RingLighting => {
+            w.u8(settings.ring_effect as u8);
+            write_colour(&mut w, &settings.ring_colour);
+        }
+        FeatureReport::TurntableAxis => w.u8(settings.tt_axis as u8),
+        FeatureReport::DigitalTurntable => {
+            w.u16(duration_to_ms(settings.tt_hold));
+            w.u16(settings.tt_threshold);
+        }
+        FeatureReport::KeyboardKeys => w.bytes(&settings.keyboard_keys),
+        FeatureReport::SwitchTurntable => w.u8(settings.switch_tt as u8),
+        FeatureReport::SerialNumber => {
+            let (len, bytes) = settings.serial_number.raw();
+            w.u8(len);
+            w.bytes(bytes);
+        }
+        FeatureReport::UsbIdentity => {
+            w.u8(settings.usb_profile as u8);
+            settings.custom_identity.encode(&mut w);
+        }
+        FeatureReport::TurntableFilter => {
+            w.u16(settings.tt_deadzone);
+            w.u16(duration_to_ms(settings.tt_smoothing));
+            w.u8(settings.tt_curve as u8);
+            w.bytes(&settings.tt_curve_gains);
+        }
+        FeatureReport::Encoder => {
+            w.u16(settings.encoder_ppr);
+            w.u16(settings.tt_max_rps);
+        }
+        FeatureReport::Encoders => {
+            w.u8(settings.encoders);
+            for scaling in &settings.extra_encoders {
+                w.u16(scaling.ppr);
+                w.u16(scaling.steps);
+            }
+        }
+    }
+
+    w.finish().map(|_| REPORT_LEN)
+}
+
+/// Applies the feature report `id` to `settings`.
+///
+/// `report` starts with the report ID, as sent over the wire. Nothing is
+/// changed unless the whole report is valid. Commands that only touch the
+/// settings are applied here; the command is returned so the caller can
+/// carry out the rest (e.g. actually writing flash for [`Command::Save`]).
+pub fn set_feature(
+    id: u8,
+    report: &[u8],
+    settings: &mut Settings,
+) -> Result<Option<Command>, ProtocolError> {
+    let kind = FeatureReport::try_from(id).map_err(ProtocolError::UnknownReport)?;
+    let mut r = payload(id, report)?;
+    let mut updated = settings.clone();
+
+    let command = match kind {
+        FeatureReport::Info
+        | FeatureReport::KeyLatency
+        | FeatureReport::EButtonLatency
+        | FeatureReport::TurntableLatency => return Err(ProtocolError::ReadOnly),
+        FeatureReport::Debounce => {
+            for config in &mut updated.debounce {
+                *config = read_debounce(&mut r)
+                    .ok_or(ProtocolError::Truncated)?
+                    .map_err(|_| ProtocolError::InvalidValue)?;
+            }
+            None
+        }
+        FeatureReport::Turntable => {
+            let steps = r.u16().ok_or(ProtocolError::Truncated)?;
+            if steps == 0 {
+                return Err(ProtocolError::InvalidValue);
+            }
+            updated.tt_steps = steps;
+            None
+        }
+        FeatureReport::Lighting => {
+            let mode = r.u8().ok_or(ProtocolError::Truncated)?;
+            updated.led_mode = LedMode::try_from(mode).map_err(|_| ProtocolError::InvalidValue)?;
+            for colour in &mut updated.key_colours {
+                *colour = read_colour(&mut r).ok_or(ProtocolError::Truncated)?;
+            }
+            None
+        }
+        FeatureReport::InputMode => {
+            let mode = r.u8().ok_or(ProtocolError::Truncated)?;
+            updated.input_mode =
+                InputMode::try_from(mode).map_err(|_| ProtocolError::InvalidValue)?;
+            None
+        }
+        FeatureReport::Command => {
+            let command = r.u8().ok_or(ProtocolError::Truncated)?;
+            let command = Command::try_from(command).map_err(|_| ProtocolError::InvalidValue)?;
+            if command == Command::LoadDefaults {
+                updated = Settings::default();
+            }
+            Some(command)
+        }
+        FeatureReport::ButtonLeds => {
+            let fade = r.u16().ok_or(ProtocolError::Truncated)?;
+            updated.reactive_fade = Duration::from_millis(fade as u64);
+            let leds: [u8; MAX_BUTTON_LEDS] = r.bytes().ok_or(ProtocolError::Truncated)?;
+            if !leds.iter().all(|&button| is_valid_led_button(button)) {
+                return Err(ProtocolError::InvalidValue);
+            }
+            updated.button_leds = leds;
+            None
+        }
+        FeatureReport::RingLighting => {
+            let effect = r.u8().ok_or(ProtocolError::Truncated)?;
+            updated.ring_effect =
+                RingEffect::try_from(effect).map_err(|_| ProtocolError::InvalidValue)?;
+            updated.ring_colour = read_colour(&mut r).ok_or(ProtocolError::Truncated)?;
+            None
+        }
+        FeatureReport::TurntableAxis => {
+            let axis = r.u8().ok_or(ProtocolError::Truncated)?;
+            updated.tt_axis =
+                TurntableAxis::try_from(axis).map_err(|_| ProtocolError::InvalidValue)?;
+            None
+        }
+        FeatureReport::DigitalTurntable => {
+            let hold = r.u16().ok_or(ProtocolError::Truncated)?;
+            let threshold = r.u16().ok_or(ProtocolError::Truncated)?;
+            if threshold == 0 {
+                return Err(ProtocolError::InvalidValue);
+            }
+            updated.tt_hold = Duration::from_millis(hold as u64);
+            updated.tt_threshold = threshold;
+            None
+        }
+        FeatureReport::KeyboardKeys => {
+            let keys: [u8; NUM_KEYBOARD_INPUTS] = r.bytes().ok_or(ProtocolError::Truncated)?;
+            if !keys.iter().all(|&key| is_valid_key(key)) {
+                return Err(ProtocolError::InvalidValue);
+            }
+            updated.keyboard_keys = keys;
+            None
+        }
+        FeatureReport::SwitchTurntable => {
+            let tt = r.u8().ok_or(ProtocolError::Truncated)?;
+            updated.switch_tt =
+                SwitchTurntable::try_from(tt).map_err(|_| ProtocolError::InvalidValue)?;
+            None
+        }
+        FeatureReport::SerialNumber => {
+            let len = r.u8().ok_or(ProtocolError::Truncated)?;
+            let bytes = r.bytes().ok_or(ProtocolError::Truncated)?;
+            updated.serial_number =
+                SerialNumber::from_raw(len, bytes).ok_or(ProtocolError::InvalidValue)?;
+            None
+        }
+        FeatureReport::UsbIdentity => {
+            let profile = r.u8().ok_or(ProtocolError::Truncated)?;
+            updated.usb_profile =
+                UsbProfile::try_from(profile).map_err(|_| ProtocolError::InvalidValue)?;
+            updated.custom_identity = CustomIdentity::decode(&mut r)
+                .ok_or(ProtocolError::Truncated)?
+                .ok_or(ProtocolError::InvalidValue)?;
+            None
+        }
+        FeatureReport::TurntableFilter => {
+            updated.tt_deadzone = r.u16().ok_or(ProtocolError::Truncated)?;
+            let smoothing = r.u16().ok_or(ProtocolError::Truncated)?;
+            updated.tt_smoothing = Duration::from_millis(smoothing as u64);
+            let curve = r.u8().ok_or(ProtocolError::Truncated)?;
+            updated.tt_curve =
+                TurntableCurve::try_from(curve).map_err(|_| ProtocolError::InvalidValue)?;
+            updated.tt_curve_gains = r.bytes().ok_or(ProtocolError::Truncated)?;
+            None
+        }
+        FeatureReport::Encoder => {
+            let ppr = r.u16().ok_or(ProtocolError::Truncated)?;
+            let max_rps = r.u16().ok_or(ProtocolError::Truncated)?;
+            if ppr == 0 || max_rps == 0 {
+                return Err(ProtocolError::InvalidValue);
+            }
+            updated.encoder_ppr = ppr;
+            updated.tt_max_rps = max_rps;
+            None
+        }
+        FeatureReport::Encoders => {
+            let encoders = r.u8().ok_or(ProtocolError::Truncated)?;
+            if !(1..=MAX_ENCODERS as u8).contains(&encoders) {
+                return Err(ProtocolError::InvalidValue);
+            }
+            updated.encoders = encoders;
+            for scaling in &mut updated.extra_encoders {
+                let ppr = r.u16().ok_or(ProtocolError::Truncated)?;
+                let steps = r.u16().ok_or(ProtocolError::Truncated)?;
+                if ppr == 0 || steps == 0 {
+                    return Err(ProtocolError::InvalidValue);
+                }
+                *scaling = EncoderScaling { ppr, steps };
+            }
+            None
+        }
+    };
+
+    *settings = updated;
+    Ok(command)
+}
+
+/// Builds the latency report for `kind` from `stats`, returning its length.
+pub fn latency_report(kind: InputKind, stats: &LatencyStats, buf: &mut [u8]) -> Option<usize> {
+    let buf = buf.get_mut(..REPORT_LEN)?;
+    buf.fill(0);
+
+    let mut w = Writer::new(buf);
+    w.u8(FeatureReport::latency(kind) as u8);
+    for stage in [Stage::Debounce, Stage::Queue] {
+        stats.histogram(kind, stage).summary().encode(&mut w);
+    }
+    w.finish().map(|_| REPORT_LEN)
+}
+
+/// Parses a latency report for `kind`, giving its debounce and queueing
+/// summaries.
+pub fn decode_latency_report(
+    kind: InputKind,
+    report: &[u8],
+) -> Result<[LatencySummary; 2], ProtocolError> {
+    let mut r = payload(FeatureReport::latency(kind) as u8, report)?;
+    let debounce = LatencySummary::decode(&mut r).ok_or(ProtocolError::Truncated)?;
+    let queue = LatencySummary::decode(&mut r).ok_or(ProtocolError::Truncated)?;
+    Ok([debounce, queue])
+}
+
+/// Builds the report that runs `command`, returning its length.
+pub fn command_report(command: Command, buf: &mut [u8]) -> Option<usize> {
+    let buf = buf.get_mut(..REPORT_LEN)?;
+    buf.fill(0);
+    buf[0] = FeatureReport::Command as u8;
+    buf[1] = command as u8;
+    Some(REPORT_LEN)
+}
+
+fn payload(id: u8, report: &[u8]) -> Result<Reader<'_>, ProtocolError> {
+    match report.split_first() {
+        Some((&first, rest)) if first == id => Ok(Reader::new(rest)),
+        Some(_) => Err(ProtocolError::IdMismatch),
+        None => Err(ProtocolError::Truncated),
+    }
+}
+
+/// Report descriptor for the configuration interface: one vendor-defined
+/// feature report of [`PAYLOAD_LEN`] bytes per [`FeatureReport`].
+#[rustfmt::skip]
+pub const CONFIG_REPORT_DESCRIPTOR: &[u8] = &[
+    0x06, 0x00, 0xFF, // Usage Page (Vendor Defined 0xFF00)
+    0x09, 0x01,       // Usage (0x01)
+    0xA1, 0x01,       // Collection (Application)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x26, 0xFF, 0x00, //   Logical Maximum (255)
+    0x75, 0x08,       //   Report Size (8)
+    0x95, PAYLOAD_LEN as u8, // Report Count
+    0x85, 0x01, 0x09, 0x01, 0xB1, 0x02, // Info: Report ID, Usage, Feature (Data,Var,Abs)
+    0x85, 0x02, 0x09, 0x02, 0xB1, 0x02, // Debounce
+    0x85, 0x03, 0x09, 0x03, 0xB1, 0x02, // Turntable
+    0x85, 0x04, 0x09, 0x04, 0xB1, 0x02, // Lighting
+    0x85, 0x05, 0x09, 0x05, 0xB1, 0x02, // InputMode
+    0x85, 0x06, 0x09, 0x06, 0xB1, 0x02, // Command
+    0x85, 0x07, 0x09, 0x07, 0xB1, 0x02, // ButtonLeds
+    0x85, 0x08, 0x09, 0x08, 0xB1, 0x02, // RingLighting
+    0x85, 0x09, 0x09, 0x09, 0xB1, 0x02, // TurntableAxis
+    0x85, 0x0A, 0x09, 0x0A, 0xB1, 0x02, // DigitalTurntable
+    0x85, 0x0B, 0x09, 0x0B, 0xB1, 0x02, // KeyboardKeys
+    0x85, 0x0C, 0x09, 0x0C, 0xB1, 0x02, // SwitchTurntable
+    0x85, 0x0D, 0x09, 0x0D, 0xB1, 0x02, // SerialNumber
+    0x85, 0x0E, 0x09, 0x0E, 0xB1, 0x02, // UsbIdentity
+    0x85, 0x0F, 0x09, 0x0F, 0xB1, 0x02, // KeyLatency
+    0x85, 0x10, 0x09, 0x10, 0xB1, 0x02, // EButtonLatency
+    0x85, 0x11, 0x09, 0x11, 0xB1, 0x02, // TurntableLatency
+    0x85, 0x12, 0x09, 0x12, 0xB1, 0x02, // TurntableFilter
+    0x85, 0x13, 0x09, 0x13, 0xB1, 0x02, // Encoder
+    0x85, 0x14, 0x09, 0x14, 0xB1, 0x02, // Encoders
+    0xC0,             // End Collection
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debounce::DebounceConfig;
+    use crate::settings::NO_BUTTON;
+    use smart_leds::RGB8;
+
+    fn read(id: FeatureReport, settings: &Settings) -> [u8; REPORT_LEN] {
+        let mut buf = [0xAA; REPORT_LEN];
+        assert_eq!(get_feature(id as u8, settings, &mut buf), Some(REPORT_LEN));
+        buf
+    }
+
+    #[test]
+    fn reports_round_trip_between_settings() {
+        let mut source = Settings::default();
+        source.debounce[3] = DebounceConfig {
+            algorithm: DebounceAlgorithm::Deferred,
+            press: Duration::from_micros(1500),
+            release: Duration::from_millis(6),
+        };
+        source.tt_steps = 72;
+        source.key_colours[10] = RGB8::new(9, 8, 7);
+        source.led_mode = LedMode::Reactive;
+        source.reactive_fade = Duration::from_millis(300);
+        source.button_leds[2] = 9;
+        source.ring_effect = RingEffect::ScratchFlash;
+        source.ring_colour = RGB8::new(1, 2, 3);
+        source.tt_axis = TurntableAxis::Raw;
+        source.tt_hold = Duration::from_millis(250);
+        source.tt_threshold = 4;
+        source.input_mode = InputMode::Keyboard;
+        source.keyboard_keys[3] = 0x2C;
+        source.switch_tt = SwitchTurntable::Dpad;
+        source.serial_number = SerialNumber::new("DP-2P").unwrap();
+        source.usb_profile = UsbProfile::OpenSource;
+        source.custom_identity.vid = 0x0F0F;
+        source.tt_deadzone = 1;
+        source.tt_smoothing = Duration::from_millis(5);
+        source.tt_curve = TurntableCurve::Accelerated;
+        source.tt_curve_gains[0] = 80;
+        source.encoder_ppr = 1000;
+        source.tt_max_rps = 25;
+        source.encoders = 4;
+        source.extra_encoders[2].steps = 256;
+
+        let mut dest = Settings {
+            tt_steps: 500,
+            ..Default::default()
+        };
+        for &id in &[
+            FeatureReport::Debounce,
+            FeatureReport::Turntable,
+            FeatureReport::Lighting,
+            FeatureReport::InputMode,
+            FeatureReport::ButtonLeds,
+            FeatureReport::RingLighting,
+            FeatureReport::TurntableAxis,
+            FeatureReport::DigitalTurntable,
+            FeatureReport::KeyboardKeys,
+            FeatureReport::SwitchTurntable,
+            FeatureReport::SerialNumber,
+            FeatureReport::UsbIdentity,
+            FeatureReport::TurntableFilter,
+            FeatureReport::Encoder,
+            FeatureReport::Encoders,
+        ] {
+            let report = read(id, &source);
+            assert_eq!(set_feature(id as u8, &report, &mut dest), Ok(None));
+        }
+
+        assert_eq!(dest, source);
+    }
+
+    #[test]
+    fn turntable_report_layout() {
+        let settings = Settings {
+            tt_steps: 0x0102,
+            ..Default::default()
+        };
+        let report = read(FeatureReport::Turntable, &settings);
+
+        assert_eq!(report[..3], [3, 0x02, 0x01]);
+        assert!(report[3..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn info_describes_firmware() {
+        let report = read(FeatureReport::Info, &Settings::default());
+        let info = Info::decode(&report).unwrap();
+
+        assert_eq!(info, Info::current());
+        assert_eq!(info.protocol_version, PROTOCOL_VERSION);
+        assert_eq!(info.num_buttons, NUM_BUTTONS as u8);
+        assert!(info.supports_report(FeatureReport::Lighting));
+        assert_eq!(info.debounce_algorithms, 0b111);
+    }
+
+    #[test]
+    fn invalid_report_leaves_settings_untouched() {
+        let mut settings = Settings::default();
+        let mut report = read(FeatureReport::Debounce, &settings);
+        report[1] = 1;
+        // Last button uses an unknown algorithm
+        report[1 + (NUM_BUTTONS - 1) * 5] = 0x7F;
+
+        assert_eq!(
+            set_feature(FeatureReport::Debounce as u8, &report, &mut settings),
+            Err(ProtocolError::InvalidValue)
+        );
+        assert_eq!(settings, Settings::default());
+    }
+
+    #[test]
+    fn button_leds_must_name_a_button() {
+        let mut settings = Settings::default();
+        let mut report = read(FeatureReport::ButtonLeds, &settings);
+        report[3] = NO_BUTTON;
+        report[4] = NUM_BUTTONS as u8 - 1;
+        assert_eq!(
+            set_feature(FeatureReport::ButtonLeds as u8, &report, &mut settings),
+            Ok(None)
+        );
+
+        report[5] = NUM_BUTTONS as u8;
+        assert_eq!(
+            set_feature(FeatureReport::ButtonLeds as u8, &report, &mut settings),
+            Err(ProtocolError::InvalidValue)
+        );
+        assert_eq!(
+            settings.button_leds[..2],
+            [NO_BUTTON, NUM_BUTTONS as u8 - 1]
+        );
+        assert_eq!(settings.button_leds[2], Settings::default().button_leds[2]);
+    }
+
+    #[test]
+    fn rejects_bad_reports() {
+        let mut settings = Settings::default();
+        let report = read(FeatureReport::Info, &settings);
+
+        assert_eq!(
+            set_feature(1, &report, &mut settings),
+            Err(ProtocolError::ReadOnly)
+        );
+        assert_eq!(
+            set_feature(3, &report, &mut settings),
+            Err(ProtocolError::IdMismatch)
+        );
+        assert_eq!(
+            set_feature(3, &[3, 0, 0], &mut settings),
+            Err(ProtocolError::InvalidValue)
+        );
+        assert_eq!(
+            set_feature(3, &[3, 1], &mut settings),
+            Err(ProtocolError::Truncated)
+        );
+        assert_eq!(
+            set_feature(0x40, &[0x40], &mut settings),
+            Err(ProtocolError::UnknownReport(0x40))
+        );
+        assert_eq!(
+            get_feature(FeatureReport::Command as u8, &settings, &mut [0; 64]),
+            None
+        );
+    }
+
+    #[test]
+    fn load_defaults_command() {
+        let mut settings = Settings {
+            tt_steps: 10,
+            ..Default::default()
+        };
+        let mut buf = [0; REPORT_LEN];
+        let len = command_report(Command::LoadDefaults, &mut buf).unwrap();
+
+        assert_eq!(
+            set_feature(FeatureReport::Command as u8, &buf[..len], &mut settings),
+            Ok(Some(Command::LoadDefaults))
+        );
+        assert_eq!(settings, Settings::default());
+    }
+
+    #[test]
+    fn latency_report_round_trips() {
+        let mut stats = LatencyStats::new();
+        stats.record(
+            InputKind::EButtons,
+            Stage::Debounce,
+            Duration::from_micros(40),
+        );
+        stats.record(
+            InputKind::EButtons,
+            Stage::Queue,
+            Duration::from_micros(900),
+        );
+        stats.record(
+            InputKind::EButtons,
+            Stage::Queue,
+            Duration::from_micros(100),
+        );
+        let mut buf = [0; REPORT_LEN];
+        let len = latency_report(InputKind::EButtons, &stats, &mut buf).unwrap();
+
+        assert_eq!(buf[0], FeatureReport::EButtonLatency as u8);
+        let [debounce, queue] = decode_latency_report(InputKind::EButtons, &buf[..len]).unwrap();
+        assert_eq!(debounce.max_us, 40);
+        assert_eq!((queue.count, queue.min_us, queue.mean_us), (2, 100, 500));
+        assert_eq!(
+            set_feature(buf[0], &buf[..len], &mut Settings::default()),
+            Err(ProtocolError::ReadOnly)
+        );
+    }
+
+    #[test]
+    fn encoders_report_checks_its_values() {
+        let mut settings = Settings::default();
+        let id = FeatureReport::Encoders as u8;
+        let mut report = read(FeatureReport::Encoders, &settings);
+
+        for count in [0, MAX_ENCODERS as u8 + 1] {
+            report[1] = count;
+            assert_eq!(
+                set_feature(id, &report, &mut settings),
+                Err(ProtocolError::InvalidValue)
+            );
+        }
+        report[1] = 2;
+        // Zero steps for the last encoder
+        report[12..14].fill(0);
+        assert_eq!(
+            set_feature(id, &report, &mut settings),
+            Err(ProtocolError::InvalidValue)
+        );
+        assert_eq!(settings, Settings::default());
+    }
+
+    #[test]
+    fn descriptor_declares_every_report() {
+        for &report in FeatureReport::ALL {
+            let item = [0x85, report as u8, 0x09, report as u8, 0xB1, 0x02];
+            assert!(
+                CONFIG_REPORT_DESCRIPTOR
+                    .windows(item.len())
+                    .any(|w| w == item)
+            );
+        }
+    }
+}
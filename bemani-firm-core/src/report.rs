@@ -0,0 +1,681 @@
+use usbd_hid::descriptor::AsInputReport;
+use usbd_hid::descriptor::SerializedDescriptor;
+use usbd_hid::descriptor::gen_hid_descriptor;
+use usbd_hid::descriptor::generator_prelude::Serialize;
+use usbd_hid::descriptor::generator_prelude::SerializeTuple;
+use usbd_hid::descriptor::generator_prelude::Serializer;
+
+use crate::encoder::{MAX_ENCODERS, TurntablePosition};
+use crate::keyboard::{KEYBOARD_REPORT_DESCRIPTOR, NUM_KEYBOARD_INPUTS, serialize_keyboard_report};
+use crate::lights::NUM_LIGHTS;
+use crate::settings::{InputMode, Settings, TurntableAxis};
+use crate::switch::{SWITCH_REPORT_DESCRIPTOR, SwitchTurntable, serialize_switch_report};
+
+#[gen_hid_descriptor(
+    (collection = APPLICATION, usage_page = GENERIC_DESKTOP, usage = JOYSTICK) = {
+        (collection = PHYSICAL, usage = GAMEPAD) = {
+            (usage_page = BUTTON, usage_min = 1, usage_max = 8) = {
+                #[packed_bits 8] #[item_settings data,variable,absolute] buttons=input;
+            };
+            (usage_page = BUTTON, usage_min = 9, usage_max = 12) = {
+                #[packed_bits 4] #[item_settings data,variable,absolute] buttons_menu=input;
+            };
+            (usage_page = GENERIC_DESKTOP,) = {
+                (usage = X, logical_min = 0) = {
+                    #[item_settings data,variable,absolute] tt=input;
+                };
+            };
+        };
+        (usage_page = ORDINAL, usage_min = 1, usage_max = 14) = {
+            #[item_settings data,variable,absolute] lights=output;
+        };
+    }
+)]
+pub struct KonamiIIDXReport {
+    pub buttons: u8,
+    pub buttons_menu: u8,
+    pub tt: u8,
+    /// Light levels sent by the host, see
+    /// [`HostLights`](crate::lights::HostLights). The macro needs a
+    /// literal length, `new` checks it matches [`NUM_LIGHTS`].
+    pub lights: [u8; 14],
+}
+
+impl KonamiIIDXReport {
+    /// Builds a report from the button bitmask produced by the button task,
+    /// with the seven keys in the low byte and E1-E4 in the high byte.
+    pub fn new(buttons: u16, tt: u8) -> Self {
+        let (buttons, buttons_menu) = split_buttons(buttons);
+        Self {
+            tt,
+            buttons,
+            buttons_menu,
+            lights: [0; NUM_LIGHTS],
+        }
+    }
+}
+
+/// [`KonamiIIDXReport`] with a 16-bit turntable axis.
+#[gen_hid_descriptor(
+    (collection = APPLICATION, usage_page = GENERIC_DESKTOP, usage = JOYSTICK) = {
+        (collection = PHYSICAL, usage = GAMEPAD) = {
+            (usage_page = BUTTON, usage_min = 1, usage_max = 8) = {
+                #[packed_bits 8] #[item_settings data,variable,absolute] buttons=input;
+            };
+            (usage_page = BUTTON, usage_min = 9, usage_max = 12) = {
+                #[packed_bits 4] #[item_settings data,variable,absolute] buttons_menu=input;
+            };
+            (usage_page = GENERIC_DESKTOP,) = {
+                (usage = X, logical_min = 0) = {
+                    #[item_settings data,variable,absolute] tt=input;
+                };
+            };
+        };
+        (usage_page = ORDINAL, usage_min = 1, usage_max = 14) = {
+            #[item_settings data,variable,absolute] lights=output;
+        };
+    }
+)]
+pub struct KonamiIIDXReport16 {
+    pub buttons: u8,
+    pub buttons_menu: u8,
+    pub tt: u16,
+    pub lights: [u8; 14],
+}
+
+impl KonamiIIDXReport16 {
+    pub fn new(buttons: u16, tt: u16) -> Self {
+        let (buttons, buttons_menu) = split_buttons(buttons);
+        Self {
+            tt,
+            buttons,
+            buttons_menu,
+            lights: [0; NUM_LIGHTS],
+        }
+    }
+}
+
+/// [`KonamiIIDXReport`] with the raw, signed encoder counts as the turntable
+/// axis.
+#[gen_hid_descriptor(
+    (collection = APPLICATION, usage_page = GENERIC_DESKTOP, usage = JOYSTICK) = {
+        (collection = PHYSICAL, usage = GAMEPAD) = {
+            (usage_page = BUTTON, usage_min = 1, usage_max = 8) = {
+                #[packed_bits 8] #[item_settings data,variable,absolute] buttons=input;
+            };
+            (usage_page = BUTTON, usage_min = 9, usage_max = 12) = {
+                #[packed_bits 4] #[item_settings data,variable,absolute] buttons_menu=input;
+            };
+            (usage_page = GENERIC_DESKTOP,) = {
+                (usage = X,) = {
+                    #[item_settings data,variable,absolute] tt=input;
+                };
+            };
+        };
+        (usage_page = ORDINAL, usage_min = 1, usage_max = 14) = {
+            #[item_settings data,variable,absolute] lights=output;
+        };
+    }
+)]
+pub struct KonamiIIDXReportRaw {
+    pub buttons: u8,
+    pub buttons_menu: u8,
+    pub tt: i32,
+    pub lights: [u8; 14],
+}
+
+impl KonamiIIDXReportRaw {
+    pub fn new(buttons: u16, tt: i32) -> Self {
+        let (buttons, buttons_menu) = split_buttons(buttons);
+        Self {
+            tt,
+            buttons,
+            buttons_menu,
+            lights: [0; NUM_LIGHTS],
+        }
+    }
+}
+
+/// Joystick report for the digital turntable mode, with TT+ and TT- as
+/// buttons 13 and 14 and no axis.
+#[gen_hid_descriptor(
+    (collection = APPLICATION, usage_page = GENERIC_DESKTOP, usage = JOYSTICK) = {
+        (collection = PHYSICAL, usage = GAMEPAD) = {
+            (usage_page = BUTTON, usage_min = 1, usage_max = 8) = {
+                #[packed_bits 8] #[item_settings data,variable,absolute] buttons=input;
+            };
+            (usage_page = BUTTON, usage_min = 9, usage_max = 14) = {
+                #[packed_bits 6] #[item_settings data,variable,absolute] buttons_menu=input;
+            };
+        };
+        (usage_page = ORDINAL, usage_min = 1, usage_max = 14) = {
+            #[item_settings data,variable,absolute] lights=output;
+        };
+    }
+)]
+pub struct KonamiIIDXReportDigital {
+    pub buttons: u8,
+    /// E1-E4, then TT+ and TT-.
+    pub buttons_menu: u8,
+    pub lights: [u8; 14],
+}
+
+impl KonamiIIDXReportDigital {
+    /// `buttons` includes the [`TT_UP_BIT`](crate::encoder::TT_UP_BIT) and
+    /// [`TT_DOWN_BIT`](crate::encoder::TT_DOWN_BIT) bits.
+    pub fn new(buttons: u16) -> Self {
+        let (buttons, buttons_menu) = split_buttons(buttons);
+        Self {
+            buttons,
+            buttons_menu,
+            lights: [0; NUM_LIGHTS],
+        }
+    }
+}
+
+/// Generates a joystick report laid out like [`KonamiIIDXReport`] with one
+/// axis per field, for builds with more than one encoder.
+macro_rules! multi_axis_report {
+    (
+        $(#[doc = $doc:literal])*
+        $name:ident: [$ty:ident; $n:literal] { $($field:ident = ($($spec:tt)*)),+ $(,)? }
+    ) => {
+        $(#[doc = $doc])*
+        #[gen_hid_descriptor(
+            (collection = APPLICATION, usage_page = GENERIC_DESKTOP, usage = JOYSTICK) = {
+                (collection = PHYSICAL, usage = GAMEPAD) = {
+                    (usage_page = BUTTON, usage_min = 1, usage_max = 8) = {
+                        #[packed_bits 8] #[item_settings data,variable,absolute] buttons=input;
+                    };
+                    (usage_page = BUTTON, usage_min = 9, usage_max = 12) = {
+                        #[packed_bits 4] #[item_settings data,variable,absolute] buttons_menu=input;
+                    };
+                    (usage_page = GENERIC_DESKTOP,) = {
+                        $(($($spec)*) = {
+                            #[item_settings data,variable,absolute] $field=input;
+                        };)+
+                    };
+                };
+                (usage_page = ORDINAL, usage_min = 1, usage_max = 14) = {
+                    #[item_settings data,variable,absolute] lights=output;
+                };
+            }
+        )]
+        pub struct $name {
+            pub buttons: u8,
+            pub buttons_menu: u8,
+            $(pub $field: $ty,)+
+            pub lights: [u8; 14],
+        }
+
+        impl $name {
+            /// Builds a report with the encoders' axes in order.
+            pub fn new(buttons: u16, [$($field),+]: [$ty; $n]) -> Self {
+                let (buttons, buttons_menu) = split_buttons(buttons);
+                Self {
+                    buttons,
+                    buttons_menu,
+                    $($field,)+
+                    lights: [0; NUM_LIGHTS],
+                }
+            }
+        }
+    };
+}
+
+multi_axis_report! {
+    /// Two 8-bit axes, X and Y.
+    TwoAxisReport: [u8; 2] {
+        tt = (usage = X, logical_min = 0),
+        tt_2 = (usage = Y, logical_min = 0),
+    }
+}
+
+multi_axis_report! {
+    /// Two 16-bit axes, X and Y.
+    TwoAxisReport16: [u16; 2] {
+        tt = (usage = X, logical_min = 0),
+        tt_2 = (usage = Y, logical_min = 0),
+    }
+}
+
+multi_axis_report! {
+    /// Two raw count axes, X and Y.
+    TwoAxisReportRaw: [i32; 2] {
+        tt = (usage = X,),
+        tt_2 = (usage = Y,),
+    }
+}
+
+multi_axis_report! {
+    /// Three 8-bit axes, X, Y and Z.
+    ThreeAxisReport: [u8; 3] {
+        tt = (usage = X, logical_min = 0),
+        tt_2 = (usage = Y, logical_min = 0),
+        tt_3 = (usage = Z, logical_min = 0),
+    }
+}
+
+multi_axis_report! {
+    /// Three 16-bit axes, X, Y and Z.
+    ThreeAxisReport16: [u16; 3] {
+        tt = (usage = X, logical_min = 0),
+        tt_2 = (usage = Y, logical_min = 0),
+        tt_3 = (usage = Z, logical_min = 0),
+    }
+}
+
+multi_axis_report! {
+    /// Three raw count axes, X, Y and Z.
+    ThreeAxisReportRaw: [i32; 3] {
+        tt = (usage = X,),
+        tt_2 = (usage = Y,),
+        tt_3 = (usage = Z,),
+    }
+}
+
+multi_axis_report! {
+    /// Four 8-bit axes, X, Y, Z and Rx.
+    FourAxisReport: [u8; 4] {
+        tt = (usage = X, logical_min = 0),
+        tt_2 = (usage = Y, logical_min = 0),
+        tt_3 = (usage = Z, logical_min = 0),
+        tt_4 = (usage = 0x33, logical_min = 0),
+    }
+}
+
+multi_axis_report! {
+    /// Four 16-bit axes, X, Y, Z and Rx.
+    FourAxisReport16: [u16; 4] {
+        tt = (usage = X, logical_min = 0),
+        tt_2 = (usage = Y, logical_min = 0),
+        tt_3 = (usage = Z, logical_min = 0),
+        tt_4 = (usage = 0x33, logical_min = 0),
+    }
+}
+
+multi_axis_report! {
+    /// Four raw count axes, X, Y, Z and Rx.
+    FourAxisReportRaw: [i32; 4] {
+        tt = (usage = X,),
+        tt_2 = (usage = Y,),
+        tt_3 = (usage = Z,),
+        tt_4 = (usage = 0x33,),
+    }
+}
+
+fn split_buttons(buttons: u16) -> (u8, u8) {
+    ((buttons & 0xFF) as u8, ((buttons & 0xFF00) >> 8) as u8)
+}
+
+/// Report descriptor of the joystick report using `axis` for `encoders`
+/// encoders.
+///
+/// The digital mode only has buttons for the first encoder, the others are
+/// left out of the report.
+pub fn iidx_report_descriptor(axis: TurntableAxis, encoders: usize) -> &'static [u8] {
+    match (axis, encoders) {
+        (TurntableAxis::Wrapped8, 2) => TwoAxisReport::desc(),
+        (TurntableAxis::Wrapped8, 3) => ThreeAxisReport::desc(),
+        (TurntableAxis::Wrapped8, 4) => FourAxisReport::desc(),
+        (TurntableAxis::Wrapped8, _) => KonamiIIDXReport::desc(),
+        (TurntableAxis::Wrapped16, 2) => TwoAxisReport16::desc(),
+        (TurntableAxis::Wrapped16, 3) => ThreeAxisReport16::desc(),
+        (TurntableAxis::Wrapped16, 4) => FourAxisReport16::desc(),
+        (TurntableAxis::Wrapped16, _) => KonamiIIDXReport16::desc(),
+        (TurntableAxis::Raw, 2) => TwoAxisReportRaw::desc(),
+        (TurntableAxis::Raw, 3) => ThreeAxisReportRaw::desc(),
+        (TurntableAxis::Raw, 4) => FourAxisReportRaw::desc(),
+        (TurntableAxis::Raw, _) => KonamiIIDXReportRaw::desc(),
+        (TurntableAxis::Digital, _) => KonamiIIDXReportDigital::desc(),
+    }
+}
+
+/// Serializes the joystick report using `axis` into `buf`, returning the
+/// number of bytes used. Each position in `tt` gets an axis, see
+/// [`iidx_report_descriptor`].
+pub fn serialize_iidx_report(
+    axis: TurntableAxis,
+    buttons: u16,
+    tt: &[TurntablePosition],
+    buf: &mut [u8],
+) -> Option<usize> {
+    let first = tt.first()?;
+    let steps8 = |tt: &TurntablePosition| tt.steps as u8;
+    let steps16 = |tt: &TurntablePosition| tt.steps;
+    let raw_counts = |tt: &TurntablePosition| tt.raw_counts;
+
+    match (axis, tt.len()) {
+        (TurntableAxis::Wrapped8, 2) => {
+            serialize_report(&TwoAxisReport::new(buttons, axes(tt, steps8)), buf)
+        }
+        (TurntableAxis::Wrapped8, 3) => {
+            serialize_report(&ThreeAxisReport::new(buttons, axes(tt, steps8)), buf)
+        }
+        (TurntableAxis::Wrapped8, 4) => {
+            serialize_report(&FourAxisReport::new(buttons, axes(tt, steps8)), buf)
+        }
+        (TurntableAxis::Wrapped8, _) => {
+            serialize_report(&KonamiIIDXReport::new(buttons, steps8(first)), buf)
+        }
+        (TurntableAxis::Wrapped16, 2) => {
+            serialize_report(&TwoAxisReport16::new(buttons, axes(tt, steps16)), buf)
+        }
+        (TurntableAxis::Wrapped16, 3) => {
+            serialize_report(&ThreeAxisReport16::new(buttons, axes(tt, steps16)), buf)
+        }
+        (TurntableAxis::Wrapped16, 4) => {
+            serialize_report(&FourAxisReport16::new(buttons, axes(tt, steps16)), buf)
+        }
+        (TurntableAxis::Wrapped16, _) => {
+            serialize_report(&KonamiIIDXReport16::new(buttons, first.steps), buf)
+        }
+        (TurntableAxis::Raw, 2) => {
+            serialize_report(&TwoAxisReportRaw::new(buttons, axes(tt, raw_counts)), buf)
+        }
+        (TurntableAxis::Raw, 3) => {
+            serialize_report(&ThreeAxisReportRaw::new(buttons, axes(tt, raw_counts)), buf)
+        }
+        (TurntableAxis::Raw, 4) => {
+            serialize_report(&FourAxisReportRaw::new(buttons, axes(tt, raw_counts)), buf)
+        }
+        (TurntableAxis::Raw, _) => {
+            serialize_report(&KonamiIIDXReportRaw::new(buttons, first.raw_counts), buf)
+        }
+        (TurntableAxis::Digital, _) => {
+            serialize_report(&KonamiIIDXReportDigital::new(buttons | first.digital), buf)
+        }
+    }
+}
+
+/// The first `N` positions of `tt` as axis values. Callers check there are
+/// that many.
+fn axes<T, const N: usize>(
+    tt: &[TurntablePosition],
+    value: impl Fn(&TurntablePosition) -> T,
+) -> [T; N] {
+    core::array::from_fn(|i| value(&tt[i]))
+}
+
+/// Current state of whichever input report the USB personality sends.
+///
+/// The interrupt endpoint and GET_REPORT both serialize from this, so they
+/// always agree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputReport {
+    pub mode: InputMode,
+    pub tt_axis: TurntableAxis,
+    pub keyboard_keys: [u8; NUM_KEYBOARD_INPUTS],
+    pub switch_tt: SwitchTurntable,
+    /// Debounced button bitmask.
+    pub buttons: u16,
+    /// Encoders with an axis in the joystick report, from 1 to
+    /// [`MAX_ENCODERS`].
+    pub encoders: usize,
+    /// Position of each encoder, the turntable first.
+    pub tt: [TurntablePosition; MAX_ENCODERS],
+}
+
+impl InputReport {
+    /// An idle report for running as `mode` with `encoders` axes of
+    /// `tt_axis`, all fixed until the next boot.
+    pub fn new(
+        mode: InputMode,
+        tt_axis: TurntableAxis,
+        encoders: usize,
+        settings: &Settings,
+    ) -> Self {
+        Self {
+            mode,
+            tt_axis,
+            keyboard_keys: settings.keyboard_keys,
+            switch_tt: settings.switch_tt,
+            buttons: 0,
+            encoders: encoders.clamp(1, MAX_ENCODERS),
+            tt: [TurntablePosition::default(); MAX_ENCODERS],
+        }
+    }
+
+    /// Picks up the settings that can change while enumerated.
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        self.keyboard_keys = settings.keyboard_keys;
+        self.switch_tt = settings.switch_tt;
+    }
+
+    pub fn descriptor(&self) -> &'static [u8] {
+        match self.mode {
+            InputMode::Joystick => iidx_report_descriptor(self.tt_axis, self.encoders),
+            InputMode::Keyboard => KEYBOARD_REPORT_DESCRIPTOR,
+            InputMode::Switch => SWITCH_REPORT_DESCRIPTOR,
+        }
+    }
+
+    /// Serializes the report into `buf`, returning the number of bytes used.
+    pub fn serialize(&self, buf: &mut [u8]) -> Option<usize> {
+        // Only the joystick has a place for the turntable axis, the other
+        // modes press the digital turntable buttons
+        let buttons = self.buttons | self.tt[0].digital;
+        match self.mode {
+            InputMode::Joystick => {
+                let tt = &self.tt[..self.encoders];
+                serialize_iidx_report(self.tt_axis, self.buttons, tt, buf)
+            }
+            InputMode::Keyboard => serialize_keyboard_report(&self.keyboard_keys, buttons, buf),
+            InputMode::Switch => serialize_switch_report(self.switch_tt, buttons, buf),
+        }
+    }
+}
+
+/// Serializes an input report into `buf` exactly as the HID writer does,
+/// returning the number of bytes used.
+pub fn serialize_report<R: AsInputReport>(report: &R, buf: &mut [u8]) -> Option<usize> {
+    ssmarshal::serialize(buf, report).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::TT_DOWN_BIT;
+
+    #[test]
+    fn buttons_are_split_into_keys_and_menu() {
+        let report = KonamiIIDXReport::new(0b1010_0100_0001, 0x7F);
+        let mut buf = [0u8; 8];
+        let len = serialize_report(&report, &mut buf).unwrap();
+
+        assert_eq!(&buf[..len], &[0b0100_0001, 0b1010, 0x7F]);
+    }
+
+    #[test]
+    fn lights_are_not_part_of_the_input_report() {
+        let mut report = KonamiIIDXReport::new(0, 0);
+        report.lights = [0xFF; NUM_LIGHTS];
+        let mut buf = [0u8; 32];
+
+        assert_eq!(serialize_report(&report, &mut buf), Some(3));
+    }
+
+    #[test]
+    fn descriptor_is_generated() {
+        let desc = KonamiIIDXReport::desc();
+        // Usage Page (Generic Desktop), Usage (Joystick)
+        assert_eq!(&desc[..4], &[0x05, 0x01, 0x09, 0x04]);
+        // Report Count (lights), Output (Data,Var,Abs), End Collection
+        assert!(desc.ends_with(&[0x95, NUM_LIGHTS as u8, 0x91, 0x02, 0xC0]));
+    }
+
+    #[test]
+    fn axis_modes_serialize_their_own_width() {
+        let tt = TurntablePosition {
+            steps: 0x1234,
+            counts: 5,
+            raw_counts: -2,
+            digital: TT_DOWN_BIT,
+        };
+        let mut buf = [0u8; 8];
+        let mut serialize = |axis| {
+            let len = serialize_iidx_report(axis, 0x0201, &[tt], &mut buf).unwrap();
+            buf[..len].to_vec()
+        };
+
+        assert_eq!(serialize(TurntableAxis::Wrapped8), [0x01, 0x02, 0x34]);
+        assert_eq!(
+            serialize(TurntableAxis::Wrapped16),
+            [0x01, 0x02, 0x34, 0x12]
+        );
+        assert_eq!(
+            serialize(TurntableAxis::Raw),
+            [0x01, 0x02, 0xFE, 0xFF, 0xFF, 0xFF]
+        );
+        assert_eq!(serialize(TurntableAxis::Digital), [0x01, 0x22]);
+    }
+
+    #[test]
+    fn input_report_matches_the_mode_reports() {
+        let settings = Settings::default();
+        let tt = TurntablePosition {
+            steps: 0x0180,
+            counts: 600,
+            raw_counts: 600,
+            digital: TT_DOWN_BIT,
+        };
+        let mut got = [0u8; 32];
+        let mut expected = [0u8; 32];
+
+        // What the interrupt endpoint's HID writer would serialize
+        let mut report =
+            InputReport::new(InputMode::Joystick, TurntableAxis::Wrapped8, 1, &settings);
+        report.buttons = 0x0403;
+        report.tt[0] = tt;
+        let len = report.serialize(&mut got).unwrap();
+        let expected_len =
+            serialize_report(&KonamiIIDXReport::new(0x0403, 0x80), &mut expected).unwrap();
+        assert_eq!(got[..len], expected[..expected_len]);
+
+        report.mode = InputMode::Keyboard;
+        let len = report.serialize(&mut got).unwrap();
+        let expected_len =
+            serialize_keyboard_report(&settings.keyboard_keys, 0x0403 | TT_DOWN_BIT, &mut expected)
+                .unwrap();
+        assert_eq!(got[..len], expected[..expected_len]);
+
+        report.mode = InputMode::Switch;
+        let len = report.serialize(&mut got).unwrap();
+        let expected_len =
+            serialize_switch_report(settings.switch_tt, 0x0403 | TT_DOWN_BIT, &mut expected)
+                .unwrap();
+        assert_eq!(got[..len], expected[..expected_len]);
+    }
+
+    #[test]
+    fn axis_descriptors_match_their_reports() {
+        // Report Size (8/16/32), Report Count (1), Input (Data,Var,Abs)
+        let axis_item = |size| [0x75, size, 0x95, 0x01, 0x81, 0x02];
+        let contains = |desc: &[u8], item: &[u8]| desc.windows(item.len()).any(|w| w == item);
+
+        for (axis, size) in [
+            (TurntableAxis::Wrapped8, 8),
+            (TurntableAxis::Wrapped16, 16),
+            (TurntableAxis::Raw, 32),
+        ] {
+            assert!(contains(iidx_report_descriptor(axis, 1), &axis_item(size)));
+        }
+        // Six buttons after the first eight, and no axis in digital mode
+        let digital = iidx_report_descriptor(TurntableAxis::Digital, 1);
+        assert!(contains(digital, &[0x19, 0x09, 0x29, 0x0E]));
+        assert!(!contains(digital, &[0x09, 0x30]));
+        // The raw counts are signed
+        assert!(contains(
+            KonamiIIDXReportRaw::desc(),
+            &[0x17, 0x01, 0x00, 0x00, 0x80]
+        ));
+    }
+
+    #[test]
+    fn each_encoder_gets_an_axis() {
+        let tt: [_; MAX_ENCODERS] = core::array::from_fn(|i| TurntablePosition {
+            steps: 0x0110 * (i as u16 + 1),
+            counts: 0,
+            raw_counts: -(i as i32) - 1,
+            digital: TT_DOWN_BIT,
+        });
+        let mut buf = [0u8; 32];
+        let mut serialize = |axis, encoders| {
+            let len = serialize_iidx_report(axis, 0x0201, &tt[..encoders], &mut buf).unwrap();
+            buf[..len].to_vec()
+        };
+
+        assert_eq!(
+            serialize(TurntableAxis::Wrapped8, 2),
+            [0x01, 0x02, 0x10, 0x20]
+        );
+        assert_eq!(
+            serialize(TurntableAxis::Wrapped16, 3),
+            [0x01, 0x02, 0x10, 0x01, 0x20, 0x02, 0x30, 0x03]
+        );
+        assert_eq!(
+            serialize(TurntableAxis::Raw, 4)[2..],
+            [
+                0xFF, 0xFF, 0xFF, 0xFF, 0xFE, 0xFF, 0xFF, 0xFF, 0xFD, 0xFF, 0xFF, 0xFF, 0xFC, 0xFF,
+                0xFF, 0xFF
+            ]
+        );
+        // Only the turntable has digital buttons
+        assert_eq!(serialize(TurntableAxis::Digital, 4), [0x01, 0x22]);
+    }
+
+    #[test]
+    fn multi_axis_descriptors_name_each_axis() {
+        let contains = |desc: &[u8], item: &[u8]| desc.windows(item.len()).any(|w| w == item);
+        // Usage (X), Usage (Y), Usage (Z), Usage (Rx)
+        let usages = [[0x09, 0x30], [0x09, 0x31], [0x09, 0x32], [0x09, 0x33]];
+
+        for axis in [
+            TurntableAxis::Wrapped8,
+            TurntableAxis::Wrapped16,
+            TurntableAxis::Raw,
+        ] {
+            for encoders in 1..=MAX_ENCODERS {
+                let desc = iidx_report_descriptor(axis, encoders);
+                for (i, usage) in usages.iter().enumerate() {
+                    assert_eq!(
+                        contains(desc, usage),
+                        i < encoders,
+                        "{axis:?} {encoders} {i}"
+                    );
+                }
+            }
+        }
+        assert_eq!(
+            iidx_report_descriptor(TurntableAxis::Digital, 2),
+            KonamiIIDXReportDigital::desc()
+        );
+    }
+
+    #[test]
+    fn input_report_sends_the_configured_axes() {
+        let settings = Settings::default();
+        let mut report =
+            InputReport::new(InputMode::Joystick, TurntableAxis::Wrapped8, 2, &settings);
+        report.tt[1].steps = 7;
+        report.tt[2].steps = 9;
+        let mut buf = [0u8; 32];
+
+        let len = report.serialize(&mut buf).unwrap();
+        assert_eq!(buf[..len], [0, 0, 0, 7]);
+        assert_eq!(report.descriptor(), TwoAxisReport::desc());
+    }
+
+    #[test]
+    fn idle_report_is_available_before_any_input() {
+        let settings = Settings::default();
+        let mut buf = [0u8; 32];
+
+        for &mode in InputMode::ALL {
+            for &axis in TurntableAxis::ALL {
+                let report = InputReport::new(mode, axis, 1, &settings);
+                assert!(report.serialize(&mut buf).is_some(), "{mode:?} {axis:?}");
+            }
+        }
+    }
+}
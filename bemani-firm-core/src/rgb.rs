@@ -0,0 +1,81 @@
+use smart_leds::RGB8;
+
+pub const NUM_LED_BITS: usize = 24;
+const BITS_PER_COLOUR: usize = 8;
+
+/// Packs colours for several WS2812 strips into the words clocked out by the
+/// parallel PIO program.
+///
+/// Word `n` holds bit `n` of the GRB stream for every strip at once, with
+/// strip `i` in bit `i`. `words` must hold `NUM_LED_BITS` words per LED.
+pub fn pack_parallel_ws2812<const NUM_LEDS: usize, const NUM_STRIPS: usize>(
+    colours: &[[RGB8; NUM_LEDS]; NUM_STRIPS],
+    words: &mut [u32],
+) {
+    assert!(NUM_STRIPS <= 32);
+    assert!(words.len() >= NUM_LEDS * NUM_LED_BITS);
+
+    words.fill(0);
+
+    for (strip, leds) in colours.iter().enumerate() {
+        for (led, rgb) in leds.iter().enumerate() {
+            for bit in 0..NUM_LED_BITS {
+                let colour = if bit < BITS_PER_COLOUR {
+                    rgb.g
+                } else if bit < (2 * BITS_PER_COLOUR) {
+                    rgb.r
+                } else {
+                    rgb.b
+                };
+
+                let colour_bit_index = bit % BITS_PER_COLOUR;
+
+                // We want MSB first
+                let colour_bit = (colour >> (BITS_PER_COLOUR - colour_bit_index - 1)) & 0b1;
+                words[(led * NUM_LED_BITS) + bit] |= (colour_bit as u32) << strip;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_strip_is_grb_msb_first() {
+        let colours = [[RGB8::new(0x0F, 0x80, 0x01)]];
+        let mut words = [0u32; NUM_LED_BITS];
+        pack_parallel_ws2812(&colours, &mut words);
+
+        let bits: [u32; NUM_LED_BITS] = [
+            1, 0, 0, 0, 0, 0, 0, 0, // g = 0x80
+            0, 0, 0, 0, 1, 1, 1, 1, // r = 0x0F
+            0, 0, 0, 0, 0, 0, 0, 1, // b = 0x01
+        ];
+        assert_eq!(words, bits);
+    }
+
+    #[test]
+    fn strips_are_interleaved_by_bit() {
+        let colours = [
+            [RGB8::new(0, 0xFF, 0)],
+            [RGB8::new(0, 0, 0)],
+            [RGB8::new(0xFF, 0xFF, 0xFF)],
+        ];
+        let mut words = [0u32; NUM_LED_BITS];
+        pack_parallel_ws2812(&colours, &mut words);
+
+        assert!(words[..8].iter().all(|&w| w == 0b101));
+        assert!(words[8..].iter().all(|&w| w == 0b100));
+    }
+
+    #[test]
+    fn previous_contents_are_cleared() {
+        let colours = [[RGB8::default(); 2]];
+        let mut words = [u32::MAX; 2 * NUM_LED_BITS];
+        pack_parallel_ws2812(&colours, &mut words);
+
+        assert!(words.iter().all(|&w| w == 0));
+    }
+}
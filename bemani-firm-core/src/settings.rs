@@ -0,0 +1,689 @@
+use embassy_time::Duration;
+use smart_leds::RGB8;
+
+use crate::button::NUM_BUTTONS;
+use crate::codec::{Reader, Writer};
+use crate::debounce::{DebounceAlgorithm, DebounceConfig};
+use crate::encoder::{
+    DEFAULT_ENCODER_PPR, DEFAULT_MAX_ROTATIONS_PER_SECOND, DEFAULT_TT_HOLD, DEFAULT_TT_THRESHOLD,
+    EncoderScaling, MAX_ENCODERS, TARGET_STEPS,
+};
+use crate::filter::{CURVE_POINTS, TurntableCurve, UNITY_GAIN};
+use crate::identity::{CustomIdentity, UsbProfile};
+use crate::keyboard::{DEFAULT_KEYBOARD_KEYS, NUM_KEYBOARD_INPUTS, is_valid_key};
+use crate::switch::SwitchTurntable;
+
+/// Version of the encoded settings layout.
+///
+/// Fields are only ever appended to the encoding, so a record written by an
+/// older firmware decodes with the newer fields left at their defaults and a
+/// newer record decodes as far as this firmware understands it. Anything
+/// beyond that (a field changing meaning or units) bumps this and gets a
+/// step in [`migrate`].
+pub const SETTINGS_VERSION: u16 = 1;
+
+/// Upper bound on the size of an encoded [`Settings`].
+pub const MAX_ENCODED_LEN: usize = 480;
+
+pub const MAX_SERIAL_LEN: usize = 16;
+
+/// Button LEDs that can be mapped. Only some are wired on any given board.
+pub const MAX_BUTTON_LEDS: usize = NUM_BUTTONS;
+
+/// [`Settings::button_leds`] entry for an LED that follows no button.
+pub const NO_BUTTON: u8 = 0xFF;
+
+/// Whether `button` is a [`Settings::button_leds`] entry this firmware can
+/// follow: a button index, or [`NO_BUTTON`].
+pub fn is_valid_led_button(button: u8) -> bool {
+    (button as usize) < NUM_BUTTONS || button == NO_BUTTON
+}
+
+const DEFAULT_REACTIVE_FADE: Duration = Duration::from_millis(200);
+
+const DEFAULT_KEY_COLOURS: [RGB8; 3] = [
+    RGB8::new(0xA2, 0x2B, 0x95),
+    RGB8::new(0x12, 0x34, 0x56),
+    RGB8::new(0x63, 0x6a, 0x2c),
+];
+
+/// How the button LEDs are driven.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum LedMode {
+    /// Each key shows its configured colour.
+    #[default]
+    Static = 0,
+    /// Each key lights up while pressed and fades out after release.
+    Reactive = 1,
+}
+
+impl LedMode {
+    /// Every mode this firmware supports.
+    pub const ALL: &[LedMode] = &[LedMode::Static, LedMode::Reactive];
+}
+
+impl TryFrom<u8> for LedMode {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Static),
+            1 => Ok(Self::Reactive),
+            x => Err(x),
+        }
+    }
+}
+
+/// Effect shown on the turntable LED ring, see [`crate::effect`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum RingEffect {
+    /// A single LED following the platter.
+    #[default]
+    SpinningDot = 0,
+    /// Hues cycling around the ring.
+    Rainbow = 1,
+    /// The whole ring slowly pulsing.
+    Breathing = 2,
+    /// A dot leaving a trail that grows with spin speed.
+    VelocityTrail = 3,
+    /// The spinning dot, plus a flash of the whole ring on each direction change.
+    ScratchFlash = 4,
+}
+
+impl RingEffect {
+    /// Every effect this firmware supports.
+    pub const ALL: &[RingEffect] = &[
+        RingEffect::SpinningDot,
+        RingEffect::Rainbow,
+        RingEffect::Breathing,
+        RingEffect::VelocityTrail,
+        RingEffect::ScratchFlash,
+    ];
+}
+
+impl TryFrom<u8> for RingEffect {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|&effect| effect as u8 == value)
+            .ok_or(value)
+    }
+}
+
+/// What the controller presents itself as over USB.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum InputMode {
+    /// Konami premium model style joystick.
+    #[default]
+    Joystick = 0,
+    /// NKRO keyboard, with the keys from [`Settings::keyboard_keys`].
+    Keyboard = 1,
+    /// Nintendo Switch wired pad, see [`crate::switch`].
+    Switch = 2,
+}
+
+impl InputMode {
+    /// Every mode this firmware supports.
+    pub const ALL: &[InputMode] = &[InputMode::Joystick, InputMode::Keyboard, InputMode::Switch];
+}
+
+impl TryFrom<u8> for InputMode {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Joystick),
+            1 => Ok(Self::Keyboard),
+            2 => Ok(Self::Switch),
+            x => Err(x),
+        }
+    }
+}
+
+/// How the turntable appears in the joystick report.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum TurntableAxis {
+    /// [`Settings::tt_steps`] per rotation, wrapping at 8 bits.
+    #[default]
+    Wrapped8 = 0,
+    /// [`Settings::tt_steps`] per rotation, wrapping at 16 bits.
+    Wrapped16 = 1,
+    /// Signed quadrature counts straight from the encoder, without the
+    /// turntable filter.
+    Raw = 2,
+    /// TT+ and TT- buttons in place of the axis, see
+    /// [`DigitalTurntable`](crate::encoder::DigitalTurntable).
+    Digital = 3,
+}
+
+impl TurntableAxis {
+    /// Every format this firmware supports.
+    pub const ALL: &[TurntableAxis] = &[
+        TurntableAxis::Wrapped8,
+        TurntableAxis::Wrapped16,
+        TurntableAxis::Raw,
+        TurntableAxis::Digital,
+    ];
+}
+
+impl TryFrom<u8> for TurntableAxis {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|&axis| axis as u8 == value)
+            .ok_or(value)
+    }
+}
+
+/// Everything about the controller that can be changed at runtime.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Settings {
+    /// Debounce configuration for each button, in [`crate::button::OUTPUT_INDICES`] order.
+    pub debounce: [DebounceConfig; NUM_BUTTONS],
+    /// Turntable report steps per platter rotation.
+    pub tt_steps: u16,
+    /// Static colour of each button's LED.
+    pub key_colours: [RGB8; NUM_BUTTONS],
+    /// USB serial number, empty to use the firmware default.
+    pub serial_number: SerialNumber,
+    pub led_mode: LedMode,
+    /// USB personality, applied on the next boot.
+    pub input_mode: InputMode,
+    /// Time a key's light takes to fade out after release in reactive mode.
+    pub reactive_fade: Duration,
+    /// Button index (settings order) each LED follows, or [`NO_BUTTON`].
+    pub button_leds: [u8; MAX_BUTTON_LEDS],
+    pub ring_effect: RingEffect,
+    /// Main colour of the ring effects that use one.
+    pub ring_colour: RGB8,
+    /// Turntable axis format, applied on the next boot. The Konami profile
+    /// always uses [`TurntableAxis::Wrapped8`], see [`UsbProfile`].
+    pub tt_axis: TurntableAxis,
+    /// Time a digital turntable button stays pressed after the platter stops.
+    pub tt_hold: Duration,
+    /// Encoder counts the platter must move from rest to press a digital
+    /// turntable button. Reversing a held one follows the velocity estimator.
+    pub tt_threshold: u16,
+    /// Keyboard usage each input presses in keyboard mode, see
+    /// [`crate::keyboard`].
+    pub keyboard_keys: [u8; NUM_KEYBOARD_INPUTS],
+    /// What the turntable moves in Switch mode.
+    pub switch_tt: SwitchTurntable,
+    /// USB identity, applied on the next boot.
+    pub usb_profile: UsbProfile,
+    /// Identity used by [`UsbProfile::Custom`].
+    pub custom_identity: CustomIdentity,
+    /// Encoder counts ignored when the platter changes direction, see
+    /// [`crate::filter`].
+    pub tt_deadzone: u16,
+    /// Time constant of the turntable low-pass filter, zero for none.
+    pub tt_smoothing: Duration,
+    pub tt_curve: TurntableCurve,
+    /// Gains in percent used by [`TurntableCurve::Custom`].
+    pub tt_curve_gains: [u8; CURVE_POINTS],
+    /// Rated pulses per rotation of the turntable encoder, see
+    /// [`Self::counts_per_rotation`].
+    pub encoder_ppr: u16,
+    /// Fastest spin, in rotations per second, the encoder is sampled
+    /// quickly enough to follow.
+    pub tt_max_rps: u16,
+    /// Encoders wired up, each reported on its own joystick axis, applied on
+    /// the next boot. The first is the turntable, and the Konami profile
+    /// only reports that one.
+    pub encoders: u8,
+    /// Scaling of the encoders after the turntable, see
+    /// [`Self::encoder_scaling`]. The turntable's deadzone, smoothing and
+    /// curve don't apply to them.
+    pub extra_encoders: [EncoderScaling; MAX_ENCODERS - 1],
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            debounce: [DebounceConfig::default(); NUM_BUTTONS],
+            tt_steps: TARGET_STEPS as u16,
+            key_colours: core::array::from_fn(|i| {
+                DEFAULT_KEY_COLOURS[i % DEFAULT_KEY_COLOURS.len()]
+            }),
+            serial_number: SerialNumber::default(),
+            led_mode: LedMode::default(),
+            input_mode: InputMode::default(),
+            reactive_fade: DEFAULT_REACTIVE_FADE,
+            button_leds: core::array::from_fn(|i| i as u8),
+            ring_effect: RingEffect::default(),
+            ring_colour: RGB8::new(0xFF, 0, 0),
+            tt_axis: TurntableAxis::default(),
+            tt_hold: DEFAULT_TT_HOLD,
+            tt_threshold: DEFAULT_TT_THRESHOLD,
+            keyboard_keys: DEFAULT_KEYBOARD_KEYS,
+            switch_tt: SwitchTurntable::default(),
+            usb_profile: UsbProfile::default(),
+            custom_identity: CustomIdentity::default(),
+            tt_deadzone: 0,
+            tt_smoothing: Duration::from_ticks(0),
+            tt_curve: TurntableCurve::default(),
+            tt_curve_gains: [UNITY_GAIN; CURVE_POINTS],
+            encoder_ppr: DEFAULT_ENCODER_PPR,
+            tt_max_rps: DEFAULT_MAX_ROTATIONS_PER_SECOND,
+            encoders: 1,
+            extra_encoders: [EncoderScaling::default(); MAX_ENCODERS - 1],
+        }
+    }
+}
+
+impl Settings {
+    /// Quadrature counts per platter rotation.
+    pub fn counts_per_rotation(&self) -> i32 {
+        self.encoder_scaling(0).counts_per_rotation()
+    }
+
+    /// Scaling of encoder `index`, the turntable being encoder 0.
+    pub fn encoder_scaling(&self, index: usize) -> EncoderScaling {
+        match index.checked_sub(1) {
+            None => EncoderScaling {
+                ppr: self.encoder_ppr,
+                steps: self.tt_steps,
+            },
+            Some(i) => self.extra_encoders[i],
+        }
+    }
+
+    /// Encodes the settings into `buf`, returning the number of bytes used.
+    pub fn encode(&self, buf: &mut [u8]) -> Option<usize> {
+        let mut w = Writer::new(buf);
+
+        for config in &self.debounce {
+            write_debounce(&mut w, config);
+        }
+        w.u16(self.tt_steps);
+        for colour in &self.key_colours {
+            write_colour(&mut w, colour);
+        }
+        w.u8(self.serial_number.len);
+        w.bytes(&self.serial_number.bytes);
+        w.u8(self.led_mode as u8);
+        w.u8(self.input_mode as u8);
+        w.u16(duration_to_ms(self.reactive_fade));
+        w.bytes(&self.button_leds);
+        w.u8(self.ring_effect as u8);
+        write_colour(&mut w, &self.ring_colour);
+        w.u8(self.tt_axis as u8);
+        w.u16(duration_to_ms(self.tt_hold));
+        w.u16(self.tt_threshold);
+        w.bytes(&self.keyboard_keys);
+        w.u8(self.switch_tt as u8);
+        w.u8(self.usb_profile as u8);
+        self.custom_identity.encode(&mut w);
+        w.u16(self.tt_deadzone);
+        w.u16(duration_to_ms(self.tt_smoothing));
+        w.u8(self.tt_curve as u8);
+        w.bytes(&self.tt_curve_gains);
+        w.u16(self.encoder_ppr);
+        w.u16(self.tt_max_rps);
+        w.u8(self.encoders);
+        for scaling in &self.extra_encoders {
+            w.u16(scaling.ppr);
+            w.u16(scaling.steps);
+        }
+
+        w.finish()
+    }
+
+    /// Decodes settings stored with layout `version`.
+    ///
+    /// Fields missing from the end of `data` keep their defaults, as do
+    /// fields holding values this firmware would not accept.
+    pub fn decode(version: u16, data: &[u8]) -> Self {
+        let mut settings = Self::default();
+        let _ = settings.read_fields(&mut Reader::new(data));
+        migrate(version, &mut settings);
+        settings
+    }
+
+    fn read_fields(&mut self, r: &mut Reader) -> Option<()> {
+        for config in &mut self.debounce {
+            if let Ok(decoded) = read_debounce(r)? {
+                *config = decoded;
+            }
+        }
+
+        let tt_steps = r.u16()?;
+        if tt_steps != 0 {
+            self.tt_steps = tt_steps;
+        }
+
+        for colour in &mut self.key_colours {
+            *colour = read_colour(r)?;
+        }
+
+        let len = r.u8()?;
+        let bytes = r.bytes()?;
+        if let Some(serial) = SerialNumber::from_raw(len, bytes) {
+            self.serial_number = serial;
+        }
+
+        if let Ok(led_mode) = LedMode::try_from(r.u8()?) {
+            self.led_mode = led_mode;
+        }
+        if let Ok(input_mode) = InputMode::try_from(r.u8()?) {
+            self.input_mode = input_mode;
+        }
+
+        self.reactive_fade = Duration::from_millis(r.u16()? as u64);
+        let leds: [u8; MAX_BUTTON_LEDS] = r.bytes()?;
+        for (led, decoded) in self.button_leds.iter_mut().zip(leds) {
+            if is_valid_led_button(decoded) {
+                *led = decoded;
+            }
+        }
+
+        if let Ok(effect) = RingEffect::try_from(r.u8()?) {
+            self.ring_effect = effect;
+        }
+        self.ring_colour = read_colour(r)?;
+
+        if let Ok(axis) = TurntableAxis::try_from(r.u8()?) {
+            self.tt_axis = axis;
+        }
+        self.tt_hold = Duration::from_millis(r.u16()? as u64);
+        let tt_threshold = r.u16()?;
+        if tt_threshold != 0 {
+            self.tt_threshold = tt_threshold;
+        }
+
+        let keys: [u8; NUM_KEYBOARD_INPUTS] = r.bytes()?;
+        for (key, decoded) in self.keyboard_keys.iter_mut().zip(keys) {
+            if is_valid_key(decoded) {
+                *key = decoded;
+            }
+        }
+
+        if let Ok(tt) = SwitchTurntable::try_from(r.u8()?) {
+            self.switch_tt = tt;
+        }
+
+        if let Ok(profile) = UsbProfile::try_from(r.u8()?) {
+            self.usb_profile = profile;
+        }
+        if let Some(identity) = CustomIdentity::decode(r)? {
+            self.custom_identity = identity;
+        }
+
+        self.tt_deadzone = r.u16()?;
+        self.tt_smoothing = Duration::from_millis(r.u16()? as u64);
+        if let Ok(curve) = TurntableCurve::try_from(r.u8()?) {
+            self.tt_curve = curve;
+        }
+        self.tt_curve_gains = r.bytes()?;
+
+        let encoder_ppr = r.u16()?;
+        if encoder_ppr != 0 {
+            self.encoder_ppr = encoder_ppr;
+        }
+        let tt_max_rps = r.u16()?;
+        if tt_max_rps != 0 {
+            self.tt_max_rps = tt_max_rps;
+        }
+
+        let encoders = r.u8()?;
+        if (1..=MAX_ENCODERS as u8).contains(&encoders) {
+            self.encoders = encoders;
+        }
+        for scaling in &mut self.extra_encoders {
+            let ppr = r.u16()?;
+            let steps = r.u16()?;
+            if ppr != 0 && steps != 0 {
+                *scaling = EncoderScaling { ppr, steps };
+            }
+        }
+
+        Some(())
+    }
+}
+
+/// Fixes up settings decoded from an older layout `version`.
+fn migrate(version: u16, _settings: &mut Settings) {
+    // Version 1 is the first layout so there is nothing to convert yet. Later
+    // layouts add `if version < N { ... }` steps here, oldest first.
+    let _ = version;
+}
+
+fn duration_to_us(d: Duration) -> u16 {
+    d.as_micros().min(u16::MAX as u64) as u16
+}
+
+pub(crate) fn duration_to_ms(d: Duration) -> u16 {
+    d.as_millis().min(u16::MAX as u64) as u16
+}
+
+pub(crate) fn write_debounce(w: &mut Writer, config: &DebounceConfig) {
+    w.u8(config.algorithm as u8);
+    w.u16(duration_to_us(config.press));
+    w.u16(duration_to_us(config.release));
+}
+
+/// Reads one debounce config, giving `Some(Err)` if it was present but used
+/// an unknown algorithm.
+pub(crate) fn read_debounce(r: &mut Reader) -> Option<Result<DebounceConfig, u8>> {
+    let algorithm = r.u8()?;
+    let press = r.u16()?;
+    let release = r.u16()?;
+
+    Some(
+        DebounceAlgorithm::try_from(algorithm).map(|algorithm| DebounceConfig {
+            algorithm,
+            press: Duration::from_micros(press as u64),
+            release: Duration::from_micros(release as u64),
+        }),
+    )
+}
+
+pub(crate) fn write_colour(w: &mut Writer, colour: &RGB8) {
+    w.bytes(&[colour.r, colour.g, colour.b]);
+}
+
+pub(crate) fn read_colour(r: &mut Reader) -> Option<RGB8> {
+    let [r, g, b] = r.bytes()?;
+    Some(RGB8::new(r, g, b))
+}
+
+/// A short printable ASCII string stored inline in the settings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SerialNumber {
+    len: u8,
+    bytes: [u8; MAX_SERIAL_LEN],
+}
+
+impl SerialNumber {
+    pub fn new(s: &str) -> Option<Self> {
+        let mut bytes = [0; MAX_SERIAL_LEN];
+        bytes.get_mut(..s.len())?.copy_from_slice(s.as_bytes());
+        Self::from_raw(s.len() as u8, bytes)
+    }
+
+    /// Formats a flash chip's 64-bit unique ID as 16 upper case hex digits.
+    pub fn from_unique_id(id: &[u8; 8]) -> Self {
+        const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+        let mut bytes = [0; MAX_SERIAL_LEN];
+        for (digits, byte) in bytes.chunks_exact_mut(2).zip(id) {
+            digits[0] = HEX[(byte >> 4) as usize];
+            digits[1] = HEX[(byte & 0xF) as usize];
+        }
+        Self {
+            len: MAX_SERIAL_LEN as u8,
+            bytes,
+        }
+    }
+
+    pub(crate) fn from_raw(len: u8, bytes: [u8; MAX_SERIAL_LEN]) -> Option<Self> {
+        let valid = bytes
+            .get(..len as usize)?
+            .iter()
+            .all(|b| b.is_ascii_graphic());
+
+        valid.then_some(Self { len, bytes })
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only ever built from printable ASCII
+        core::str::from_utf8(&self.bytes[..self.len as usize]).unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub(crate) fn raw(&self) -> (u8, &[u8; MAX_SERIAL_LEN]) {
+        (self.len, &self.bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn custom() -> Settings {
+        let mut settings = Settings::default();
+        settings.debounce[8] = DebounceConfig {
+            algorithm: DebounceAlgorithm::Asymmetric,
+            press: Duration::from_millis(2),
+            release: Duration::from_millis(12),
+        };
+        settings.tt_steps = 256;
+        settings.key_colours[0] = RGB8::new(1, 2, 3);
+        settings.serial_number = SerialNumber::new("DP-1P").unwrap();
+        settings.led_mode = LedMode::Reactive;
+        settings.reactive_fade = Duration::from_millis(750);
+        settings.button_leds[0] = 6;
+        settings.button_leds[1] = NO_BUTTON;
+        settings.ring_effect = RingEffect::VelocityTrail;
+        settings.ring_colour = RGB8::new(0, 0x80, 0xFF);
+        settings.tt_axis = TurntableAxis::Digital;
+        settings.tt_hold = Duration::from_millis(40);
+        settings.tt_threshold = 3;
+        settings.input_mode = InputMode::Keyboard;
+        settings.keyboard_keys[12] = 0x2C;
+        settings.switch_tt = SwitchTurntable::Dpad;
+        settings.usb_profile = UsbProfile::Custom;
+        settings.custom_identity.pid = 0x4242;
+        settings.tt_deadzone = 2;
+        settings.tt_smoothing = Duration::from_millis(8);
+        settings.tt_curve = TurntableCurve::Custom;
+        settings.tt_curve_gains[7] = 250;
+        settings.encoder_ppr = 600;
+        settings.tt_max_rps = 30;
+        settings.encoders = 3;
+        settings.extra_encoders[1] = EncoderScaling { ppr: 24, steps: 96 };
+        settings
+    }
+
+    #[test]
+    fn round_trip() {
+        let settings = custom();
+        let mut buf = [0u8; MAX_ENCODED_LEN];
+        let len = settings.encode(&mut buf).unwrap();
+
+        assert_eq!(Settings::decode(SETTINGS_VERSION, &buf[..len]), settings);
+    }
+
+    #[test]
+    fn truncated_record_keeps_defaults_for_missing_fields() {
+        let settings = custom();
+        let mut buf = [0u8; MAX_ENCODED_LEN];
+        settings.encode(&mut buf).unwrap();
+
+        // Cut off inside the serial number, as if written before it existed
+        let serial_start = NUM_BUTTONS * 5 + 2 + NUM_BUTTONS * 3;
+        let decoded = Settings::decode(SETTINGS_VERSION, &buf[..serial_start + 1]);
+
+        assert_eq!(decoded.debounce, settings.debounce);
+        assert_eq!(decoded.key_colours, settings.key_colours);
+        assert_eq!(decoded.serial_number, SerialNumber::default());
+        assert_eq!(decoded.led_mode, LedMode::default());
+        assert_eq!(decoded.button_leds, Settings::default().button_leds);
+    }
+
+    #[test]
+    fn longer_record_from_newer_firmware_decodes() {
+        let settings = custom();
+        let mut buf = [0xAAu8; MAX_ENCODED_LEN];
+        let len = settings.encode(&mut buf).unwrap();
+
+        assert_eq!(
+            Settings::decode(SETTINGS_VERSION + 1, &buf[..len + 16]),
+            settings
+        );
+    }
+
+    #[test]
+    fn invalid_values_are_ignored() {
+        let mut buf = [0u8; MAX_ENCODED_LEN];
+        let len = custom().encode(&mut buf).unwrap();
+        // Unknown algorithm for the first button, a zero step count and the
+        // first LED following a button that doesn't exist
+        buf[0] = 0x7F;
+        buf[NUM_BUTTONS * 5] = 0;
+        buf[NUM_BUTTONS * 5 + 1] = 0;
+        let button_leds_start = NUM_BUTTONS * 5 + 2 + NUM_BUTTONS * 3 + 1 + MAX_SERIAL_LEN + 4;
+        buf[button_leds_start] = NUM_BUTTONS as u8;
+
+        let decoded = Settings::decode(SETTINGS_VERSION, &buf[..len]);
+        assert_eq!(decoded.debounce[0], DebounceConfig::default());
+        assert_eq!(decoded.tt_steps, TARGET_STEPS as u16);
+        assert_eq!(decoded.button_leds[0], Settings::default().button_leds[0]);
+        assert_eq!(decoded.button_leds[1], NO_BUTTON);
+    }
+
+    #[test]
+    fn encoder_scaling_starts_with_the_turntable() {
+        let settings = custom();
+
+        assert_eq!(
+            settings.encoder_scaling(0),
+            EncoderScaling {
+                ppr: 600,
+                steps: 256
+            }
+        );
+        assert_eq!(settings.encoder_scaling(1), EncoderScaling::default());
+        assert_eq!(settings.encoder_scaling(2).counts_per_rotation(), 96);
+    }
+
+    #[test]
+    fn default_fits() {
+        let mut buf = [0u8; MAX_ENCODED_LEN];
+        assert!(Settings::default().encode(&mut buf).is_some());
+    }
+
+    #[test]
+    fn serial_number_rejects_bad_strings() {
+        assert!(SerialNumber::new("0123456789ABCDEF0").is_none());
+        assert!(SerialNumber::new("has space").is_none());
+        assert_eq!(SerialNumber::new("ABC").unwrap().as_str(), "ABC");
+    }
+
+    #[test]
+    fn serial_number_from_unique_id() {
+        let id = [0xE6, 0x60, 0x58, 0x38, 0x83, 0x1A, 0x2B, 0x05];
+
+        assert_eq!(
+            SerialNumber::from_unique_id(&id).as_str(),
+            "E6605838831A2B05"
+        );
+    }
+}
@@ -0,0 +1,940 @@
+//! Line-based configuration shell served over the USB serial port.
+//!
+//! Everything is plain text so any serial terminal or script can drive it:
+//!
+//! ```text
+//! help                  list the commands
+//! get [key]             print one setting, or all of them
+//! set <key> <value>     change a setting, without saving it
+//! status                buttons, raw encoder count, reversals and USB state
+//! save                  write the settings to flash
+//! defaults              reset the settings to their defaults, without saving
+//! reboot                restart the controller
+//! bootloader            restart into the USB mass storage bootloader
+//! ```
+//!
+//! Setting values use the same names as the host tool's TOML files, and run
+//! to the end of the line so USB strings can hold spaces. Setting `serial`
+//! to `-` clears the override; `usb_vid` and `usb_pid` are `0x..` hex.
+//!
+//! Per-button settings are keyed by name: `colour.<button>`,
+//! `debounce.<button>` (`<algorithm>,<press_us>,<release_us>`),
+//! `key.<input>` (a `0x..` keyboard usage or `none`) and `led.<n>` (the
+//! button LED `n` follows, or `none`).
+
+use core::fmt::{self, Write};
+
+use embassy_time::Duration;
+use smart_leds::RGB8;
+
+use crate::button::NUM_BUTTONS;
+use crate::debounce::{DebounceAlgorithm, DebounceConfig};
+use crate::encoder::MAX_ENCODERS;
+use crate::filter::{CURVE_POINTS, TurntableCurve};
+use crate::identity::{KONAMI_OVERRIDE_NOTE, UsbProfile, UsbString, konami_overrides};
+use crate::keyboard::{NO_KEY, NUM_KEYBOARD_INPUTS, is_valid_key};
+use crate::settings::{
+    InputMode, LedMode, MAX_BUTTON_LEDS, NO_BUTTON, RingEffect, SerialNumber, Settings,
+    TurntableAxis,
+};
+use crate::switch::SwitchTurntable;
+
+/// Longest line the shell accepts, excluding the line ending.
+pub const MAX_LINE_LEN: usize = 64;
+
+const BUTTON_NAMES: [&str; NUM_BUTTONS] = [
+    "key1", "key2", "key3", "key4", "key5", "key6", "key7", "e1", "e2", "e3", "e4",
+];
+
+const KEYBOARD_INPUT_NAMES: [&str; NUM_KEYBOARD_INPUTS] = [
+    "key1", "key2", "key3", "key4", "key5", "key6", "key7", "e1", "e2", "e3", "e4", "tt-up",
+    "tt-down",
+];
+
+/// LEDs are numbered from 1, in wiring order.
+const LED_NAMES: [&str; MAX_BUTTON_LEDS] =
+    ["1", "2", "3", "4", "5", "6", "7", "8", "9", "10", "11"];
+
+const INPUT_MODES: &[(&str, InputMode)] = &[
+    ("joystick", InputMode::Joystick),
+    ("keyboard", InputMode::Keyboard),
+    ("switch", InputMode::Switch),
+];
+
+const TURNTABLE_AXES: &[(&str, TurntableAxis)] = &[
+    ("8-bit", TurntableAxis::Wrapped8),
+    ("16-bit", TurntableAxis::Wrapped16),
+    ("raw", TurntableAxis::Raw),
+    ("digital", TurntableAxis::Digital),
+];
+
+const TURNTABLE_CURVES: &[(&str, TurntableCurve)] = &[
+    ("linear", TurntableCurve::Linear),
+    ("accelerated", TurntableCurve::Accelerated),
+    ("custom", TurntableCurve::Custom),
+];
+
+const SWITCH_TURNTABLES: &[(&str, SwitchTurntable)] = &[
+    ("stick", SwitchTurntable::Stick),
+    ("dpad", SwitchTurntable::Dpad),
+];
+
+const USB_PROFILES: &[(&str, UsbProfile)] = &[
+    ("konami", UsbProfile::Konami),
+    ("open-source", UsbProfile::OpenSource),
+    ("custom", UsbProfile::Custom),
+];
+
+const DEBOUNCE_ALGORITHMS: &[(&str, DebounceAlgorithm)] = &[
+    ("eager", DebounceAlgorithm::Eager),
+    ("deferred", DebounceAlgorithm::Deferred),
+    ("asymmetric", DebounceAlgorithm::Asymmetric),
+];
+
+const LED_MODES: &[(&str, LedMode)] =
+    &[("static", LedMode::Static), ("reactive", LedMode::Reactive)];
+
+const RING_EFFECTS: &[(&str, RingEffect)] = &[
+    ("spinning-dot", RingEffect::SpinningDot),
+    ("rainbow", RingEffect::Rainbow),
+    ("breathing", RingEffect::Breathing),
+    ("velocity-trail", RingEffect::VelocityTrail),
+    ("scratch-flash", RingEffect::ScratchFlash),
+];
+
+/// Settings reachable with `get` and `set`, besides the [`Table`] entries.
+const KEYS: &[&str] = &[
+    "input_mode",
+    "tt_steps",
+    "tt_axis",
+    "tt_hold_ms",
+    "tt_threshold",
+    "tt_deadzone",
+    "tt_smoothing_ms",
+    "tt_curve",
+    "tt_curve_gains",
+    "encoder_ppr",
+    "tt_max_rps",
+    "encoders",
+    "encoder_2_ppr",
+    "encoder_2_steps",
+    "encoder_3_ppr",
+    "encoder_3_steps",
+    "encoder_4_ppr",
+    "encoder_4_steps",
+    "switch_tt",
+    "led_mode",
+    "reactive_fade_ms",
+    "ring_effect",
+    "ring_colour",
+    "serial",
+    "usb_profile",
+    "usb_vid",
+    "usb_pid",
+    "usb_manufacturer",
+    "usb_product",
+];
+
+/// Value for `key.<input>` and `led.<n>` that turns them off.
+const NONE: &str = "none";
+
+const MAX_ENCODERS_U8: u8 = MAX_ENCODERS as u8;
+
+const HELP: &str = "\
+help                  list the commands\r
+get [key]             print one setting, or all of them\r
+set <key> <value>     change a setting, without saving it\r
+status                buttons, raw encoder count, reversals and USB state\r
+save                  write the settings to flash\r
+defaults              reset the settings to their defaults, without saving\r
+reboot                restart the controller\r
+bootloader            restart into the USB mass storage bootloader\r
+";
+
+/// USB device state, as last reported by the USB stack.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum UsbState {
+    #[default]
+    Disabled = 0,
+    /// Powered, waiting for an address.
+    Default = 1,
+    Addressed = 2,
+    Configured = 3,
+    Suspended = 4,
+}
+
+impl UsbState {
+    pub const ALL: &[UsbState] = &[
+        UsbState::Disabled,
+        UsbState::Default,
+        UsbState::Addressed,
+        UsbState::Configured,
+        UsbState::Suspended,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Disabled => "disabled",
+            Self::Default => "default",
+            Self::Addressed => "addressed",
+            Self::Configured => "configured",
+            Self::Suspended => "suspended",
+        }
+    }
+}
+
+impl TryFrom<u8> for UsbState {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|&state| state as u8 == value)
+            .ok_or(value)
+    }
+}
+
+/// Live controller state shown by `status`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Status {
+    /// Debounced button bitmask, as sent to the host.
+    pub buttons: u16,
+    /// Raw quadrature count.
+    pub encoder: i32,
+    /// Turntable direction changes since boot, wrapping.
+    pub reversals: u32,
+    pub usb: UsbState,
+}
+
+/// Settings holding a value per button, keyboard input or LED, keyed
+/// `<prefix><name>`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Table {
+    Colour,
+    Debounce,
+    Key,
+    Led,
+}
+
+impl Table {
+    const ALL: [Table; 4] = [Table::Colour, Table::Debounce, Table::Key, Table::Led];
+
+    fn prefix(self) -> &'static str {
+        match self {
+            Self::Colour => "colour.",
+            Self::Debounce => "debounce.",
+            Self::Key => "key.",
+            Self::Led => "led.",
+        }
+    }
+
+    /// Entry names, in settings order.
+    fn names(self) -> &'static [&'static str] {
+        match self {
+            Self::Colour | Self::Debounce => &BUTTON_NAMES,
+            Self::Key => &KEYBOARD_INPUT_NAMES,
+            Self::Led => &LED_NAMES,
+        }
+    }
+
+    fn entry_kind(self) -> &'static str {
+        match self {
+            Self::Colour | Self::Debounce => "button",
+            Self::Key => "input",
+            Self::Led => "LED",
+        }
+    }
+
+    /// Splits a key into its table and entry name.
+    fn split(key: &str) -> Option<(Self, &str)> {
+        Self::ALL
+            .into_iter()
+            .find_map(|table| Some((table, key.strip_prefix(table.prefix())?)))
+    }
+
+    fn index(self, name: &str) -> Option<usize> {
+        self.names().iter().position(|&n| n == name)
+    }
+}
+
+/// What the caller has to do after a command ran.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Action {
+    /// The settings were changed and should be published.
+    SettingsChanged,
+    Save,
+    Reboot,
+    Bootloader,
+}
+
+/// Collects typed bytes into lines.
+pub struct LineBuffer {
+    buf: [u8; MAX_LINE_LEN],
+    len: usize,
+    overflowed: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineError {
+    TooLong,
+    NotUtf8,
+}
+
+impl LineBuffer {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; MAX_LINE_LEN],
+            len: 0,
+            overflowed: false,
+        }
+    }
+
+    /// Adds a byte, returning the line once it ends. Empty lines, as left
+    /// between `\r` and `\n`, are skipped.
+    pub fn push(&mut self, byte: u8) -> Option<Result<&str, LineError>> {
+        match byte {
+            b'\r' | b'\n' => {
+                let (len, overflowed) = (self.len, self.overflowed);
+                self.len = 0;
+                self.overflowed = false;
+
+                if overflowed {
+                    Some(Err(LineError::TooLong))
+                } else if len == 0 {
+                    None
+                } else {
+                    Some(core::str::from_utf8(&self.buf[..len]).map_err(|_| LineError::NotUtf8))
+                }
+            }
+            // Backspace and delete
+            0x08 | 0x7F => {
+                self.len = self.len.saturating_sub(1);
+                None
+            }
+            _ => {
+                match self.buf.get_mut(self.len) {
+                    Some(slot) => {
+                        *slot = byte;
+                        self.len += 1;
+                    }
+                    None => self.overflowed = true,
+                }
+                None
+            }
+        }
+    }
+}
+
+impl Default for LineBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Runs one command line, writing its reply to `out`.
+pub fn execute(
+    line: &str,
+    settings: &mut Settings,
+    status: &Status,
+    out: &mut impl Write,
+) -> Result<Option<Action>, fmt::Error> {
+    let mut words = line.split_whitespace();
+    let Some(command) = words.next() else {
+        return Ok(None);
+    };
+    let args = (words.next(), words.next(), words.next());
+
+    match (command, args) {
+        ("help", (None, ..)) => out.write_str(HELP)?,
+        ("get", (None, ..)) => {
+            for key in KEYS {
+                get(key, settings, out)?;
+            }
+            for table in Table::ALL {
+                for name in table.names() {
+                    get_entry(table, name, settings, out)?;
+                }
+            }
+        }
+        ("get", (Some(key), None, _)) => get(key, settings, out)?,
+        ("set", (Some(key), Some(_), _)) => {
+            let value = skip_words(line, 2);
+            let mut updated = settings.clone();
+            match set(key, value, &mut updated) {
+                Ok(()) => {
+                    let changed = updated != *settings;
+                    *settings = updated;
+                    get(key, settings, out)?;
+                    if matches!(key, "tt_axis" | "encoders" | "usb_profile")
+                        && konami_overrides(settings)
+                    {
+                        write!(out, "note: {KONAMI_OVERRIDE_NOTE}\r\n")?;
+                    }
+                    return Ok(changed.then_some(Action::SettingsChanged));
+                }
+                Err(e) => write!(out, "error: {e}\r\n")?,
+            }
+        }
+        ("status", (None, ..)) => write!(
+            out,
+            "buttons 0x{:04x}\r\nencoder {}\r\nreversals {}\r\nusb {}\r\n",
+            status.buttons,
+            status.encoder,
+            status.reversals,
+            status.usb.name()
+        )?,
+        ("save", (None, ..)) => {
+            out.write_str("saving\r\n")?;
+            return Ok(Some(Action::Save));
+        }
+        ("defaults", (None, ..)) => {
+            *settings = Settings::default();
+            out.write_str("defaults loaded, not saved\r\n")?;
+            return Ok(Some(Action::SettingsChanged));
+        }
+        ("reboot", (None, ..)) => {
+            out.write_str("rebooting\r\n")?;
+            return Ok(Some(Action::Reboot));
+        }
+        ("bootloader", (None, ..)) => {
+            out.write_str("entering bootloader\r\n")?;
+            return Ok(Some(Action::Bootloader));
+        }
+        ("help" | "get" | "set" | "status" | "save" | "defaults" | "reboot" | "bootloader", _) => {
+            out.write_str("error: wrong arguments, see help\r\n")?
+        }
+        _ => write!(out, "error: unknown command {command:?}, see help\r\n")?,
+    }
+    Ok(None)
+}
+
+/// Why a `set` was refused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SetError {
+    UnknownKey,
+    InvalidValue,
+}
+
+impl fmt::Display for SetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownKey => f.write_str("unknown key, see get"),
+            Self::InvalidValue => f.write_str("invalid value"),
+        }
+    }
+}
+
+fn get(key: &str, settings: &Settings, out: &mut impl Write) -> fmt::Result {
+    if let Some((table, name)) = Table::split(key) {
+        return get_entry(table, name, settings, out);
+    }
+
+    write!(out, "{key} ")?;
+    match key {
+        "input_mode" => out.write_str(name_of(INPUT_MODES, settings.input_mode))?,
+        "tt_steps" => write!(out, "{}", settings.tt_steps)?,
+        "tt_axis" => out.write_str(name_of(TURNTABLE_AXES, settings.tt_axis))?,
+        "tt_hold_ms" => write!(out, "{}", settings.tt_hold.as_millis())?,
+        "tt_threshold" => write!(out, "{}", settings.tt_threshold)?,
+        "tt_deadzone" => write!(out, "{}", settings.tt_deadzone)?,
+        "tt_smoothing_ms" => write!(out, "{}", settings.tt_smoothing.as_millis())?,
+        "tt_curve" => out.write_str(name_of(TURNTABLE_CURVES, settings.tt_curve))?,
+        "tt_curve_gains" => write_gains(out, &settings.tt_curve_gains)?,
+        "encoder_ppr" => write!(out, "{}", settings.encoder_ppr)?,
+        "tt_max_rps" => write!(out, "{}", settings.tt_max_rps)?,
+        "switch_tt" => out.write_str(name_of(SWITCH_TURNTABLES, settings.switch_tt))?,
+        "led_mode" => out.write_str(name_of(LED_MODES, settings.led_mode))?,
+        "reactive_fade_ms" => write!(out, "{}", settings.reactive_fade.as_millis())?,
+        "ring_effect" => out.write_str(name_of(RING_EFFECTS, settings.ring_effect))?,
+        "ring_colour" => write_colour(out, settings.ring_colour)?,
+        "serial" => out.write_str(settings.serial_number.as_str())?,
+        "usb_profile" => out.write_str(name_of(USB_PROFILES, settings.usb_profile))?,
+        "usb_vid" => write!(out, "0x{:04x}", settings.custom_identity.vid)?,
+        "usb_pid" => write!(out, "0x{:04x}", settings.custom_identity.pid)?,
+        "usb_manufacturer" => out.write_str(settings.custom_identity.manufacturer.as_str())?,
+        "usb_product" => out.write_str(settings.custom_identity.product.as_str())?,
+        "encoders" => write!(out, "{}", settings.encoders)?,
+        _ => match extra_encoder(key) {
+            Some((i, "ppr")) => write!(out, "{}", settings.extra_encoders[i].ppr)?,
+            Some((i, "steps")) => write!(out, "{}", settings.extra_encoders[i].steps)?,
+            _ => out.write_str("error: unknown key")?,
+        },
+    }
+    out.write_str("\r\n")
+}
+
+fn get_entry(table: Table, name: &str, settings: &Settings, out: &mut impl Write) -> fmt::Result {
+    write!(out, "{}{name} ", table.prefix())?;
+    match (table, table.index(name)) {
+        (Table::Colour, Some(i)) => write_colour(out, settings.key_colours[i])?,
+        (Table::Debounce, Some(i)) => write_debounce(out, &settings.debounce[i])?,
+        (Table::Key, Some(i)) => write_key(out, settings.keyboard_keys[i])?,
+        (Table::Led, Some(i)) => {
+            let button = BUTTON_NAMES.get(settings.button_leds[i] as usize);
+            out.write_str(button.unwrap_or(&NONE))?
+        }
+        (_, None) => write!(out, "error: unknown {}", table.entry_kind())?,
+    }
+    out.write_str("\r\n")
+}
+
+fn set(key: &str, value: &str, settings: &mut Settings) -> Result<(), SetError> {
+    if let Some((table, name)) = Table::split(key) {
+        let i = table.index(name).ok_or(SetError::UnknownKey)?;
+        match table {
+            Table::Colour => settings.key_colours[i] = parse_colour(value)?,
+            Table::Debounce => settings.debounce[i] = parse_debounce(value)?,
+            Table::Key => settings.keyboard_keys[i] = parse_key(value)?,
+            Table::Led if value == NONE => settings.button_leds[i] = NO_BUTTON,
+            Table::Led => {
+                let button = BUTTON_NAMES.iter().position(|&n| n == value);
+                settings.button_leds[i] = button.ok_or(SetError::InvalidValue)? as u8
+            }
+        }
+        return Ok(());
+    }
+
+    match key {
+        "input_mode" => settings.input_mode = parse_name(INPUT_MODES, value)?,
+        "tt_steps" => settings.tt_steps = parse_nonzero(value)?,
+        "tt_axis" => settings.tt_axis = parse_name(TURNTABLE_AXES, value)?,
+        "tt_hold_ms" => settings.tt_hold = parse_ms(value)?,
+        "tt_threshold" => settings.tt_threshold = parse_nonzero(value)?,
+        "tt_deadzone" => {
+            settings.tt_deadzone = value.parse().map_err(|_| SetError::InvalidValue)?
+        }
+        "tt_smoothing_ms" => settings.tt_smoothing = parse_ms(value)?,
+        "tt_curve" => settings.tt_curve = parse_name(TURNTABLE_CURVES, value)?,
+        "tt_curve_gains" => settings.tt_curve_gains = parse_gains(value)?,
+        "encoder_ppr" => settings.encoder_ppr = parse_nonzero(value)?,
+        "tt_max_rps" => settings.tt_max_rps = parse_nonzero(value)?,
+        "switch_tt" => settings.switch_tt = parse_name(SWITCH_TURNTABLES, value)?,
+        "led_mode" => settings.led_mode = parse_name(LED_MODES, value)?,
+        "reactive_fade_ms" => settings.reactive_fade = parse_ms(value)?,
+        "ring_effect" => settings.ring_effect = parse_name(RING_EFFECTS, value)?,
+        "ring_colour" => settings.ring_colour = parse_colour(value)?,
+        // "-" goes back to the flash chip's unique ID
+        "serial" if value == "-" => settings.serial_number = SerialNumber::default(),
+        "serial" => {
+            settings.serial_number = SerialNumber::new(value).ok_or(SetError::InvalidValue)?
+        }
+        "usb_profile" => settings.usb_profile = parse_name(USB_PROFILES, value)?,
+        "usb_vid" => settings.custom_identity.vid = parse_hex(value)?,
+        "usb_pid" => settings.custom_identity.pid = parse_hex(value)?,
+        "usb_manufacturer" => {
+            settings.custom_identity.manufacturer =
+                UsbString::new(value).ok_or(SetError::InvalidValue)?
+        }
+        "usb_product" => {
+            settings.custom_identity.product =
+                UsbString::new(value).ok_or(SetError::InvalidValue)?
+        }
+        "encoders" => match value.parse() {
+            Ok(n @ 1..=MAX_ENCODERS_U8) => settings.encoders = n,
+            _ => return Err(SetError::InvalidValue),
+        },
+        _ => match extra_encoder(key) {
+            Some((i, "ppr")) => settings.extra_encoders[i].ppr = parse_nonzero(value)?,
+            Some((i, "steps")) => settings.extra_encoders[i].steps = parse_nonzero(value)?,
+            _ => return Err(SetError::UnknownKey),
+        },
+    }
+    Ok(())
+}
+
+/// The rest of `line` after its first `n` words, trimmed.
+fn skip_words(line: &str, n: usize) -> &str {
+    let mut rest = line.trim();
+    for _ in 0..n {
+        rest = rest
+            .trim_start_matches(|c: char| !c.is_whitespace())
+            .trim_start();
+    }
+    rest
+}
+
+/// Splits an `encoder_<n>_<field>` key into the encoder's index in
+/// [`Settings::extra_encoders`] and the field.
+fn extra_encoder(key: &str) -> Option<(usize, &str)> {
+    let (n, field) = key.strip_prefix("encoder_")?.split_once('_')?;
+    let n: usize = n.parse().ok()?;
+    let i = n.checked_sub(2).filter(|&i| i < MAX_ENCODERS - 1)?;
+    Some((i, field))
+}
+
+fn name_of<T: Copy + PartialEq>(table: &[(&'static str, T)], value: T) -> &'static str {
+    table
+        .iter()
+        .find(|&&(_, v)| v == value)
+        .map_or("?", |&(name, _)| name)
+}
+
+fn parse_name<T: Copy>(table: &[(&str, T)], name: &str) -> Result<T, SetError> {
+    table
+        .iter()
+        .find(|&&(n, _)| n == name)
+        .map(|&(_, value)| value)
+        .ok_or(SetError::InvalidValue)
+}
+
+fn parse_nonzero(value: &str) -> Result<u16, SetError> {
+    match value.parse() {
+        Ok(0) | Err(_) => Err(SetError::InvalidValue),
+        Ok(n) => Ok(n),
+    }
+}
+
+fn parse_hex(value: &str) -> Result<u16, SetError> {
+    let hex = value.strip_prefix("0x").ok_or(SetError::InvalidValue)?;
+    u16::from_str_radix(hex, 16).map_err(|_| SetError::InvalidValue)
+}
+
+fn parse_ms(value: &str) -> Result<Duration, SetError> {
+    let ms: u16 = value.parse().map_err(|_| SetError::InvalidValue)?;
+    Ok(Duration::from_millis(ms as u64))
+}
+
+/// Writes curve gains as a comma separated list.
+fn write_gains(out: &mut impl Write, gains: &[u8; CURVE_POINTS]) -> fmt::Result {
+    for (i, gain) in gains.iter().enumerate() {
+        if i > 0 {
+            out.write_char(',')?;
+        }
+        write!(out, "{gain}")?;
+    }
+    Ok(())
+}
+
+fn parse_gains(value: &str) -> Result<[u8; CURVE_POINTS], SetError> {
+    let mut gains = [0; CURVE_POINTS];
+    let mut parts = value.split(',');
+    for gain in &mut gains {
+        let part = parts.next().ok_or(SetError::InvalidValue)?;
+        *gain = part.parse().map_err(|_| SetError::InvalidValue)?;
+    }
+    match parts.next() {
+        Some(_) => Err(SetError::InvalidValue),
+        None => Ok(gains),
+    }
+}
+
+/// Writes a debounce config as `<algorithm>,<press_us>,<release_us>`.
+fn write_debounce(out: &mut impl Write, config: &DebounceConfig) -> fmt::Result {
+    write!(
+        out,
+        "{},{},{}",
+        name_of(DEBOUNCE_ALGORITHMS, config.algorithm),
+        config.press.as_micros(),
+        config.release.as_micros()
+    )
+}
+
+fn parse_debounce(value: &str) -> Result<DebounceConfig, SetError> {
+    let mut parts = value.split(',');
+    let mut next = || parts.next().ok_or(SetError::InvalidValue);
+    let algorithm = parse_name(DEBOUNCE_ALGORITHMS, next()?)?;
+    let press: u16 = next()?.parse().map_err(|_| SetError::InvalidValue)?;
+    let release: u16 = next()?.parse().map_err(|_| SetError::InvalidValue)?;
+    if parts.next().is_some() {
+        return Err(SetError::InvalidValue);
+    }
+    Ok(DebounceConfig {
+        algorithm,
+        press: Duration::from_micros(press as u64),
+        release: Duration::from_micros(release as u64),
+    })
+}
+
+fn write_key(out: &mut impl Write, key: u8) -> fmt::Result {
+    match key {
+        NO_KEY => out.write_str(NONE),
+        key => write!(out, "0x{key:02x}"),
+    }
+}
+
+/// Parses a keyboard usage. Key names are left to the host tool.
+fn parse_key(value: &str) -> Result<u8, SetError> {
+    if value == NONE {
+        return Ok(NO_KEY);
+    }
+    match u8::try_from(parse_hex(value)?) {
+        Ok(key) if is_valid_key(key) => Ok(key),
+        _ => Err(SetError::InvalidValue),
+    }
+}
+
+fn write_colour(out: &mut impl Write, colour: RGB8) -> fmt::Result {
+    write!(out, "#{:02x}{:02x}{:02x}", colour.r, colour.g, colour.b)
+}
+
+fn parse_colour(value: &str) -> Result<RGB8, SetError> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    if hex.len() != 6 {
+        return Err(SetError::InvalidValue);
+    }
+    let rgb = u32::from_str_radix(hex, 16).map_err(|_| SetError::InvalidValue)?;
+    let [_, r, g, b] = rgb.to_be_bytes();
+    Ok(RGB8::new(r, g, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::String;
+
+    fn run(line: &str, settings: &mut Settings) -> (Option<Action>, String) {
+        let status = Status {
+            buttons: 0x0103,
+            encoder: -42,
+            reversals: 7,
+            usb: UsbState::Configured,
+        };
+        let mut out = String::new();
+        let action = execute(line, settings, &status, &mut out).unwrap();
+        (action, out)
+    }
+
+    #[test]
+    fn get_and_set_settings() {
+        let mut settings = Settings::default();
+
+        let (action, out) = run("set ring_effect rainbow", &mut settings);
+        assert_eq!(action, Some(Action::SettingsChanged));
+        assert_eq!(out, "ring_effect rainbow\r\n");
+        assert_eq!(settings.ring_effect, RingEffect::Rainbow);
+
+        run("set colour.e2 #0080ff", &mut settings);
+        assert_eq!(settings.key_colours[8], RGB8::new(0, 0x80, 0xFF));
+        assert_eq!(
+            run("get colour.e2", &mut settings).1,
+            "colour.e2 #0080ff\r\n"
+        );
+
+        // Setting the same value again changes nothing
+        assert_eq!(run("set tt_steps 144", &mut settings).0, None);
+        assert_eq!(run("get tt_hold_ms", &mut settings).1, "tt_hold_ms 100\r\n");
+    }
+
+    #[test]
+    fn curve_gains_are_a_list() {
+        let mut settings = Settings::default();
+
+        let (_, out) = run(
+            "set tt_curve_gains 100,100,120,140,160,180,200,250",
+            &mut settings,
+        );
+        assert_eq!(out, "tt_curve_gains 100,100,120,140,160,180,200,250\r\n");
+        assert_eq!(settings.tt_curve_gains[7], 250);
+
+        for bad in ["100,100", "1,2,3,4,5,6,7,8,9", "1,2,3,4,5,6,7,256"] {
+            let line = std::format!("set tt_curve_gains {bad}");
+            assert_eq!(run(&line, &mut settings).1, "error: invalid value\r\n");
+        }
+    }
+
+    #[test]
+    fn extra_encoders_have_numbered_keys() {
+        let mut settings = Settings::default();
+
+        run("set encoders 3", &mut settings);
+        run("set encoder_3_ppr 24", &mut settings);
+        assert_eq!(settings.encoders, 3);
+        assert_eq!(settings.extra_encoders[1].ppr, 24);
+        assert_eq!(
+            run("get encoder_3_ppr", &mut settings).1,
+            "encoder_3_ppr 24\r\n"
+        );
+
+        for bad in ["encoders 0", "encoders 5", "encoder_2_steps 0"] {
+            let line = std::format!("set {bad}");
+            assert_eq!(run(&line, &mut settings).1, "error: invalid value\r\n");
+        }
+        for unknown in ["encoder_1_ppr", "encoder_5_ppr", "encoder_2_rps"] {
+            let line = std::format!("set {unknown} 10");
+            assert_eq!(
+                run(&line, &mut settings).1,
+                "error: unknown key, see get\r\n"
+            );
+        }
+    }
+
+    #[test]
+    fn per_button_settings() {
+        let mut settings = Settings::default();
+
+        let (_, out) = run("set debounce.e1 deferred,2000,8000", &mut settings);
+        assert_eq!(out, "debounce.e1 deferred,2000,8000\r\n");
+        assert_eq!(
+            settings.debounce[7],
+            DebounceConfig {
+                algorithm: DebounceAlgorithm::Deferred,
+                press: Duration::from_millis(2),
+                release: Duration::from_millis(8),
+            }
+        );
+
+        run("set key.tt-down 0x2c", &mut settings);
+        run("set key.key1 none", &mut settings);
+        assert_eq!(settings.keyboard_keys[12], 0x2C);
+        assert_eq!(run("get key.key1", &mut settings).1, "key.key1 none\r\n");
+
+        run("set led.2 none", &mut settings);
+        run("set led.3 e2", &mut settings);
+        assert_eq!(settings.button_leds[1], NO_BUTTON);
+        assert_eq!(settings.button_leds[2], 8);
+        assert_eq!(run("get led.2", &mut settings).1, "led.2 none\r\n");
+
+        for bad in [
+            "debounce.key1 eager,4000",
+            "debounce.key1 lazy,4000,4000",
+            "debounce.key1 eager,4000,4000,4000",
+            "key.key2 z",
+            "key.key2 0x02",
+            "led.1 key8",
+        ] {
+            let line = std::format!("set {bad}");
+            assert_eq!(run(&line, &mut settings).1, "error: invalid value\r\n");
+        }
+        for unknown in ["debounce.tt-up", "key.e5", "led.0", "led.12"] {
+            let line = std::format!("set {unknown} none");
+            assert_eq!(
+                run(&line, &mut settings).1,
+                "error: unknown key, see get\r\n"
+            );
+        }
+        assert_eq!(
+            run("get led.12", &mut settings).1,
+            "led.12 error: unknown LED\r\n"
+        );
+    }
+
+    #[test]
+    fn custom_usb_identity() {
+        let mut settings = Settings::default();
+
+        assert_eq!(
+            run("set usb_vid 0x1CCF", &mut settings).1,
+            "usb_vid 0x1ccf\r\n"
+        );
+        run("set usb_pid 0x8048", &mut settings);
+        let (_, out) = run("set usb_product  My IIDX  Controller ", &mut settings);
+        assert_eq!(out, "usb_product My IIDX  Controller\r\n");
+        assert_eq!(settings.custom_identity.vid, 0x1CCF);
+        assert_eq!(settings.custom_identity.pid, 0x8048);
+        assert_eq!(
+            settings.custom_identity.product.as_str(),
+            "My IIDX  Controller"
+        );
+
+        for bad in [
+            "usb_vid 1ccf",
+            "usb_pid 0x10000",
+            "usb_manufacturer a very long manufacturer name",
+            "tt_steps 144 144",
+        ] {
+            let line = std::format!("set {bad}");
+            assert_eq!(run(&line, &mut settings).1, "error: invalid value\r\n");
+        }
+    }
+
+    #[test]
+    fn konami_profile_overrides_are_noted() {
+        let mut settings = Settings::default();
+
+        let (action, out) = run("set tt_axis 16-bit", &mut settings);
+        assert_eq!(action, Some(Action::SettingsChanged));
+        assert_eq!(
+            out,
+            std::format!("tt_axis 16-bit\r\nnote: {KONAMI_OVERRIDE_NOTE}\r\n")
+        );
+        assert_eq!(settings.tt_axis, TurntableAxis::Wrapped16);
+
+        assert_eq!(
+            run("set usb_profile open-source", &mut settings).1,
+            "usb_profile open-source\r\n"
+        );
+        assert_eq!(run("set encoders 2", &mut settings).1, "encoders 2\r\n");
+        assert!(
+            run("set usb_profile konami", &mut settings)
+                .1
+                .contains(KONAMI_OVERRIDE_NOTE)
+        );
+    }
+
+    #[test]
+    fn get_lists_everything() {
+        let (_, out) = run("get", &mut Settings::default());
+
+        assert_eq!(
+            out.lines().count(),
+            KEYS.len() + 2 * NUM_BUTTONS + NUM_KEYBOARD_INPUTS + MAX_BUTTON_LEDS
+        );
+        assert!(out.contains("input_mode joystick\r\n"));
+        assert!(out.contains("colour.key1 #a22b95\r\n"));
+        assert!(out.contains("debounce.e4 eager,4000,4000\r\n"));
+        assert!(out.contains("key.tt-up 0xe1\r\n"));
+        assert!(out.contains("led.11 e4\r\n"));
+    }
+
+    #[test]
+    fn bad_input_is_reported() {
+        let mut settings = Settings::default();
+
+        assert_eq!(
+            run("set tt_steps 0", &mut settings),
+            (None, "error: invalid value\r\n".into())
+        );
+        assert_eq!(
+            run("set tt_stepz 10", &mut settings).1,
+            "error: unknown key, see get\r\n"
+        );
+        assert_eq!(
+            run("set led_mode", &mut settings).1,
+            "error: wrong arguments, see help\r\n"
+        );
+        assert!(run("frobnicate", &mut settings).1.starts_with("error"));
+        assert_eq!(settings, Settings::default());
+    }
+
+    #[test]
+    fn status_and_actions() {
+        let mut settings = Settings::default();
+
+        assert_eq!(
+            run("status", &mut settings).1,
+            "buttons 0x0103\r\nencoder -42\r\nreversals 7\r\nusb configured\r\n"
+        );
+        assert_eq!(run("save", &mut settings).0, Some(Action::Save));
+        assert_eq!(run(" reboot ", &mut settings).0, Some(Action::Reboot));
+        assert_eq!(run("bootloader", &mut settings).0, Some(Action::Bootloader));
+    }
+
+    #[test]
+    fn line_buffer_splits_lines() {
+        let mut lines = LineBuffer::new();
+        let mut got = std::vec::Vec::new();
+        for &byte in b"stat\x7Ftus\r\n\r\nget\n" {
+            if let Some(line) = lines.push(byte) {
+                got.push(line.map(String::from));
+            }
+        }
+
+        assert_eq!(got, [Ok("status".into()), Ok("get".into())]);
+    }
+
+    #[test]
+    fn long_lines_are_dropped() {
+        let mut lines = LineBuffer::new();
+        for _ in 0..MAX_LINE_LEN + 1 {
+            assert_eq!(lines.push(b'a'), None);
+        }
+
+        assert_eq!(lines.push(b'\n'), Some(Err(LineError::TooLong)));
+        assert_eq!(lines.push(b'b'), None);
+        assert_eq!(lines.push(b'\n'), Some(Ok("b")));
+    }
+}
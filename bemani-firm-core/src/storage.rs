@@ -0,0 +1,413 @@
+//! Wear-levelled, CRC-checked settings records in NOR flash.
+//!
+//! The reserved region is split into fixed size slots. Every save writes a
+//! complete record into the slot after the newest one, wrapping around the
+//! region, so each sector is only erased once per trip around the ring. On
+//! load the valid record with the highest sequence number wins, which means a
+//! save interrupted by a power cut just leaves the previous record in place.
+
+use embedded_storage::nor_flash::NorFlash;
+
+use crate::codec::{Reader, Writer, crc32};
+use crate::settings::{MAX_ENCODED_LEN, SETTINGS_VERSION, Settings};
+
+pub const SLOT_SIZE: usize = 512;
+
+const MAGIC: u32 = u32::from_le_bytes(*b"BFRS");
+const HEADER_LEN: usize = 12;
+const CRC_LEN: usize = 4;
+
+const _: () = assert!(HEADER_LEN + MAX_ENCODED_LEN + CRC_LEN <= SLOT_SIZE);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StorageError {
+    /// The flash driver reported an error.
+    Flash,
+    /// The settings did not fit in a slot.
+    TooLarge,
+}
+
+struct Newest {
+    slot: u32,
+    sequence: u32,
+}
+
+pub struct SettingsStore<F: NorFlash> {
+    flash: F,
+    base: u32,
+    slots: u32,
+    newest: Option<Newest>,
+}
+
+impl<F: NorFlash> SettingsStore<F> {
+    /// Uses `sectors` erase sectors starting at flash offset `base`.
+    pub fn new(flash: F, base: u32, sectors: u32) -> Self {
+        assert!(F::ERASE_SIZE % SLOT_SIZE == 0);
+        assert!(SLOT_SIZE % F::WRITE_SIZE == 0);
+        assert!(base as usize % F::ERASE_SIZE == 0);
+
+        Self {
+            flash,
+            base,
+            slots: sectors * (F::ERASE_SIZE / SLOT_SIZE) as u32,
+            newest: None,
+        }
+    }
+
+    pub fn flash(&mut self) -> &mut F {
+        &mut self.flash
+    }
+
+    /// Loads the newest valid settings, falling back to the defaults when
+    /// nothing valid has been stored.
+    pub fn load(&mut self) -> Settings {
+        self.newest = None;
+        let mut settings = None;
+        let mut slot_buf = [0u8; SLOT_SIZE];
+
+        for slot in 0..self.slots {
+            if self.read_slot(slot, &mut slot_buf).is_err() {
+                continue;
+            }
+            let Some((sequence, version, payload)) = parse_record(&slot_buf) else {
+                continue;
+            };
+
+            let is_newer = self
+                .newest
+                .as_ref()
+                .is_none_or(|newest| sequence > newest.sequence);
+            if is_newer {
+                self.newest = Some(Newest { slot, sequence });
+                settings = Some(Settings::decode(version, payload));
+            }
+        }
+
+        settings.unwrap_or_default()
+    }
+
+    /// Writes `settings` as the new newest record.
+    pub fn save(&mut self, settings: &Settings) -> Result<(), StorageError> {
+        let mut slot_buf = [0xFFu8; SLOT_SIZE];
+
+        let sequence = self
+            .newest
+            .as_ref()
+            .map_or(0, |n| n.sequence.wrapping_add(1));
+        let len = settings
+            .encode(&mut slot_buf[HEADER_LEN..HEADER_LEN + MAX_ENCODED_LEN])
+            .ok_or(StorageError::TooLarge)?;
+
+        let mut w = Writer::new(&mut slot_buf[..HEADER_LEN]);
+        w.u32(MAGIC);
+        w.u32(sequence);
+        w.u16(SETTINGS_VERSION);
+        w.u16(len as u16);
+
+        let crc = crc32(&slot_buf[..HEADER_LEN + len]);
+        slot_buf[HEADER_LEN + len..HEADER_LEN + len + CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+
+        let slot = self.next_slot()?;
+        let offset = self.slot_offset(slot);
+        self.flash
+            .write(offset, &slot_buf)
+            .map_err(|_| StorageError::Flash)?;
+
+        self.newest = Some(Newest { slot, sequence });
+        Ok(())
+    }
+
+    /// Picks the slot for the next record, erasing its sector if needed.
+    ///
+    /// A record is never written into a sector that still holds the newest
+    /// record unless the slot is blank, so the newest record survives until
+    /// its replacement is complete.
+    fn next_slot(&mut self) -> Result<u32, StorageError> {
+        let slots_per_sector = (F::ERASE_SIZE / SLOT_SIZE) as u32;
+        let mut slot = match &self.newest {
+            Some(newest) => (newest.slot + 1) % self.slots,
+            None => 0,
+        };
+
+        if slot % slots_per_sector != 0 && !self.slot_is_blank(slot)? {
+            // Leftovers from an interrupted write, move on to a fresh sector
+            slot = (slot / slots_per_sector + 1) * slots_per_sector % self.slots;
+        }
+
+        if slot % slots_per_sector == 0 && !self.sector_is_blank(slot)? {
+            let from = self.slot_offset(slot);
+            self.flash
+                .erase(from, from + F::ERASE_SIZE as u32)
+                .map_err(|_| StorageError::Flash)?;
+        }
+
+        Ok(slot)
+    }
+
+    fn slot_offset(&self, slot: u32) -> u32 {
+        self.base + slot * SLOT_SIZE as u32
+    }
+
+    fn read_slot(&mut self, slot: u32, buf: &mut [u8; SLOT_SIZE]) -> Result<(), StorageError> {
+        let offset = self.slot_offset(slot);
+        self.flash
+            .read(offset, buf)
+            .map_err(|_| StorageError::Flash)
+    }
+
+    fn slot_is_blank(&mut self, slot: u32) -> Result<bool, StorageError> {
+        let mut buf = [0u8; SLOT_SIZE];
+        self.read_slot(slot, &mut buf)?;
+        Ok(buf.iter().all(|&b| b == 0xFF))
+    }
+
+    fn sector_is_blank(&mut self, first_slot: u32) -> Result<bool, StorageError> {
+        let slots_per_sector = (F::ERASE_SIZE / SLOT_SIZE) as u32;
+        for slot in first_slot..first_slot + slots_per_sector {
+            if !self.slot_is_blank(slot)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Validates a slot, returning its sequence number, layout version and
+/// settings payload.
+fn parse_record(slot: &[u8; SLOT_SIZE]) -> Option<(u32, u16, &[u8])> {
+    let mut r = Reader::new(&slot[..HEADER_LEN]);
+    let magic = r.u32()?;
+    let sequence = r.u32()?;
+    let version = r.u16()?;
+    let len = r.u16()? as usize;
+
+    if magic != MAGIC || HEADER_LEN + len + CRC_LEN > SLOT_SIZE {
+        return None;
+    }
+
+    let (data, rest) = slot.split_at(HEADER_LEN + len);
+    let crc = u32::from_le_bytes(*rest.first_chunk()?);
+    if crc != crc32(data) {
+        return None;
+    }
+
+    Some((sequence, version, &data[HEADER_LEN..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_storage::nor_flash::{ErrorType, NorFlashError, NorFlashErrorKind, ReadNorFlash};
+    use std::vec;
+    use std::vec::Vec;
+
+    const SECTOR: usize = 4096;
+    const SECTORS: u32 = 4;
+    const BASE: u32 = 2 * SECTOR as u32;
+
+    /// RAM-backed flash that only lets writes clear bits, like real NOR.
+    struct MockFlash {
+        data: Vec<u8>,
+        erases: Vec<u32>,
+        /// Fail writes after this many bytes, simulating a power cut.
+        write_budget: Option<usize>,
+    }
+
+    impl MockFlash {
+        fn new() -> Self {
+            let size = BASE as usize + SECTORS as usize * SECTOR;
+            Self {
+                data: vec![0xFF; size],
+                erases: vec![0; size / SECTOR],
+                write_budget: None,
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    struct MockError;
+
+    impl NorFlashError for MockError {
+        fn kind(&self) -> NorFlashErrorKind {
+            NorFlashErrorKind::Other
+        }
+    }
+
+    impl ErrorType for MockFlash {
+        type Error = MockError;
+    }
+
+    impl ReadNorFlash for MockFlash {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            let offset = offset as usize;
+            bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.data.len()
+        }
+    }
+
+    impl NorFlash for MockFlash {
+        const WRITE_SIZE: usize = 1;
+        const ERASE_SIZE: usize = SECTOR;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            for sector in (from as usize / SECTOR)..(to as usize / SECTOR) {
+                self.erases[sector] += 1;
+                self.data[sector * SECTOR..(sector + 1) * SECTOR].fill(0xFF);
+            }
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            for (i, &b) in bytes.iter().enumerate() {
+                if let Some(budget) = &mut self.write_budget {
+                    if *budget == 0 {
+                        return Err(MockError);
+                    }
+                    *budget -= 1;
+                }
+                self.data[offset as usize + i] &= b;
+            }
+            Ok(())
+        }
+    }
+
+    fn store(flash: MockFlash) -> SettingsStore<MockFlash> {
+        SettingsStore::new(flash, BASE, SECTORS)
+    }
+
+    fn settings_with_steps(tt_steps: u16) -> Settings {
+        Settings {
+            tt_steps,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn blank_flash_loads_defaults() {
+        let mut store = store(MockFlash::new());
+        assert_eq!(store.load(), Settings::default());
+    }
+
+    #[test]
+    fn saved_settings_survive_reload() {
+        let mut store = store(MockFlash::new());
+        store.load();
+        store.save(&settings_with_steps(100)).unwrap();
+        store.save(&settings_with_steps(200)).unwrap();
+
+        let mut reloaded = self::store(store.flash);
+        assert_eq!(reloaded.load(), settings_with_steps(200));
+    }
+
+    #[test]
+    fn saves_are_spread_over_all_sectors() {
+        let mut store = store(MockFlash::new());
+        store.load();
+        let slots_per_cycle = SECTORS as usize * SECTOR / SLOT_SIZE;
+        for i in 0..slots_per_cycle * 3 {
+            store.save(&settings_with_steps(i as u16 + 1)).unwrap();
+        }
+
+        let region_erases = &store.flash.erases[BASE as usize / SECTOR..];
+        // First pass writes onto blank flash, the next two erase once each
+        assert!(region_erases.iter().all(|&n| n == 2), "{region_erases:?}");
+        assert!(
+            store.flash.erases[..BASE as usize / SECTOR]
+                .iter()
+                .all(|&n| n == 0)
+        );
+
+        let mut reloaded = self::store(store.flash);
+        assert_eq!(
+            reloaded.load(),
+            settings_with_steps(slots_per_cycle as u16 * 3)
+        );
+    }
+
+    #[test]
+    fn corrupt_newest_record_falls_back_to_previous() {
+        let mut store = store(MockFlash::new());
+        store.load();
+        store.save(&settings_with_steps(100)).unwrap();
+        store.save(&settings_with_steps(200)).unwrap();
+
+        // Flip a payload bit in the second slot
+        store.flash.data[BASE as usize + SLOT_SIZE + HEADER_LEN + 3] ^= 0x01;
+
+        let mut reloaded = self::store(store.flash);
+        assert_eq!(reloaded.load(), settings_with_steps(100));
+    }
+
+    #[test]
+    fn garbage_region_loads_defaults() {
+        let mut flash = MockFlash::new();
+        for (i, b) in flash.data.iter_mut().enumerate() {
+            *b = (i * 7) as u8;
+        }
+
+        let mut store = store(flash);
+        assert_eq!(store.load(), Settings::default());
+        store.save(&settings_with_steps(100)).unwrap();
+        assert_eq!(store.load(), settings_with_steps(100));
+    }
+
+    #[test]
+    fn interrupted_save_keeps_previous_record() {
+        let mut store = store(MockFlash::new());
+        store.load();
+        store.save(&settings_with_steps(100)).unwrap();
+
+        store.flash.write_budget = Some(40);
+        assert_eq!(
+            store.save(&settings_with_steps(200)),
+            Err(StorageError::Flash)
+        );
+        store.flash.write_budget = None;
+
+        let mut reloaded = self::store(store.flash);
+        assert_eq!(reloaded.load(), settings_with_steps(100));
+
+        // The half written slot is skipped rather than written over
+        reloaded.save(&settings_with_steps(300)).unwrap();
+        assert_eq!(reloaded.load(), settings_with_steps(300));
+    }
+
+    #[test]
+    fn older_layout_is_migrated_with_defaults() {
+        let mut flash = MockFlash::new();
+
+        // A record holding only the debounce settings, as an older firmware
+        // with a shorter layout would have written
+        let mut slot = [0xFFu8; SLOT_SIZE];
+        let mut payload = [0u8; MAX_ENCODED_LEN];
+        let mut expected = Settings::default();
+        expected.debounce[0].press = embassy_time::Duration::from_millis(9);
+        expected.encode(&mut payload).unwrap();
+        let len = crate::button::NUM_BUTTONS * 5;
+
+        let mut w = Writer::new(&mut slot[..HEADER_LEN]);
+        w.u32(MAGIC);
+        w.u32(7);
+        w.u16(0);
+        w.u16(len as u16);
+        slot[HEADER_LEN..HEADER_LEN + len].copy_from_slice(&payload[..len]);
+        let crc = crc32(&slot[..HEADER_LEN + len]);
+        slot[HEADER_LEN + len..HEADER_LEN + len + CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+        flash.write(BASE, &slot).unwrap();
+
+        let mut store = store(flash);
+        assert_eq!(store.load(), expected);
+
+        // The next save continues the sequence after the migrated record
+        store.save(&expected).unwrap();
+        let newest = store.newest.as_ref().unwrap();
+        assert_eq!((newest.slot, newest.sequence), (1, 8));
+    }
+}
@@ -0,0 +1,228 @@
+//! Nintendo Switch personality, see [`InputMode::Switch`].
+//!
+//! The console only takes wired pads it knows, so this copies the HORI
+//! Pokken controller: its VID/PID, descriptor and 8-byte report. The keys
+//! and E buttons map onto fixed pad buttons and the turntable's digital
+//! TT+/TT- bits move the left stick or the d-pad.
+//!
+//! [`InputMode::Switch`]: crate::settings::InputMode::Switch
+
+use crate::button::{NUM_BUTTONS, OUTPUT_INDICES};
+use crate::encoder::{TT_DOWN_BIT, TT_UP_BIT};
+
+pub const SWITCH_VID: u16 = 0x0F0D;
+pub const SWITCH_PID: u16 = 0x0092;
+pub const SWITCH_MANUFACTURER: &str = "HORI CO.,LTD.";
+pub const SWITCH_PRODUCT: &str = "POKKEN CONTROLLER";
+
+/// Buttons, hat, four stick axes and a vendor byte.
+pub const SWITCH_REPORT_LEN: usize = 8;
+
+/// Pad button bits, in report order.
+pub const Y: u16 = 1 << 0;
+pub const B: u16 = 1 << 1;
+pub const A: u16 = 1 << 2;
+pub const X: u16 = 1 << 3;
+pub const L: u16 = 1 << 4;
+pub const R: u16 = 1 << 5;
+pub const ZL: u16 = 1 << 6;
+pub const ZR: u16 = 1 << 7;
+pub const MINUS: u16 = 1 << 8;
+pub const PLUS: u16 = 1 << 9;
+pub const HOME: u16 = 1 << 12;
+pub const CAPTURE: u16 = 1 << 13;
+
+/// Pad button for each controller button, in settings order: the face
+/// buttons and shoulders for keys 1-7, then Plus, Minus, Home and Capture
+/// for E1-E4.
+pub const BUTTON_MAP: [u16; NUM_BUTTONS] = [Y, B, X, A, L, R, ZR, PLUS, MINUS, HOME, CAPTURE];
+
+const HAT_UP: u8 = 0;
+const HAT_DOWN: u8 = 4;
+const HAT_CENTRE: u8 = 8;
+
+const STICK_MIN: u8 = 0x00;
+const STICK_CENTRE: u8 = 0x80;
+const STICK_MAX: u8 = 0xFF;
+
+/// What the turntable moves on the pad.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum SwitchTurntable {
+    /// The left stick, fully up for TT+ and fully down for TT-.
+    #[default]
+    Stick = 0,
+    /// D-pad up for TT+ and down for TT-.
+    Dpad = 1,
+}
+
+impl SwitchTurntable {
+    /// Every option this firmware supports.
+    pub const ALL: &[SwitchTurntable] = &[SwitchTurntable::Stick, SwitchTurntable::Dpad];
+}
+
+impl TryFrom<u8> for SwitchTurntable {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|&tt| tt as u8 == value)
+            .ok_or(value)
+    }
+}
+
+/// Serializes the pad report for the button bitmask (including the digital
+/// turntable bits) into `buf`, returning the number of bytes used.
+pub fn serialize_switch_report(tt: SwitchTurntable, buttons: u16, buf: &mut [u8]) -> Option<usize> {
+    let report = buf.get_mut(..SWITCH_REPORT_LEN)?;
+
+    let pad_buttons = OUTPUT_INDICES
+        .iter()
+        .zip(BUTTON_MAP)
+        .filter(|&(&index, _)| buttons & (1 << index) != 0)
+        .fold(0, |pad, (_, button)| pad | button);
+
+    let spin = match (buttons & TT_UP_BIT != 0, buttons & TT_DOWN_BIT != 0) {
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        _ => None,
+    };
+    let (hat, stick_y) = match (tt, spin) {
+        (SwitchTurntable::Stick, Some(true)) => (HAT_CENTRE, STICK_MIN),
+        (SwitchTurntable::Stick, Some(false)) => (HAT_CENTRE, STICK_MAX),
+        (SwitchTurntable::Dpad, Some(true)) => (HAT_UP, STICK_CENTRE),
+        (SwitchTurntable::Dpad, Some(false)) => (HAT_DOWN, STICK_CENTRE),
+        (_, None) => (HAT_CENTRE, STICK_CENTRE),
+    };
+
+    let [lo, hi] = pad_buttons.to_le_bytes();
+    report.copy_from_slice(&[
+        lo,
+        hi,
+        hat,
+        STICK_CENTRE,
+        stick_y,
+        STICK_CENTRE,
+        STICK_CENTRE,
+        0,
+    ]);
+    Some(SWITCH_REPORT_LEN)
+}
+
+/// Report descriptor of the HORI Pokken controller.
+#[rustfmt::skip]
+pub const SWITCH_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01,       // Usage Page (Generic Desktop)
+    0x09, 0x05,       // Usage (Game Pad)
+    0xA1, 0x01,       // Collection (Application)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x25, 0x01,       //   Logical Maximum (1)
+    0x35, 0x00,       //   Physical Minimum (0)
+    0x45, 0x01,       //   Physical Maximum (1)
+    0x75, 0x01,       //   Report Size (1)
+    0x95, 0x10,       //   Report Count (16)
+    0x05, 0x09,       //   Usage Page (Button)
+    0x19, 0x01,       //   Usage Minimum (1)
+    0x29, 0x10,       //   Usage Maximum (16)
+    0x81, 0x02,       //   Input (Data,Var,Abs)
+    0x05, 0x01,       //   Usage Page (Generic Desktop)
+    0x25, 0x07,       //   Logical Maximum (7)
+    0x46, 0x3B, 0x01, //   Physical Maximum (315)
+    0x75, 0x04,       //   Report Size (4)
+    0x95, 0x01,       //   Report Count (1)
+    0x65, 0x14,       //   Unit (Degrees)
+    0x09, 0x39,       //   Usage (Hat Switch)
+    0x81, 0x42,       //   Input (Data,Var,Abs,Null State)
+    0x65, 0x00,       //   Unit (None)
+    0x95, 0x01,       //   Report Count (1)
+    0x81, 0x01,       //   Input (Const)
+    0x26, 0xFF, 0x00, //   Logical Maximum (255)
+    0x46, 0xFF, 0x00, //   Physical Maximum (255)
+    0x09, 0x30,       //   Usage (X)
+    0x09, 0x31,       //   Usage (Y)
+    0x09, 0x32,       //   Usage (Z)
+    0x09, 0x35,       //   Usage (Rz)
+    0x75, 0x08,       //   Report Size (8)
+    0x95, 0x04,       //   Report Count (4)
+    0x81, 0x02,       //   Input (Data,Var,Abs)
+    0x06, 0x00, 0xFF, //   Usage Page (Vendor Defined 0xFF00)
+    0x09, 0x20,       //   Usage (0x20)
+    0x95, 0x01,       //   Report Count (1)
+    0x81, 0x02,       //   Input (Data,Var,Abs)
+    0x0A, 0x21, 0x26, //   Usage (0x2621)
+    0x95, 0x08,       //   Report Count (8)
+    0x91, 0x02,       //   Output (Data,Var,Abs)
+    0xC0,             // End Collection
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(tt: SwitchTurntable, buttons: u16) -> [u8; SWITCH_REPORT_LEN] {
+        let mut buf = [0xAA; SWITCH_REPORT_LEN];
+        assert_eq!(
+            serialize_switch_report(tt, buttons, &mut buf),
+            Some(SWITCH_REPORT_LEN)
+        );
+        buf
+    }
+
+    #[test]
+    fn descriptor_matches_hori_pad() {
+        // As read back from a HORI Pokken controller
+        let hori: [u8; 86] = [
+            0x05, 0x01, 0x09, 0x05, 0xA1, 0x01, 0x15, 0x00, 0x25, 0x01, 0x35, 0x00, 0x45, 0x01,
+            0x75, 0x01, 0x95, 0x10, 0x05, 0x09, 0x19, 0x01, 0x29, 0x10, 0x81, 0x02, 0x05, 0x01,
+            0x25, 0x07, 0x46, 0x3B, 0x01, 0x75, 0x04, 0x95, 0x01, 0x65, 0x14, 0x09, 0x39, 0x81,
+            0x42, 0x65, 0x00, 0x95, 0x01, 0x81, 0x01, 0x26, 0xFF, 0x00, 0x46, 0xFF, 0x00, 0x09,
+            0x30, 0x09, 0x31, 0x09, 0x32, 0x09, 0x35, 0x75, 0x08, 0x95, 0x04, 0x81, 0x02, 0x06,
+            0x00, 0xFF, 0x09, 0x20, 0x95, 0x01, 0x81, 0x02, 0x0A, 0x21, 0x26, 0x95, 0x08, 0x91,
+            0x02, 0xC0,
+        ];
+
+        assert_eq!(SWITCH_REPORT_DESCRIPTOR, hori);
+    }
+
+    #[test]
+    fn idle_report_is_centred() {
+        assert_eq!(
+            report(SwitchTurntable::Stick, 0),
+            [0x00, 0x00, 0x08, 0x80, 0x80, 0x80, 0x80, 0x00]
+        );
+    }
+
+    #[test]
+    fn buttons_map_to_pad_buttons() {
+        // Key 1, key 4, key 7 and E1-E4
+        let buttons = 1 | 1 << 3 | 1 << 6 | 0xF << 8;
+
+        assert_eq!(
+            report(SwitchTurntable::Stick, buttons),
+            [0x85, 0x33, 0x08, 0x80, 0x80, 0x80, 0x80, 0x00]
+        );
+    }
+
+    #[test]
+    fn turntable_moves_stick_or_dpad() {
+        assert_eq!(
+            report(SwitchTurntable::Stick, TT_UP_BIT),
+            [0x00, 0x00, 0x08, 0x80, 0x00, 0x80, 0x80, 0x00]
+        );
+        assert_eq!(
+            report(SwitchTurntable::Stick, TT_DOWN_BIT),
+            [0x00, 0x00, 0x08, 0x80, 0xFF, 0x80, 0x80, 0x00]
+        );
+        assert_eq!(
+            report(SwitchTurntable::Dpad, TT_UP_BIT),
+            [0x00, 0x00, 0x00, 0x80, 0x80, 0x80, 0x80, 0x00]
+        );
+        assert_eq!(
+            report(SwitchTurntable::Dpad, TT_DOWN_BIT | 1 << 1),
+            [0x02, 0x00, 0x04, 0x80, 0x80, 0x80, 0x80, 0x00]
+        );
+    }
+}
@@ -0,0 +1,266 @@
+//! Turntable speed and direction, estimated from timestamped encoder counts.
+//!
+//! Speed is the movement over a short sliding window, so it settles back to
+//! zero one window after the platter stops. Direction only flips once the
+//! platter has come back a few counts from the furthest point it reached,
+//! so jitter on a resting platter is not a scratch.
+
+use embassy_time::{Duration, Instant};
+
+use crate::encoder::{PPR, TARGET_STEPS};
+
+/// Time the speed is measured over.
+pub const VELOCITY_WINDOW: Duration = Duration::from_millis(16);
+
+/// Movement back from the turning point that counts as a direction change,
+/// one report step at the default resolution. The encoder task keeps it at
+/// [`EncoderScaling::counts_per_step`](crate::encoder::EncoderScaling::counts_per_step)
+/// for the configured one.
+pub const DEFAULT_REVERSAL_THRESHOLD: i32 = PPR / TARGET_STEPS;
+
+/// Way the platter last turned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Direction {
+    /// Counts going up.
+    Forward,
+    /// Counts going down.
+    Backward,
+}
+
+/// How the platter is moving, published with each encoder reading.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TurntableMotion {
+    /// Raw quadrature counts.
+    pub counts: i32,
+    /// Signed speed in thousandths of an RPM, positive going forward.
+    pub milli_rpm: i32,
+    /// Way the platter last turned, `None` until it first moves.
+    pub direction: Option<Direction>,
+    /// Direction changes so far, wrapping. Readers compare it with the last
+    /// value they saw so they never miss a change between two readings.
+    pub reversals: u32,
+}
+
+/// Tracks speed and direction changes from successive encoder readings.
+pub struct VelocityEstimator {
+    counts_per_rotation: i32,
+    window: Duration,
+    reversal_threshold: i32,
+    /// Reading and time the current window started at.
+    window_start: Option<(i32, Instant)>,
+    /// Furthest reading in the current direction.
+    turning_point: Option<i32>,
+    motion: TurntableMotion,
+}
+
+impl VelocityEstimator {
+    pub fn new(counts_per_rotation: i32, window: Duration, reversal_threshold: i32) -> Self {
+        Self {
+            counts_per_rotation: counts_per_rotation.max(1),
+            window,
+            reversal_threshold: reversal_threshold.max(1),
+            window_start: None,
+            turning_point: None,
+            motion: TurntableMotion::default(),
+        }
+    }
+
+    /// Feeds a new raw encoder reading, returning the motion up to `now`.
+    pub fn update(&mut self, reading: i32, now: Instant) -> TurntableMotion {
+        self.motion.counts = reading;
+        self.update_speed(reading, now);
+        self.update_direction(reading);
+        self.motion
+    }
+
+    /// Changes the encoder resolution speeds are worked out for.
+    pub fn set_counts_per_rotation(&mut self, counts_per_rotation: i32) {
+        self.counts_per_rotation = counts_per_rotation.max(1);
+    }
+
+    /// Changes how far back from the turning point counts as a direction
+    /// change.
+    pub fn set_reversal_threshold(&mut self, reversal_threshold: i32) {
+        self.reversal_threshold = reversal_threshold.max(1);
+    }
+
+    pub fn motion(&self) -> TurntableMotion {
+        self.motion
+    }
+
+    fn update_speed(&mut self, reading: i32, now: Instant) {
+        let (start, started_at) = *self.window_start.get_or_insert((reading, now));
+        let elapsed = now.saturating_duration_since(started_at);
+        if elapsed < self.window || elapsed.as_micros() == 0 {
+            return;
+        }
+
+        let moved = reading.wrapping_sub(start) as i64;
+        let milli_rpm = moved * 60 * 1000 * 1_000_000
+            / (self.counts_per_rotation as i64 * elapsed.as_micros() as i64);
+        self.motion.milli_rpm = milli_rpm.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+        self.window_start = Some((reading, now));
+    }
+
+    fn update_direction(&mut self, reading: i32) {
+        let turning_point = *self.turning_point.get_or_insert(reading);
+        let moved = reading.wrapping_sub(turning_point);
+
+        let direction = match self.motion.direction {
+            // Still going the same way, so the turning point moves along
+            Some(Direction::Forward) if moved > 0 => Direction::Forward,
+            Some(Direction::Backward) if moved < 0 => Direction::Backward,
+            _ if moved >= self.reversal_threshold => Direction::Forward,
+            _ if moved <= -self.reversal_threshold => Direction::Backward,
+            _ => return,
+        };
+
+        if self.motion.direction.is_some_and(|last| last != direction) {
+            self.motion.reversals = self.motion.reversals.wrapping_add(1);
+        }
+        self.motion.direction = Some(direction);
+        self.turning_point = Some(reading);
+    }
+}
+
+impl Default for VelocityEstimator {
+    fn default() -> Self {
+        Self::new(PPR, VELOCITY_WINDOW, DEFAULT_REVERSAL_THRESHOLD)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::EncoderScaling;
+
+    fn ms(ms: u64) -> Instant {
+        Instant::from_millis(ms)
+    }
+
+    /// Feeds one reading per millisecond, `counts_per_ms` apart.
+    fn spin(
+        estimator: &mut VelocityEstimator,
+        from: i32,
+        counts_per_ms: i32,
+        start_ms: u64,
+        len_ms: u64,
+    ) -> TurntableMotion {
+        let mut motion = estimator.motion();
+        for t in 0..=len_ms {
+            let reading = from.wrapping_add(counts_per_ms * t as i32);
+            motion = estimator.update(reading, ms(start_ms + t));
+        }
+        motion
+    }
+
+    #[test]
+    fn measures_steady_spin() {
+        let mut estimator = VelocityEstimator::default();
+
+        // 24 counts a millisecond is 1000 rotations a minute
+        let motion = spin(&mut estimator, 0, 24, 0, 100);
+        assert_eq!(motion.milli_rpm, 1_000_000);
+        assert_eq!(motion.direction, Some(Direction::Forward));
+
+        let motion = spin(&mut estimator, motion.counts, -12, 101, 100);
+        assert_eq!(motion.milli_rpm, -500_000);
+        assert_eq!(motion.direction, Some(Direction::Backward));
+    }
+
+    #[test]
+    fn slow_spin_is_not_rounded_away() {
+        let mut estimator = VelocityEstimator::default();
+
+        // One count every 4ms, about 10 RPM
+        for t in 0..=64 {
+            estimator.update(t as i32 / 4, ms(t));
+        }
+        assert_eq!(estimator.motion().milli_rpm, 10_416);
+    }
+
+    #[test]
+    fn speed_drops_to_zero_after_stopping() {
+        let mut estimator = VelocityEstimator::default();
+        let motion = spin(&mut estimator, 0, 24, 0, 50);
+        assert!(motion.milli_rpm > 0);
+
+        let motion = spin(
+            &mut estimator,
+            motion.counts,
+            0,
+            51,
+            2 * VELOCITY_WINDOW.as_millis(),
+        );
+        assert_eq!(motion.milli_rpm, 0);
+        // Stopping is not a direction change
+        assert_eq!(motion.direction, Some(Direction::Forward));
+        assert_eq!(motion.reversals, 0);
+    }
+
+    #[test]
+    fn counts_each_direction_change() {
+        let mut estimator = VelocityEstimator::new(PPR, VELOCITY_WINDOW, 10);
+        let mut reading = 0;
+
+        for (change, t) in (0..6).zip((0..).step_by(100)) {
+            let counts_per_ms = if change % 2 == 0 { 5 } else { -5 };
+            reading = spin(&mut estimator, reading, counts_per_ms, t, 50).counts;
+        }
+        assert_eq!(estimator.motion().reversals, 5);
+        assert_eq!(estimator.motion().direction, Some(Direction::Backward));
+    }
+
+    #[test]
+    fn jitter_is_not_a_direction_change() {
+        let mut estimator = VelocityEstimator::new(PPR, VELOCITY_WINDOW, 10);
+        spin(&mut estimator, 0, 5, 0, 20);
+
+        for t in 21..200 {
+            let reading = if t % 2 == 0 { 91 } else { 100 };
+            estimator.update(reading, ms(t));
+        }
+        assert_eq!(estimator.motion().reversals, 0);
+
+        // Coming back a full threshold from the furthest point is
+        estimator.update(90, ms(200));
+        assert_eq!(estimator.motion().reversals, 1);
+    }
+
+    #[test]
+    fn reversal_threshold_follows_the_configured_steps() {
+        let fine = EncoderScaling {
+            steps: 1440,
+            ..EncoderScaling::default()
+        };
+        let mut estimator = VelocityEstimator::default();
+        estimator.set_reversal_threshold(fine.counts_per_step());
+        let reading = spin(&mut estimator, 0, 5, 0, 20).counts;
+
+        // One fine step back is a scratch, well under a default step
+        estimator.update(reading - fine.counts_per_step(), ms(21));
+        assert_eq!(estimator.motion().reversals, 1);
+
+        let coarse = EncoderScaling {
+            steps: 36,
+            ..EncoderScaling::default()
+        };
+        estimator.set_reversal_threshold(coarse.counts_per_step());
+        let reading = spin(&mut estimator, reading, 5, 22, 20).counts;
+        assert_eq!(estimator.motion().reversals, 2);
+
+        // A default step back is too small to be a scratch at 36 steps
+        estimator.update(reading - DEFAULT_REVERSAL_THRESHOLD, ms(43));
+        assert_eq!(estimator.motion().reversals, 2);
+    }
+
+    #[test]
+    fn counts_wrapping_does_not_spike_speed() {
+        let mut estimator = VelocityEstimator::default();
+        let motion = spin(&mut estimator, i32::MAX - 50, 24, 0, 50);
+        assert_eq!(motion.milli_rpm, 1_000_000);
+        assert_eq!(motion.direction, Some(Direction::Forward));
+    }
+}
@@ -1,6 +1,10 @@
 MEMORY {
     BOOT2 : ORIGIN = 0x10000000, LENGTH = 0x100
-    FLASH : ORIGIN = 0x10000100, LENGTH = 2048K - 0x100
+    FLASH : ORIGIN = 0x10000100, LENGTH = 2048K - 0x100 - 16K
+
+    /* The last four sectors hold the settings store (see settings.rs) */
+    /* and are kept out of FLASH so reflashing leaves them alone.       */
+    SETTINGS : ORIGIN = 0x10000000 + 2048K - 16K, LENGTH = 16K
 
     /* Pick one of the two options for RAM layout     */
 
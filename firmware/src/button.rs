@@ -0,0 +1,133 @@
+use bemani_firm_core::button::{
+    NUM_BUTTONS, POLL_PERIOD, apply_debounce_configs, buttons_to_bitstring, new_buttons,
+    poll_buttons,
+};
+use bemani_firm_core::latency::{DebounceTimer, Stamped};
+use defmt::debug;
+use embassy_rp::{
+    Peri,
+    gpio::{AnyPin, Input},
+};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::{Duration, Instant, Ticker, block_for};
+
+use crate::latency::SharedLatency;
+use crate::settings::SettingsWatch;
+
+pub struct ButtonGPIO {
+    pub key_1: Peri<'static, AnyPin>,
+    pub key_2: Peri<'static, AnyPin>,
+    pub key_3: Peri<'static, AnyPin>,
+    pub key_4: Peri<'static, AnyPin>,
+    pub key_5: Peri<'static, AnyPin>,
+    pub key_6: Peri<'static, AnyPin>,
+    pub key_7: Peri<'static, AnyPin>,
+
+    pub e_1: Peri<'static, AnyPin>,
+    pub e_2: Peri<'static, AnyPin>,
+    pub e_3: Peri<'static, AnyPin>,
+    pub e_4: Peri<'static, AnyPin>,
+}
+
+/// Samples taken by [`sample_held`], all of which must see a button held.
+const HELD_SAMPLES: usize = 10;
+const HELD_SAMPLE_PERIOD: Duration = Duration::from_millis(2);
+
+fn new_input<'a>(pin: Peri<'a, AnyPin>) -> Input<'a> {
+    Input::new(pin, embassy_rp::gpio::Pull::Up)
+}
+
+/// Blocks for a few milliseconds reading which buttons are held, in
+/// settings order, before the button task takes the pins.
+pub fn sample_held(gpio: &mut ButtonGPIO) -> [bool; NUM_BUTTONS] {
+    let pins = [
+        new_input(gpio.key_1.reborrow()),
+        new_input(gpio.key_2.reborrow()),
+        new_input(gpio.key_3.reborrow()),
+        new_input(gpio.key_4.reborrow()),
+        new_input(gpio.key_5.reborrow()),
+        new_input(gpio.key_6.reborrow()),
+        new_input(gpio.key_7.reborrow()),
+        new_input(gpio.e_1.reborrow()),
+        new_input(gpio.e_2.reborrow()),
+        new_input(gpio.e_3.reborrow()),
+        new_input(gpio.e_4.reborrow()),
+    ];
+
+    // Give the pull-ups time to settle, and ignore a button being bumped
+    let mut held = [true; NUM_BUTTONS];
+    for _ in 0..HELD_SAMPLES {
+        block_for(HELD_SAMPLE_PERIOD);
+        for (held, pin) in held.iter_mut().zip(&pins) {
+            *held &= pin.is_low();
+        }
+    }
+    held
+}
+
+#[embassy_executor::task]
+pub async fn button_task(
+    gpio: ButtonGPIO,
+    settings: &'static SettingsWatch,
+    output: &'static Signal<CriticalSectionRawMutex, Stamped<u16>>,
+    lights_output: &'static Signal<CriticalSectionRawMutex, u16>,
+    latency: &'static SharedLatency,
+) {
+    let pins = [
+        new_input(gpio.key_1),
+        new_input(gpio.key_2),
+        new_input(gpio.key_3),
+        new_input(gpio.key_4),
+        new_input(gpio.key_5),
+        new_input(gpio.key_6),
+        new_input(gpio.key_7),
+        new_input(gpio.e_1),
+        new_input(gpio.e_2),
+        new_input(gpio.e_3),
+        new_input(gpio.e_4),
+    ];
+
+    let mut settings = settings.receiver().unwrap();
+    let mut debounce = settings.get().await.debounce;
+    let mut buttons = new_buttons(&debounce);
+
+    let mut ticker = Ticker::every(POLL_PERIOD);
+    let mut last_bits = None;
+    let mut changed_at = Instant::now();
+    let mut debounce_timer = DebounceTimer::default();
+
+    loop {
+        if let Some(new_settings) = settings.try_changed()
+            && new_settings.debounce != debounce
+        {
+            debug!("debounce config changed to {}", new_settings.debounce);
+            apply_debounce_configs(&mut buttons, &debounce, &new_settings.debounce);
+            debounce = new_settings.debounce;
+        }
+
+        let pin_states: [bool; NUM_BUTTONS] = core::array::from_fn(|i| pins[i].is_low());
+        let now = Instant::now();
+        poll_buttons(&mut buttons, &pin_states, now);
+        let bits = buttons_to_bitstring(buttons.as_slice());
+        // debug!("{}", bits);
+        latency.lock(|stats| {
+            let before = last_bits.unwrap_or(bits);
+            debounce_timer.update(&pin_states, before, bits, now, &mut stats.borrow_mut());
+        });
+
+        // Only changes are passed on, so a report is sent for each one
+        if last_bits != Some(bits) {
+            last_bits = Some(bits);
+            // Changes the report hasn't picked up yet keep the first one's time
+            if !output.signaled() {
+                changed_at = now;
+            }
+            output.signal(Stamped {
+                value: bits,
+                at: changed_at,
+            });
+            lights_output.signal(bits);
+        }
+        ticker.next().await;
+    }
+}
@@ -0,0 +1,435 @@
+use core::sync::atomic::AtomicU32;
+use core::sync::atomic::Ordering;
+
+use bemani_firm_core::encoder::DigitalTurntable;
+use bemani_firm_core::encoder::EncoderScaling;
+use bemani_firm_core::encoder::MAX_ENCODERS;
+use bemani_firm_core::encoder::TurntablePosition;
+use bemani_firm_core::encoder::TurntableScaler;
+use bemani_firm_core::encoder::sample_rate;
+use bemani_firm_core::filter::TurntableFilter;
+use bemani_firm_core::latency::Stamped;
+use bemani_firm_core::settings::Settings;
+use bemani_firm_core::velocity::TurntableMotion;
+use bemani_firm_core::velocity::VelocityEstimator;
+use defmt::debug;
+use defmt::info;
+use embassy_rp::Peri;
+use embassy_rp::bind_interrupts;
+use embassy_rp::clocks::clk_sys_freq;
+use embassy_rp::peripherals::PIN_0;
+use embassy_rp::peripherals::PIN_1;
+use embassy_rp::peripherals::PIN_14;
+use embassy_rp::peripherals::PIN_15;
+use embassy_rp::peripherals::PIN_16;
+use embassy_rp::peripherals::PIN_17;
+use embassy_rp::peripherals::PIN_18;
+use embassy_rp::peripherals::PIN_19;
+use embassy_rp::peripherals::PIO0;
+use embassy_rp::pio::Common;
+use embassy_rp::pio::Config;
+use embassy_rp::pio::FifoJoin;
+use embassy_rp::pio::Instance;
+use embassy_rp::pio::InterruptHandler;
+use embassy_rp::pio::LoadedProgram;
+use embassy_rp::pio::Pin;
+use embassy_rp::pio::Pio;
+use embassy_rp::pio::ShiftDirection;
+use embassy_rp::pio::StateMachine;
+use embassy_rp::pio::program::pio_asm;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::Instant;
+use fixed::traits::ToFixed;
+
+use crate::settings::SettingsWatch;
+
+/// The turntable's [`TurntableMotion::reversals`], for the shell's `status`
+/// command.
+pub static TT_REVERSALS: AtomicU32 = AtomicU32::new(0);
+
+bind_interrupts!(struct Irqs {
+    PIO0_IRQ_0 => InterruptHandler<PIO0>;
+});
+
+pub struct QuadratureEncoderProgram<'a, PIO: Instance> {
+    prg: LoadedProgram<'a, PIO>,
+}
+
+impl<'a, PIO: Instance> QuadratureEncoderProgram<'a, PIO> {
+    pub fn new(common: &mut Common<'a, PIO>) -> Self {
+        // https://github.com/raspberrypi/pico-examples/blob/master/pio/quadrature_encoder/quadrature_encoder.pio
+        let prg = pio_asm!(
+            ".origin 0",
+            // 00 state
+            "JMP update",    // read 00
+            "JMP decrement", // read 01
+            "JMP increment", // read 10
+            "JMP update",    // read 11
+            // 01 state
+            "JMP increment", // read 00
+            "JMP update",    // read 01
+            "JMP update",    // read 10
+            "JMP decrement", // read 11
+            // 10 state
+            "JMP decrement", // read 00
+            "JMP update",    // read 01
+            "JMP update",    // read 10
+            "JMP increment", // read 11
+            // last 2 states implemented in place, become target for other jumps
+            // 11 state
+            "JMP update",    // read 00
+            "JMP increment", // read 01
+            "decrement:",
+            "JMP Y--, update", // read 10
+            // main loop begins
+            ".wrap_target",
+            "update:",
+            "MOV ISR, Y", // read 11
+            "PUSH noblock",
+            "sample_pins:",
+            "OUT ISR, 2",
+            "IN PINS, 2",
+            "MOV OSR, ISR",
+            "MOV PC, ISR",
+            "increment:",
+            "MOV Y, ~Y",
+            "JMP Y--, increment_cont",
+            "increment_cont:",
+            "MOV Y, ~Y",
+            ".wrap"
+        );
+
+        let prg = common.load_program(&prg.program);
+
+        Self { prg }
+    }
+}
+
+pub struct QuadratureEncoder<'d, T: Instance, const SM: usize> {
+    sm: StateMachine<'d, T, SM>,
+}
+
+impl<'d, T: Instance, const SM: usize> QuadratureEncoder<'d, T, SM> {
+    pub fn new(
+        mut sm: StateMachine<'d, T, SM>,
+        mut pin_0: Pin<'d, T>,
+        mut pin_1: Pin<'d, T>,
+        program: &QuadratureEncoderProgram<'d, T>,
+        sample_rate: u32,
+    ) -> Self {
+        pin_0.set_pull(embassy_rp::gpio::Pull::Up);
+        pin_1.set_pull(embassy_rp::gpio::Pull::Up);
+
+        let borrowed_pio_pins = [&pin_0, &pin_1];
+
+        sm.set_pin_dirs(embassy_rp::pio::Direction::In, &borrowed_pio_pins);
+
+        let mut cfg = Config::default();
+        cfg.set_in_pins(&borrowed_pio_pins);
+        cfg.set_jmp_pin(&pin_0);
+
+        cfg.use_program(&program.prg, &[]);
+
+        cfg.shift_in.auto_fill = false;
+        cfg.shift_in.direction = ShiftDirection::Left;
+        cfg.shift_in.threshold = 32;
+
+        cfg.fifo_join = FifoJoin::Duplex;
+
+        cfg.clock_divider = clock_divider(sample_rate).to_fixed();
+
+        sm.set_config(&cfg);
+        sm.set_enable(true);
+
+        Self { sm }
+    }
+
+    /// Changes how often the pins are sampled, keeping the count.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sm
+            .set_clock_divider(clock_divider(sample_rate).to_fixed());
+    }
+
+    pub async fn read(&mut self) -> i32 {
+        let num_to_purge = self.sm.rx().level();
+        for _ in 0..num_to_purge {
+            self.sm.rx().pull();
+        }
+
+        self.sm.rx().wait_pull().await as i32
+    }
+
+    /// The newest reading already in the FIFO, without waiting for one.
+    pub fn latest(&mut self) -> Option<i32> {
+        let mut latest = None;
+        for _ in 0..self.sm.rx().level() {
+            latest = self.sm.rx().try_pull().or(latest);
+        }
+        latest.map(|reading| reading as i32)
+    }
+}
+
+/// Integer PIO clock divider giving at least `sample_rate` samples a second.
+fn clock_divider(sample_rate: u32) -> u32 {
+    let clock_freq = clk_sys_freq();
+    let divider = (clock_freq / sample_rate.max(1)).clamp(1, u16::MAX as u32);
+    debug!(
+        "clock freq {} requested rate {} clock divider {}",
+        clock_freq, sample_rate, divider
+    );
+    divider
+}
+
+/// A [`QuadratureEncoder`] on any of PIO0's state machines.
+enum PioEncoder {
+    Sm0(QuadratureEncoder<'static, PIO0, 0>),
+    Sm1(QuadratureEncoder<'static, PIO0, 1>),
+    Sm2(QuadratureEncoder<'static, PIO0, 2>),
+    Sm3(QuadratureEncoder<'static, PIO0, 3>),
+}
+
+impl PioEncoder {
+    async fn read(&mut self) -> i32 {
+        match self {
+            Self::Sm0(encoder) => encoder.read().await,
+            Self::Sm1(encoder) => encoder.read().await,
+            Self::Sm2(encoder) => encoder.read().await,
+            Self::Sm3(encoder) => encoder.read().await,
+        }
+    }
+
+    fn latest(&mut self) -> Option<i32> {
+        match self {
+            Self::Sm0(encoder) => encoder.latest(),
+            Self::Sm1(encoder) => encoder.latest(),
+            Self::Sm2(encoder) => encoder.latest(),
+            Self::Sm3(encoder) => encoder.latest(),
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        match self {
+            Self::Sm0(encoder) => encoder.set_sample_rate(sample_rate),
+            Self::Sm1(encoder) => encoder.set_sample_rate(sample_rate),
+            Self::Sm2(encoder) => encoder.set_sample_rate(sample_rate),
+            Self::Sm3(encoder) => encoder.set_sample_rate(sample_rate),
+        }
+    }
+}
+
+/// Pins of each encoder's A and B channels. The PIO program reads both
+/// channels at once, so each pair has to be consecutive.
+pub struct EncoderPins {
+    /// The turntable.
+    pub encoder_1: (Peri<'static, PIN_0>, Peri<'static, PIN_1>),
+    pub encoder_2: (Peri<'static, PIN_14>, Peri<'static, PIN_15>),
+    pub encoder_3: (Peri<'static, PIN_16>, Peri<'static, PIN_17>),
+    pub encoder_4: (Peri<'static, PIN_18>, Peri<'static, PIN_19>),
+}
+
+/// One encoder's path from raw counts to its report axis.
+struct Channel {
+    encoder: PioEncoder,
+    scaling: EncoderScaling,
+    rate: u32,
+    scaler: TurntableScaler,
+    velocity: VelocityEstimator,
+    /// Only the turntable's counts are filtered, the other encoders' axes
+    /// report them as they come.
+    filter: Option<TurntableFilter>,
+}
+
+impl Channel {
+    /// Wraps encoder `index`, already sampling at [`encoder_rate`].
+    fn new(encoder: PioEncoder, settings: &Settings, index: usize) -> Self {
+        let scaling = settings.encoder_scaling(index);
+        let counts_per_rotation = scaling.counts_per_rotation();
+        let mut velocity = VelocityEstimator::default();
+        velocity.set_counts_per_rotation(counts_per_rotation);
+        velocity.set_reversal_threshold(scaling.counts_per_step());
+
+        Self {
+            encoder,
+            scaling,
+            rate: encoder_rate(settings, index),
+            scaler: TurntableScaler::new(counts_per_rotation, scaling.steps as i32),
+            velocity,
+            filter: (index == 0).then(|| TurntableFilter::new(settings)),
+        }
+    }
+
+    /// Picks up changed settings for encoder `index`.
+    fn set_config(&mut self, settings: &Settings, index: usize) {
+        let scaling = settings.encoder_scaling(index);
+        if scaling != self.scaling {
+            self.scaling = scaling;
+            let counts_per_rotation = scaling.counts_per_rotation();
+            self.scaler
+                .set_ratio(counts_per_rotation, scaling.steps as i32);
+            self.velocity.set_counts_per_rotation(counts_per_rotation);
+            self.velocity
+                .set_reversal_threshold(scaling.counts_per_step());
+        }
+        let rate = encoder_rate(settings, index);
+        if rate != self.rate {
+            self.rate = rate;
+            self.encoder.set_sample_rate(rate);
+        }
+        if let Some(filter) = &mut self.filter {
+            filter.set_config(settings);
+        }
+    }
+
+    /// Feeds a raw reading taken at `now`, returning how the encoder moves.
+    fn update(&mut self, reading: i32, now: Instant) -> TurntableMotion {
+        // The lights follow the platter itself, the report the filtered counts
+        let motion = self.velocity.update(reading, now);
+        let counts = match &mut self.filter {
+            Some(filter) => filter.update(reading, motion.milli_rpm, now),
+            None => reading,
+        };
+        self.scaler.update(counts);
+        motion
+    }
+}
+
+/// Sample rate encoder `index` needs to keep up with the fastest spin.
+fn encoder_rate(settings: &Settings, index: usize) -> u32 {
+    let counts_per_rotation = settings.encoder_scaling(index).counts_per_rotation();
+    sample_rate(counts_per_rotation, settings.tt_max_rps)
+}
+
+/// Reads `encoders` encoders, one per PIO0 state machine, and publishes
+/// their positions. The count comes from the USB identity, which the Konami
+/// profile limits to one. Only the first, the turntable, has digital buttons
+/// and drives the lights.
+#[embassy_executor::task]
+pub async fn encoder_task(
+    pio: Peri<'static, PIO0>,
+    pins: EncoderPins,
+    encoders: usize,
+    settings: &'static SettingsWatch,
+    output: &'static Signal<CriticalSectionRawMutex, Stamped<[TurntablePosition; MAX_ENCODERS]>>,
+    output_motion: &'static Signal<CriticalSectionRawMutex, TurntableMotion>,
+) {
+    let Pio {
+        mut common,
+        sm0,
+        sm1,
+        sm2,
+        sm3,
+        ..
+    } = Pio::new(pio, Irqs);
+
+    let mut settings = settings.receiver().unwrap();
+    let current = settings.get().await;
+    let count = encoders.clamp(1, MAX_ENCODERS);
+    info!("Reading {} encoders", count);
+
+    let prg = QuadratureEncoderProgram::new(&mut common);
+    let EncoderPins {
+        encoder_1,
+        encoder_2,
+        encoder_3,
+        encoder_4,
+    } = pins;
+    let mut channels = [
+        Some({
+            let encoder = QuadratureEncoder::new(
+                sm0,
+                common.make_pio_pin(encoder_1.0),
+                common.make_pio_pin(encoder_1.1),
+                &prg,
+                encoder_rate(&current, 0),
+            );
+            Channel::new(PioEncoder::Sm0(encoder), &current, 0)
+        }),
+        (count > 1).then(|| {
+            let encoder = QuadratureEncoder::new(
+                sm1,
+                common.make_pio_pin(encoder_2.0),
+                common.make_pio_pin(encoder_2.1),
+                &prg,
+                encoder_rate(&current, 1),
+            );
+            Channel::new(PioEncoder::Sm1(encoder), &current, 1)
+        }),
+        (count > 2).then(|| {
+            let encoder = QuadratureEncoder::new(
+                sm2,
+                common.make_pio_pin(encoder_3.0),
+                common.make_pio_pin(encoder_3.1),
+                &prg,
+                encoder_rate(&current, 2),
+            );
+            Channel::new(PioEncoder::Sm2(encoder), &current, 2)
+        }),
+        (count > 3).then(|| {
+            let encoder = QuadratureEncoder::new(
+                sm3,
+                common.make_pio_pin(encoder_4.0),
+                common.make_pio_pin(encoder_4.1),
+                &prg,
+                encoder_rate(&current, 3),
+            );
+            Channel::new(PioEncoder::Sm3(encoder), &current, 3)
+        }),
+    ];
+
+    let mut digital = DigitalTurntable::new(current.tt_hold, current.tt_threshold);
+    let mut readings = [0; MAX_ENCODERS];
+    let mut last_positions = None;
+    let mut changed_at = Instant::now();
+
+    loop {
+        // Only the turntable waits for its next sample. The others keep
+        // their last reading until a newer one is in their FIFO, so a pass
+        // takes one turntable sample period however many encoders there are
+        for (i, channel) in channels.iter_mut().flatten().enumerate() {
+            if i == 0 {
+                readings[i] = channel.encoder.read().await;
+            } else if let Some(latest) = channel.encoder.latest() {
+                readings[i] = latest;
+            }
+        }
+
+        if let Some(new_settings) = settings.try_changed() {
+            for (i, channel) in channels.iter_mut().flatten().enumerate() {
+                channel.set_config(&new_settings, i);
+            }
+            digital.set_config(new_settings.tt_hold, new_settings.tt_threshold);
+        }
+
+        let now = Instant::now();
+        let mut positions = [TurntablePosition::default(); MAX_ENCODERS];
+        let mut motion = TurntableMotion::default();
+        for (i, channel) in channels.iter_mut().flatten().enumerate() {
+            let channel_motion = channel.update(readings[i], now);
+            positions[i] = TurntablePosition {
+                raw_counts: readings[i],
+                ..channel.scaler.position()
+            };
+            if i == 0 {
+                motion = channel_motion;
+            }
+        }
+
+        // The encoder pushes a reading every sample, so the hold runs out
+        // even while the platter is still
+        positions[0].digital = digital.update(&motion, now);
+        TT_REVERSALS.store(motion.reversals, Ordering::Relaxed);
+        if last_positions != Some(positions) {
+            last_positions = Some(positions);
+            // Moves the report hasn't picked up yet keep the first one's time
+            if !output.signaled() {
+                changed_at = now;
+            }
+            output.signal(Stamped {
+                value: positions,
+                at: changed_at,
+            });
+        }
+        output_motion.signal(motion);
+    }
+}
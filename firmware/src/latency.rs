@@ -0,0 +1,9 @@
+use core::cell::RefCell;
+
+use bemani_firm_core::latency::LatencyStats;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+
+/// Latency histograms, recorded by the button and USB tasks and read by the
+/// host through the configuration interface.
+pub type SharedLatency = Mutex<CriticalSectionRawMutex, RefCell<LatencyStats>>;
@@ -0,0 +1,191 @@
+#![no_std]
+#![no_main]
+
+mod button;
+mod encoder;
+mod latency;
+mod rgb;
+mod settings;
+mod shell;
+mod usb;
+
+use bemani_firm_core::boot::BootSelection;
+use bemani_firm_core::encoder::MAX_ENCODERS;
+use bemani_firm_core::encoder::TurntablePosition;
+use bemani_firm_core::identity::UsbIdentity;
+use bemani_firm_core::latency::LatencyStats;
+use bemani_firm_core::latency::Stamped;
+use bemani_firm_core::lights::HostLights;
+use bemani_firm_core::settings::SerialNumber;
+use bemani_firm_core::velocity::TurntableMotion;
+use core::cell::RefCell;
+use defmt::*;
+use embassy_executor::Executor;
+use embassy_rp::multicore::{Stack, spawn_core1};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_sync::watch::Watch;
+use rgb::rgb_task;
+use static_cell::StaticCell;
+use {defmt_rtt as _, panic_probe as _};
+
+use crate::{
+    button::{ButtonGPIO, button_task, sample_held},
+    encoder::{EncoderPins, encoder_task},
+    latency::SharedLatency,
+    rgb::{RGBButtonPins, RgbInputs},
+    settings::{SettingsWatch, settings_task},
+    usb::{DEFAULT_SERIAL_NUMBER, UsbInputs, usb_task},
+};
+
+static mut CORE1_STACK: Stack<4096> = Stack::new();
+static EXECUTOR0: StaticCell<Executor> = StaticCell::new();
+static EXECUTOR1: StaticCell<Executor> = StaticCell::new();
+
+static SETTINGS: SettingsWatch = Watch::new();
+static SAVE_SETTINGS_SIGNAL: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+static SERIAL_NUMBER: StaticCell<SerialNumber> = StaticCell::new();
+static BUTTON_SIGNAL: Signal<CriticalSectionRawMutex, Stamped<u16>> = Signal::new();
+static BUTTON_LIGHTS_SIGNAL: Signal<CriticalSectionRawMutex, u16> = Signal::new();
+static ENCODER_SIGNAL: Signal<CriticalSectionRawMutex, Stamped<[TurntablePosition; MAX_ENCODERS]>> =
+    Signal::new();
+static ENCODER_MOTION_SIGNAL: Signal<CriticalSectionRawMutex, TurntableMotion> = Signal::new();
+static HOST_LIGHTS_SIGNAL: Signal<CriticalSectionRawMutex, HostLights> = Signal::new();
+static LATENCY: SharedLatency = Mutex::new(RefCell::new(LatencyStats::new()));
+
+#[cortex_m_rt::entry]
+fn main() -> ! {
+    let p = embassy_rp::init(Default::default());
+
+    let mut buttons = ButtonGPIO {
+        key_1: p.PIN_2.into(),
+        key_2: p.PIN_3.into(),
+        key_3: p.PIN_4.into(),
+        key_4: p.PIN_8.into(),
+        key_5: p.PIN_5.into(),
+        key_6: p.PIN_6.into(),
+        key_7: p.PIN_7.into(),
+
+        e_1: p.PIN_13.into(),
+        e_2: p.PIN_9.into(),
+        e_3: p.PIN_10.into(),
+        e_4: p.PIN_11.into(),
+    };
+
+    let mut settings_store = settings::new_store(p.FLASH);
+    let mut settings = settings_store.load();
+    info!("Loaded settings");
+
+    // Held keys pick the USB personality, before anything enumerates
+    let boot_selection = BootSelection::from_held(&sample_held(&mut buttons));
+    let mut input_mode = settings.input_mode;
+    if let Some(selection) = boot_selection {
+        info!("Boot selected {}", selection);
+        input_mode = selection.mode;
+        if selection.save && settings.input_mode != selection.mode {
+            settings.input_mode = selection.mode;
+            match settings_store.save(&settings) {
+                Ok(()) => info!("Input mode saved"),
+                Err(e) => warn!("Failed to save input mode: {:?}", e),
+            }
+        }
+    }
+
+    // Tells several controllers on one host apart, unless overridden
+    let serial_number = if settings.serial_number.is_empty() {
+        let mut id = [0; 8];
+        match settings_store.flash().blocking_unique_id(&mut id) {
+            Ok(()) => Some(SerialNumber::from_unique_id(&id)),
+            Err(e) => {
+                warn!("Failed to read the flash unique ID: {:?}", e);
+                None
+            }
+        }
+    } else {
+        Some(settings.serial_number)
+    };
+    let serial_number = match serial_number {
+        Some(serial) => SERIAL_NUMBER.init(serial).as_str(),
+        None => DEFAULT_SERIAL_NUMBER,
+    };
+    info!("USB serial number {}", serial_number);
+
+    // The report's axes are fixed until the next boot, and so is this
+    let encoders = UsbIdentity::new(input_mode, &settings).encoders;
+    SETTINGS.sender().send(settings);
+
+    let encoder_pins = EncoderPins {
+        encoder_1: (p.PIN_0, p.PIN_1),
+        encoder_2: (p.PIN_14, p.PIN_15),
+        encoder_3: (p.PIN_16, p.PIN_17),
+        encoder_4: (p.PIN_18, p.PIN_19),
+    };
+
+    let rgb_buttons = RGBButtonPins {
+        key_1: p.PIN_20,
+        key_2: p.PIN_21,
+        key_3: p.PIN_22,
+    };
+
+    spawn_core1(
+        p.CORE1,
+        unsafe { &mut *core::ptr::addr_of_mut!(CORE1_STACK) },
+        move || {
+            let executor1 = EXECUTOR1.init(Executor::new());
+            executor1.run(|spawner| {
+                unwrap!(spawner.spawn(rgb_task(
+                    p.PIO1,
+                    p.PIN_28,
+                    rgb_buttons,
+                    p.DMA_CH0,
+                    p.DMA_CH1,
+                    RgbInputs {
+                        settings: &SETTINGS,
+                        encoder: &ENCODER_MOTION_SIGNAL,
+                        host_lights: &HOST_LIGHTS_SIGNAL,
+                        buttons: &BUTTON_LIGHTS_SIGNAL,
+                        boot_selection,
+                    }
+                )));
+            });
+        },
+    );
+
+    let executor0 = EXECUTOR0.init(Executor::new());
+    executor0.run(|spawner| {
+        unwrap!(spawner.spawn(usb_task(
+            p.USB,
+            serial_number,
+            input_mode,
+            UsbInputs {
+                buttons: &BUTTON_SIGNAL,
+                encoder: &ENCODER_SIGNAL,
+                host_lights: &HOST_LIGHTS_SIGNAL,
+                latency: &LATENCY,
+            },
+            &SETTINGS,
+            &SAVE_SETTINGS_SIGNAL
+        )));
+        unwrap!(spawner.spawn(button_task(
+            buttons,
+            &SETTINGS,
+            &BUTTON_SIGNAL,
+            &BUTTON_LIGHTS_SIGNAL,
+            &LATENCY
+        )));
+        unwrap!(spawner.spawn(encoder_task(
+            p.PIO0,
+            encoder_pins,
+            encoders,
+            &SETTINGS,
+            &ENCODER_SIGNAL,
+            &ENCODER_MOTION_SIGNAL
+        )));
+        unwrap!(spawner.spawn(settings_task(
+            settings_store,
+            &SETTINGS,
+            &SAVE_SETTINGS_SIGNAL
+        )));
+    })
+}
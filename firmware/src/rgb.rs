@@ -1,5 +1,18 @@
 use core::array::from_fn;
 
+use bemani_firm_core::boot::BootSelection;
+use bemani_firm_core::effect::AnyEffect;
+use bemani_firm_core::effect::Effect;
+use bemani_firm_core::effect::InputState;
+use bemani_firm_core::effect::RAINBOW_CYCLE_TIME;
+use bemani_firm_core::lights::HostLights;
+use bemani_firm_core::lights::HostLightsState;
+use bemani_firm_core::lights::ReactiveLights;
+use bemani_firm_core::lights::render_button_leds;
+use bemani_firm_core::rgb::NUM_LED_BITS;
+use bemani_firm_core::rgb::pack_parallel_ws2812;
+use bemani_firm_core::settings::LedMode;
+use bemani_firm_core::velocity::TurntableMotion;
 use embassy_rp::Peri;
 use embassy_rp::bind_interrupts;
 use embassy_rp::clocks::clk_sys_freq;
@@ -30,29 +43,30 @@ use embassy_rp::pio_programs::ws2812::PioWs2812Program;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::signal::Signal;
 use embassy_time::Duration;
+use embassy_time::Instant;
 use embassy_time::Ticker;
 use embassy_time::Timer;
 use fixed::traits::ToFixed;
 use smart_leds::RGB8;
-use smart_leds::hsv::Hsv;
-use smart_leds::hsv::hsv2rgb;
 
-use crate::encoder::PPR;
+use crate::settings::SettingsWatch;
 
 bind_interrupts!(struct Irqs {
     PIO1_IRQ_0 => InterruptHandler<PIO1>;
 });
 
-const HUE_CYCLE_TIME_MS: u64 = 3500;
-const TICKER_TIME_MS: u64 = HUE_CYCLE_TIME_MS / 256;
+// Fast enough for every step of the rainbow's hue
+const TICKER_TIME_MS: u64 = RAINBOW_CYCLE_TIME.as_millis() / 256;
 const T1: u8 = 2; // start bit
 const T2: u8 = 5; // data bit
 const T3: u8 = 3; // stop bit
 const CYCLES_PER_BIT: u32 = (T1 + T2 + T3) as u32;
 
-const NUM_LED_BITS: usize = 24;
 const NUM_LEDS_PER_BUTTON: usize = 1;
 
+/// Button LEDs wired up, mapped to buttons by `Settings::button_leds`.
+const NUM_RGB_BUTTONS: usize = 3;
+
 pub struct ParallelWs2812Program<'a, PIO: Instance> {
     prg: LoadedProgram<'a, PIO>,
 }
@@ -123,33 +137,10 @@ impl<'d, T: Instance, const SM: usize, const NUM_STRIPS: usize>
         }
     }
 
-    pub async fn write(&mut self, colours: &[[RGB8; NUM_LEDS_PER_BUTTON]; NUM_STRIPS])
-    where
-        [(); NUM_LEDS_PER_BUTTON * NUM_LED_BITS]:,
-    {
-        const BITS_PER_COLOUR: usize = 8;
-
+    pub async fn write(&mut self, colours: &[[RGB8; NUM_LEDS_PER_BUTTON]; NUM_STRIPS]) {
         // Precompute the word bytes from the colors
         let mut words = [0u32; NUM_LEDS_PER_BUTTON * NUM_LED_BITS];
-        for strip in 0..NUM_STRIPS {
-            for led in 0..NUM_LEDS_PER_BUTTON {
-                for bit in 0..NUM_LED_BITS {
-                    let colour = if bit < BITS_PER_COLOUR {
-                        colours[strip][led].g
-                    } else if bit < (2 * BITS_PER_COLOUR) {
-                        colours[strip][led].r
-                    } else {
-                        colours[strip][led].b
-                    };
-
-                    let colour_bit_index = bit % BITS_PER_COLOUR;
-
-                    // We want MSB first
-                    let colour_bit = (colour >> (NUM_LED_BITS - colour_bit_index - 1)) & 0b1;
-                    words[(led * NUM_LED_BITS) + bit] |= (colour_bit as u32) << strip;
-                }
-            }
-        }
+        pack_parallel_ws2812(colours, &mut words);
 
         // DMA transfer
         self.sm
@@ -167,6 +158,17 @@ pub struct RGBButtonPins {
     pub key_3: Peri<'static, PIN_22>,
 }
 
+/// State from the other tasks that the lights follow.
+pub struct RgbInputs {
+    pub settings: &'static SettingsWatch,
+    pub encoder: &'static Signal<CriticalSectionRawMutex, TurntableMotion>,
+    pub host_lights: &'static Signal<CriticalSectionRawMutex, HostLights>,
+    /// Debounced button bitmask, for the reactive mode.
+    pub buttons: &'static Signal<CriticalSectionRawMutex, u16>,
+    /// Mode picked by the buttons held at power-on, shown for a moment.
+    pub boot_selection: Option<BootSelection>,
+}
+
 #[embassy_executor::task]
 pub async fn rgb_task(
     pio: Peri<'static, PIO1>,
@@ -174,7 +176,7 @@ pub async fn rgb_task(
     button_pins: RGBButtonPins,
     dma_strip: Peri<'static, DMA_CH0>,
     dma_buttons: Peri<'static, DMA_CH1>,
-    encoder_signal: &'static Signal<CriticalSectionRawMutex, i32>,
+    inputs: RgbInputs,
 ) {
     let Pio {
         mut common,
@@ -191,50 +193,83 @@ pub async fn rgb_task(
 
     const NUM_LEDS: usize = 49;
     let mut data = [RGB8::default(); NUM_LEDS];
-    let mut data_buttons = [[RGB8::default(); NUM_LEDS_PER_BUTTON]; 3];
+    let mut data_buttons = [[RGB8::default(); NUM_LEDS_PER_BUTTON]; NUM_RGB_BUTTONS];
 
     let prg = PioWs2812Program::new(&mut common);
     let mut rgb_strip = PioWs2812::new(&mut common, sm0, dma_strip, strip_pin, &prg);
     let prg_parallel = ParallelWs2812Program::new(&mut common);
     let mut rgb_buttons = ParallelWs2812::new(sm1, button_pins, dma_buttons, &prg_parallel);
 
+    let mut settings = inputs.settings.receiver().unwrap();
+    let mut current = settings.get().await;
+
+    let started = Instant::now();
     let mut ticker = Ticker::every(Duration::from_millis(TICKER_TIME_MS));
-    let hue = 0;
-    let mut encoder_val = 0;
+    let mut effect = AnyEffect::new(current.ring_effect, current.ring_colour);
+    let mut motion = TurntableMotion::default();
+    let mut buttons = 0;
+    let mut host_lights = HostLightsState::default();
+    let mut reactive = ReactiveLights::default();
     loop {
-        let mut hsv = Hsv {
-            hue,
-            sat: 255,
-            val: 255,
-        };
+        if let Some(new_settings) = settings.try_changed() {
+            if (new_settings.ring_effect, new_settings.ring_colour)
+                != (current.ring_effect, current.ring_colour)
+            {
+                effect = AnyEffect::new(new_settings.ring_effect, new_settings.ring_colour);
+            }
+            current = new_settings;
+        }
 
-        encoder_val = match encoder_signal.try_take() {
-            None => encoder_val,
-            Some(x) => x,
-        };
+        if let Some(new_motion) = inputs.encoder.try_take() {
+            motion = new_motion;
+        }
 
-        for i in 0..NUM_LEDS {
-            hsv.hue = 0;
-            hsv.val = if i == 0 { 255 } else { 0 };
-            data[i] = hsv2rgb(hsv);
+        let now = Instant::now();
+        if let Some(lights) = inputs.host_lights.try_take() {
+            host_lights.receive(lights, now);
+        }
+        if let Some(bits) = inputs.buttons.try_take() {
+            buttons = bits;
+            reactive.update(buttons, now);
         }
 
-        let rot_percent = (encoder_val % PPR * 100) / PPR;
+        // The game's lights take over while it keeps sending them
+        let host = host_lights.active(now);
 
-        if rot_percent < 0 {
-            data.rotate_left((rot_percent + 100) as usize * NUM_LEDS / 100);
+        if let Some(lights) = host {
+            data.fill(lights.turntable);
         } else {
-            data.rotate_right(rot_percent as usize * NUM_LEDS / 100);
+            let input = InputState {
+                tt_position: motion.counts,
+                counts_per_rotation: current.counts_per_rotation(),
+                tt_milli_rpm: motion.milli_rpm,
+                tt_reversals: motion.reversals,
+                buttons,
+            };
+            effect.render(&mut data, &input, now);
         }
 
-        // hue += 1;
-
-        rgb_strip.write(&data).await;
+        let mut button_colours = [RGB8::default(); NUM_RGB_BUTTONS];
+        match (host, current.led_mode) {
+            (Some(lights), _) => {
+                render_button_leds(&current, &mut button_colours, |b| lights.buttons[b])
+            }
+            (None, LedMode::Static) => {
+                render_button_leds(&current, &mut button_colours, |_| u8::MAX)
+            }
+            (None, LedMode::Reactive) => render_button_leds(&current, &mut button_colours, |b| {
+                reactive.level(b, current.reactive_fade, now)
+            }),
+        }
+        if let Some(selection) = &inputs.boot_selection {
+            selection.render(&current, &mut button_colours, now - started);
+        }
 
-        data_buttons[0][0] = RGB8::new(0xA2, 0x2B, 0x95);
-        data_buttons[1][0] = RGB8::new(0x12, 0x34, 0x56);
-        data_buttons[2][0] = RGB8::new(0x63, 0x6a, 0x2c);
+        for (leds, colour) in data_buttons.iter_mut().zip(button_colours) {
+            leds[0] = colour;
+        }
 
+        rgb_strip.write(&data).await;
         rgb_buttons.write(&data_buttons).await;
 
         ticker.next().await;
@@ -0,0 +1,55 @@
+use bemani_firm_core::settings::Settings;
+use bemani_firm_core::storage::SettingsStore;
+use defmt::info;
+use defmt::warn;
+use embassy_rp::Peri;
+use embassy_rp::flash::Blocking;
+use embassy_rp::flash::ERASE_SIZE;
+use embassy_rp::flash::Flash;
+use embassy_rp::peripherals::FLASH;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_sync::watch::Watch;
+
+pub const FLASH_SIZE: usize = 2 * 1024 * 1024;
+
+/// Sectors at the end of flash reserved for settings, must match `memory.x`.
+const SETTINGS_SECTORS: u32 = 4;
+const SETTINGS_OFFSET: u32 = FLASH_SIZE as u32 - SETTINGS_SECTORS * ERASE_SIZE as u32;
+
+/// Number of tasks that can follow settings changes.
+const MAX_RECEIVERS: usize = 4;
+
+/// Current settings, shared with every task that needs to react to changes.
+pub type SettingsWatch = Watch<CriticalSectionRawMutex, Settings, MAX_RECEIVERS>;
+
+pub type FlashSettingsStore = SettingsStore<Flash<'static, FLASH, Blocking, FLASH_SIZE>>;
+
+pub fn new_store(flash: Peri<'static, FLASH>) -> FlashSettingsStore {
+    SettingsStore::new(
+        Flash::new_blocking(flash),
+        SETTINGS_OFFSET,
+        SETTINGS_SECTORS,
+    )
+}
+
+/// Writes the current settings to flash whenever a save is requested.
+#[embassy_executor::task]
+pub async fn settings_task(
+    mut store: FlashSettingsStore,
+    settings: &'static SettingsWatch,
+    save: &'static Signal<CriticalSectionRawMutex, ()>,
+) {
+    loop {
+        save.wait().await;
+
+        let Some(current) = settings.try_get() else {
+            continue;
+        };
+
+        match store.save(&current) {
+            Ok(()) => info!("Settings saved"),
+            Err(e) => warn!("Failed to save settings: {:?}", e),
+        }
+    }
+}
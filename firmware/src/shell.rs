@@ -0,0 +1,201 @@
+use core::cell::Cell;
+use core::fmt;
+use core::sync::atomic::Ordering;
+
+use bemani_firm_core::shell::Action;
+use bemani_firm_core::shell::LineBuffer;
+use bemani_firm_core::shell::LineError;
+use bemani_firm_core::shell::Status;
+use bemani_firm_core::shell::UsbState;
+use bemani_firm_core::shell::execute;
+use defmt::info;
+use defmt::warn;
+use embassy_rp::peripherals::USB;
+use embassy_rp::rom_data::reset_to_usb_boot;
+use embassy_rp::usb::Driver;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::Timer;
+use embassy_usb::class::cdc_acm::CdcAcmClass;
+use embassy_usb::driver::EndpointError;
+
+use crate::encoder::TT_REVERSALS;
+use crate::settings::SettingsWatch;
+use crate::usb::USB_STATE;
+
+/// Room for the longest reply, `get` listing every setting.
+const MAX_REPLY_LEN: usize = 2048;
+
+const PROMPT: &[u8] = b"> ";
+
+type SerialClass<'d> = CdcAcmClass<'d, Driver<'d, USB>>;
+
+/// Latest button and encoder readings, kept up to date by the report loop.
+#[derive(Default)]
+pub struct ShellInputs {
+    pub buttons: Cell<u16>,
+    pub encoder: Cell<i32>,
+}
+
+/// Reply text collected before it is sent, cut short if it overflows.
+struct Reply {
+    buf: [u8; MAX_REPLY_LEN],
+    len: usize,
+}
+
+impl Reply {
+    fn new() -> Self {
+        Self {
+            buf: [0; MAX_REPLY_LEN],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        let n = bytes.len().min(MAX_REPLY_LEN - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&bytes[..n]);
+        self.len += n;
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl fmt::Write for Reply {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Serves the configuration shell on the USB serial port, one terminal
+/// session at a time.
+pub async fn run_shell(
+    class: &mut SerialClass<'_>,
+    inputs: &ShellInputs,
+    settings: &SettingsWatch,
+    save_settings: &Signal<CriticalSectionRawMutex, ()>,
+) {
+    loop {
+        class.wait_connection().await;
+        info!("Shell connected");
+        match session(class, inputs, settings, save_settings).await {
+            Ok(action) => apply(action).await,
+            Err(EndpointError::Disabled) => info!("Shell disconnected"),
+            Err(e) => warn!("Shell failed: {:?}", e),
+        }
+    }
+}
+
+/// Runs commands until one needs the controller to restart.
+async fn session(
+    class: &mut SerialClass<'_>,
+    inputs: &ShellInputs,
+    settings: &SettingsWatch,
+    save_settings: &Signal<CriticalSectionRawMutex, ()>,
+) -> Result<Action, EndpointError> {
+    let mut lines = LineBuffer::new();
+    let mut packet = [0; 64];
+
+    let mut reply = Reply::new();
+    reply.push(PROMPT);
+    send(class, &reply).await?;
+
+    loop {
+        let n = class.read_packet(&mut packet).await?;
+
+        let mut reply = Reply::new();
+        let mut restart = None;
+        for &byte in &packet[..n] {
+            let line = lines.push(byte);
+
+            // Echo what was typed, as terminals expect
+            match byte {
+                b'\r' | b'\n' if line.is_some() || byte == b'\r' => reply.push(b"\r\n"),
+                b'\r' | b'\n' => {}
+                0x08 | 0x7F => reply.push(b"\x08 \x08"),
+                _ => reply.push(&[byte]),
+            }
+
+            match line {
+                None => continue,
+                Some(Ok(line)) => {
+                    if let Some(action) = run_command(line, inputs, settings, &mut reply) {
+                        match action {
+                            Action::SettingsChanged => {}
+                            Action::Save => save_settings.signal(()),
+                            Action::Reboot | Action::Bootloader => restart = Some(action),
+                        }
+                    }
+                }
+                Some(Err(LineError::TooLong)) => reply.push(b"error: line too long\r\n"),
+                Some(Err(LineError::NotUtf8)) => reply.push(b"error: not text\r\n"),
+            }
+            if restart.is_some() {
+                break;
+            }
+            reply.push(PROMPT);
+        }
+
+        send(class, &reply).await?;
+        if let Some(action) = restart {
+            return Ok(action);
+        }
+    }
+}
+
+/// Runs one command against the current settings, publishing any change.
+fn run_command(
+    line: &str,
+    inputs: &ShellInputs,
+    settings: &SettingsWatch,
+    reply: &mut Reply,
+) -> Option<Action> {
+    let current = settings.try_get()?;
+    let status = Status {
+        buttons: inputs.buttons.get(),
+        encoder: inputs.encoder.get(),
+        reversals: TT_REVERSALS.load(Ordering::Relaxed),
+        usb: UsbState::try_from(USB_STATE.load(Ordering::Relaxed)).unwrap_or_default(),
+    };
+
+    let mut updated = current.clone();
+    // The reply truncates rather than failing
+    let action = execute(line, &mut updated, &status, reply).ok()?;
+    if action == Some(Action::SettingsChanged) {
+        settings.sender().send(updated);
+    }
+    action
+}
+
+/// Sends a reply in packets, ending with a short one so the host hands it
+/// over straight away.
+async fn send(class: &mut SerialClass<'_>, reply: &Reply) -> Result<(), EndpointError> {
+    let max_packet_size = class.max_packet_size() as usize;
+    let bytes = reply.as_bytes();
+    for chunk in bytes.chunks(max_packet_size) {
+        class.write_packet(chunk).await?;
+    }
+    if bytes.len() % max_packet_size == 0 {
+        class.write_packet(&[]).await?;
+    }
+    Ok(())
+}
+
+async fn apply(action: Action) {
+    // Give the host a moment to collect the reply
+    Timer::after_millis(100).await;
+
+    match action {
+        Action::Reboot => {
+            info!("Rebooting");
+            cortex_m::peripheral::SCB::sys_reset();
+        }
+        Action::Bootloader => {
+            info!("Entering the bootloader");
+            reset_to_usb_boot(0, 0);
+        }
+        Action::SettingsChanged | Action::Save => {}
+    }
+}
@@ -0,0 +1,478 @@
+use bemani_firm_core::encoder::MAX_ENCODERS;
+use bemani_firm_core::encoder::TurntablePosition;
+use bemani_firm_core::identity::UsbIdentity;
+use bemani_firm_core::idle::KEYBOARD_IDLE_MS;
+use bemani_firm_core::idle::ReportScheduler;
+use bemani_firm_core::latency::InputKind;
+use bemani_firm_core::latency::PendingChanges;
+use bemani_firm_core::latency::Stamped;
+use bemani_firm_core::lights::HostLights;
+use bemani_firm_core::lights::NUM_LIGHTS;
+use bemani_firm_core::protocol;
+use bemani_firm_core::protocol::CONFIG_REPORT_DESCRIPTOR;
+use bemani_firm_core::protocol::Command;
+use bemani_firm_core::protocol::FeatureReport;
+use bemani_firm_core::protocol::REPORT_LEN;
+use bemani_firm_core::report::InputReport;
+use bemani_firm_core::settings::InputMode;
+use bemani_firm_core::shell::UsbState;
+use core::cell::Cell;
+use core::future::pending;
+use core::sync::atomic::AtomicU8;
+use core::sync::atomic::AtomicU32;
+use core::sync::atomic::Ordering;
+use defmt::debug;
+use defmt::info;
+use defmt::warn;
+use embassy_futures::join::join;
+use embassy_futures::select::Either4;
+use embassy_futures::select::select4;
+use embassy_rp::Peri;
+use embassy_rp::bind_interrupts;
+use embassy_rp::peripherals::USB;
+use embassy_rp::usb::Driver;
+use embassy_rp::usb::InterruptHandler;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::Instant;
+use embassy_time::Timer;
+use embassy_usb::Builder;
+use embassy_usb::Config;
+use embassy_usb::Handler;
+use embassy_usb::class::cdc_acm;
+use embassy_usb::class::cdc_acm::CdcAcmClass;
+use embassy_usb::class::hid::HidReaderWriter;
+use embassy_usb::class::hid::HidWriter;
+use embassy_usb::class::hid::ReportId;
+use embassy_usb::class::hid::RequestHandler;
+use embassy_usb::class::hid::State;
+use embassy_usb::control::OutResponse;
+
+use crate::latency::SharedLatency;
+use crate::settings::SettingsWatch;
+use crate::shell::ShellInputs;
+use crate::shell::run_shell;
+
+bind_interrupts!(struct Irqs {
+    USBCTRL_IRQ => InterruptHandler<USB>;
+});
+
+/// Serial number used when the flash unique ID can't be read.
+pub const DEFAULT_SERIAL_NUMBER: &str = "12345678";
+
+/// Current `UsbState`, for the shell's `status` command.
+pub static USB_STATE: AtomicU8 = AtomicU8::new(UsbState::Disabled as u8);
+
+/// Input report idle rate set by the host, 0 to only send changes.
+static IDLE_MS: AtomicU32 = AtomicU32::new(0);
+
+/// Wakes the report loop when the idle rate changes.
+static IDLE_CHANGED: Signal<CriticalSectionRawMutex, u32> = Signal::new();
+
+/// Latest input state, for answering GET_REPORT. Set to the idle report
+/// before the device enumerates.
+static INPUT_REPORT: Mutex<CriticalSectionRawMutex, Cell<Option<InputReport>>> =
+    Mutex::new(Cell::new(None));
+
+/// Longest input report of any personality.
+const MAX_INPUT_REPORT_LEN: usize = 32;
+
+/// State from the other tasks that goes into the reports.
+pub struct UsbInputs {
+    pub buttons: &'static Signal<CriticalSectionRawMutex, Stamped<u16>>,
+    pub encoder:
+        &'static Signal<CriticalSectionRawMutex, Stamped<[TurntablePosition; MAX_ENCODERS]>>,
+    pub host_lights: &'static Signal<CriticalSectionRawMutex, HostLights>,
+    pub latency: &'static SharedLatency,
+}
+
+/// Runs the USB device as `input_mode`, which can differ from the settings
+/// when picked at boot.
+#[embassy_executor::task]
+pub async fn usb_task(
+    usb: Peri<'static, USB>,
+    serial_number: &'static str,
+    input_mode: InputMode,
+    inputs: UsbInputs,
+    settings: &'static SettingsWatch,
+    save_settings: &'static Signal<CriticalSectionRawMutex, ()>,
+) {
+    debug!("in usb task");
+    let driver = Driver::new(usb, Irqs);
+    let UsbInputs {
+        buttons,
+        encoder,
+        host_lights,
+        latency,
+    } = inputs;
+
+    // The report format is fixed once enumerated, so mode, identity and axis
+    // changes wait for the next boot
+    let mut settings_receiver = settings.receiver().unwrap();
+    let current = settings_receiver.get().await;
+    let identity = UsbIdentity::new(input_mode, &current);
+    let tt_axis = identity.tt_axis;
+    let input_report = InputReport::new(input_mode, tt_axis, identity.encoders, &current);
+    // GET_REPORT can come straight after enumeration, before any input
+    // changes, and has to be answered rather than stalled
+    INPUT_REPORT.lock(|current| current.set(Some(input_report)));
+    info!(
+        "Input mode {}, USB profile {}, turntable axis {} for {} encoders",
+        input_mode, current.usb_profile, tt_axis, input_report.encoders
+    );
+
+    let idle_ms = match input_mode {
+        InputMode::Keyboard => KEYBOARD_IDLE_MS,
+        InputMode::Joystick | InputMode::Switch => 0,
+    };
+    IDLE_MS.store(idle_ms, Ordering::Relaxed);
+
+    let mut config = Config::new(identity.vid, identity.pid);
+    config.manufacturer = identity.manufacturer;
+    config.product = identity.product;
+    config.serial_number = Some(serial_number);
+
+    let mut config_descriptor = [0; 256];
+    let mut bos_descriptor = [0; 256];
+    let mut msos_descriptor = [0; 256];
+    let mut control_buf = [0; 256];
+    // Output reports arrive on the interrupt endpoint or as SET_REPORT
+    // requests, depending on the host, so both paths get a handler.
+    let mut request_handler = MyRequestHandler { host_lights };
+    let mut control_request_handler = MyRequestHandler { host_lights };
+    let mut config_request_handler = ConfigRequestHandler {
+        settings,
+        save_settings,
+        latency,
+    };
+    let mut device_handler = MyDeviceHandler::new();
+
+    let mut state = State::new();
+    let mut config_state = State::new();
+    let mut cdc_state = cdc_acm::State::new();
+
+    let mut builder = Builder::new(
+        driver,
+        config,
+        &mut config_descriptor,
+        &mut bos_descriptor,
+        &mut msos_descriptor,
+        &mut control_buf,
+    );
+
+    builder.handler(&mut device_handler);
+
+    let config = embassy_usb::class::hid::Config {
+        report_descriptor: input_report.descriptor(),
+        request_handler: Some(&mut control_request_handler),
+        poll_ms: 1,
+        max_packet_size: 64,
+    };
+
+    let hid = HidReaderWriter::<_, NUM_LIGHTS, MAX_INPUT_REPORT_LEN>::new(
+        &mut builder,
+        &mut state,
+        config,
+    );
+
+    // Settings are read and written through feature reports on a separate
+    // interface, so the joystick's report format is left alone. Like the
+    // serial port below, the Switch doesn't get it, as it only expects the
+    // pad's interfaces.
+    let config = embassy_usb::class::hid::Config {
+        report_descriptor: CONFIG_REPORT_DESCRIPTOR,
+        request_handler: Some(&mut config_request_handler),
+        poll_ms: 10,
+        max_packet_size: REPORT_LEN as u16,
+    };
+
+    let _config_hid = match input_mode {
+        InputMode::Joystick | InputMode::Keyboard => Some(HidWriter::<_, REPORT_LEN>::new(
+            &mut builder,
+            &mut config_state,
+            config,
+        )),
+        InputMode::Switch => None,
+    };
+
+    // A serial port for configuring from a terminal
+    let mut cdc = match input_mode {
+        InputMode::Joystick | InputMode::Keyboard => {
+            Some(CdcAcmClass::new(&mut builder, &mut cdc_state, 64))
+        }
+        InputMode::Switch => None,
+    };
+
+    // Build the builder.
+    let mut usb = builder.build();
+
+    debug!("built usb descriptor");
+
+    // Run the USB device.
+    let usb_fut = usb.run();
+
+    debug!("running usb device");
+
+    let (reader, mut writer) = hid.split();
+    let shell_inputs = ShellInputs::default();
+
+    let in_fut = async {
+        let mut input = input_report;
+        let mut report = [0; MAX_INPUT_REPORT_LEN];
+        let mut scheduler =
+            ReportScheduler::<MAX_INPUT_REPORT_LEN>::new(IDLE_MS.load(Ordering::Relaxed));
+        let mut changes = PendingChanges::default();
+
+        loop {
+            // Any input change, or the idle rate running out, builds a report
+            let idle_timeout = async {
+                match scheduler.deadline() {
+                    Some(deadline) => Timer::at(deadline).await,
+                    None => pending().await,
+                }
+            };
+            match select4(
+                buttons.wait(),
+                encoder.wait(),
+                IDLE_CHANGED.wait(),
+                idle_timeout,
+            )
+            .await
+            {
+                Either4::First(change) => take_buttons(&mut input, &mut changes, change),
+                Either4::Second(change) => take_turntable(&mut input, &mut changes, change),
+                Either4::Third(idle_ms) => scheduler.set_idle_ms(idle_ms),
+                Either4::Fourth(()) => {}
+            }
+            // Fold in whatever else changed meanwhile
+            if let Some(change) = buttons.try_take() {
+                take_buttons(&mut input, &mut changes, change);
+            }
+            if let Some(change) = encoder.try_take() {
+                take_turntable(&mut input, &mut changes, change);
+            }
+            if let Some(new_settings) = settings_receiver.try_changed() {
+                input.apply_settings(&new_settings);
+            }
+
+            shell_inputs.buttons.set(input.buttons);
+            shell_inputs.encoder.set(input.tt[0].raw_counts);
+            INPUT_REPORT.lock(|current| current.set(Some(input)));
+
+            let len = input.serialize(&mut report);
+            let Some(len) = len else {
+                warn!("Report does not fit its buffer");
+                continue;
+            };
+            if !scheduler.should_send(&report[..len], Instant::now()) {
+                // Whatever changed has changed back
+                changes.clear();
+                continue;
+            }
+
+            // Waits for the host's next poll, while newer changes pile up in
+            // the signals for the following report
+            match writer.write(&report[..len]).await {
+                Ok(()) => {
+                    latency.lock(|stats| changes.sent(Instant::now(), &mut stats.borrow_mut()))
+                }
+                Err(e) => {
+                    warn!("Failed to send report: {:?}", e);
+                    changes.clear();
+                }
+            };
+        }
+    };
+
+    let out_fut = async {
+        reader.run(false, &mut request_handler).await;
+    };
+
+    let shell_fut = async {
+        match &mut cdc {
+            Some(class) => run_shell(class, &shell_inputs, settings, save_settings).await,
+            None => pending().await,
+        }
+    };
+
+    join(usb_fut, join(in_fut, join(out_fut, shell_fut))).await;
+}
+
+fn take_buttons(input: &mut InputReport, changes: &mut PendingChanges, change: Stamped<u16>) {
+    changes.mark_buttons(input.buttons ^ change.value, change.at);
+    input.buttons = change.value;
+}
+
+fn take_turntable(
+    input: &mut InputReport,
+    changes: &mut PendingChanges,
+    change: Stamped<[TurntablePosition; MAX_ENCODERS]>,
+) {
+    if input.tt != change.value {
+        changes.mark(InputKind::Turntable, change.at);
+    }
+    input.tt = change.value;
+}
+
+struct MyRequestHandler {
+    host_lights: &'static Signal<CriticalSectionRawMutex, HostLights>,
+}
+
+impl RequestHandler for MyRequestHandler {
+    fn get_report(&mut self, id: ReportId, buf: &mut [u8]) -> Option<usize> {
+        match id {
+            // The input report has no ID, and this interface has no feature
+            // reports
+            ReportId::In(0) => INPUT_REPORT.lock(|current| current.get())?.serialize(buf),
+            _ => {
+                info!("Get report for {:?}", id);
+                None
+            }
+        }
+    }
+
+    fn set_report(&mut self, id: ReportId, data: &[u8]) -> OutResponse {
+        match (id, HostLights::from_report(data)) {
+            (ReportId::Out(_), Some(lights)) => {
+                self.host_lights.signal(lights);
+                OutResponse::Accepted
+            }
+            _ => {
+                info!("Set report for {:?}: {=[u8]}", id, data);
+                OutResponse::Rejected
+            }
+        }
+    }
+
+    // The input report has no ID, so one rate covers every ID
+    fn set_idle_ms(&mut self, id: Option<ReportId>, dur: u32) {
+        info!("Set idle rate for {:?} to {:?}", id, dur);
+        IDLE_MS.store(dur, Ordering::Relaxed);
+        IDLE_CHANGED.signal(dur);
+    }
+
+    fn get_idle_ms(&mut self, id: Option<ReportId>) -> Option<u32> {
+        info!("Get idle rate for {:?}", id);
+        Some(IDLE_MS.load(Ordering::Relaxed))
+    }
+}
+
+/// Answers the configuration feature reports from the shared settings.
+struct ConfigRequestHandler {
+    settings: &'static SettingsWatch,
+    save_settings: &'static Signal<CriticalSectionRawMutex, ()>,
+    latency: &'static SharedLatency,
+}
+
+impl RequestHandler for ConfigRequestHandler {
+    fn get_report(&mut self, id: ReportId, buf: &mut [u8]) -> Option<usize> {
+        let ReportId::Feature(id) = id else {
+            return None;
+        };
+
+        if let Some(&kind) = InputKind::ALL
+            .iter()
+            .find(|&&kind| FeatureReport::latency(kind) as u8 == id)
+        {
+            return self
+                .latency
+                .lock(|stats| protocol::latency_report(kind, &stats.borrow(), buf));
+        }
+
+        let settings = self.settings.try_get()?;
+        protocol::get_feature(id, &settings, buf)
+    }
+
+    fn set_report(&mut self, id: ReportId, data: &[u8]) -> OutResponse {
+        let ReportId::Feature(id) = id else {
+            return OutResponse::Rejected;
+        };
+        let Some(current) = self.settings.try_get() else {
+            return OutResponse::Rejected;
+        };
+
+        let mut updated = current.clone();
+        match protocol::set_feature(id, data, &mut updated) {
+            Ok(command) => {
+                if updated != current {
+                    self.settings.sender().send(updated);
+                }
+                match command {
+                    Some(Command::Save) => self.save_settings.signal(()),
+                    Some(Command::ResetLatency) => {
+                        self.latency.lock(|stats| stats.borrow_mut().reset())
+                    }
+                    _ => {}
+                }
+                OutResponse::Accepted
+            }
+            Err(e) => {
+                warn!("Rejected feature report {}: {:?}", id, e);
+                OutResponse::Rejected
+            }
+        }
+    }
+}
+
+/// Publishes the device state in `USB_STATE`.
+struct MyDeviceHandler {
+    /// State to return to when the bus resumes.
+    state: UsbState,
+}
+
+impl MyDeviceHandler {
+    fn new() -> Self {
+        MyDeviceHandler {
+            state: UsbState::Disabled,
+        }
+    }
+
+    fn set_state(&mut self, state: UsbState) {
+        self.state = state;
+        USB_STATE.store(state as u8, Ordering::Relaxed);
+    }
+}
+
+impl Handler for MyDeviceHandler {
+    fn enabled(&mut self, enabled: bool) {
+        if enabled {
+            self.set_state(UsbState::Default);
+            info!("Device enabled");
+        } else {
+            self.set_state(UsbState::Disabled);
+            info!("Device disabled");
+        }
+    }
+
+    fn reset(&mut self) {
+        self.set_state(UsbState::Default);
+        info!("Bus reset, the Vbus current limit is 100mA");
+    }
+
+    fn addressed(&mut self, addr: u8) {
+        self.set_state(UsbState::Addressed);
+        info!("USB address set to: {}", addr);
+    }
+
+    fn configured(&mut self, configured: bool) {
+        if configured {
+            self.set_state(UsbState::Configured);
+            info!(
+                "Device configured, it may now draw up to the configured current limit from Vbus."
+            )
+        } else {
+            self.set_state(UsbState::Addressed);
+            info!("Device is no longer configured, the Vbus current limit is 100mA.");
+        }
+    }
+
+    fn suspended(&mut self, suspended: bool) {
+        let state = if suspended {
+            UsbState::Suspended
+        } else {
+            self.state
+        };
+        USB_STATE.store(state as u8, Ordering::Relaxed);
+    }
+}